@@ -2,8 +2,6 @@
 //! contents to live longer than the process on Linux.
 
 use arboard::Clipboard;
-#[cfg(target_os = "linux")]
-use arboard::SetExtLinux;
 use std::{env, error::Error, process};
 
 // An argument that can be passed into the program to signal that it should daemonize itself. This
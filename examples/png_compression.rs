@@ -0,0 +1,37 @@
+//! Compares how long setting a 4K image takes with the default and the fast PNG compression.
+//!
+//! Images are only encoded as PNG on Linux, so this does nothing on other platforms.
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+fn main() {
+	use arboard::{Clipboard, ImageData, PngCompression, SetExtLinux};
+	use std::time::Instant;
+
+	const WIDTH: usize = 3840;
+	const HEIGHT: usize = 2160;
+
+	// A gradient with some noise, to be closer to a real screenshot than a solid color.
+	let mut bytes = Vec::with_capacity(WIDTH * HEIGHT * 4);
+	for y in 0..HEIGHT {
+		for x in 0..WIDTH {
+			let noise = (x * 7 + y * 13) % 17;
+			bytes.extend_from_slice(&[(x % 256) as u8, (y % 256) as u8, noise as u8, 255]);
+		}
+	}
+	let image = ImageData::new(WIDTH, HEIGHT, bytes).unwrap();
+
+	let mut ctx = Clipboard::new().unwrap();
+	for compression in [PngCompression::Default, PngCompression::Fast] {
+		let start = Instant::now();
+		ctx.set().png_compression(compression).image(image.clone()).unwrap();
+		println!("{compression:?}: {:?}", start.elapsed());
+	}
+}
+
+#[cfg(not(all(
+	unix,
+	not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+)))]
+fn main() {
+	println!("Images are only encoded as PNG on Linux.");
+}
@@ -10,6 +10,7 @@ and conditions of the chosen license apply to this file.
 
 #[cfg(feature = "image-data")]
 use std::borrow::Cow;
+use std::time::Duration;
 
 /// An error that might happen during a clipboard operation.
 ///
@@ -19,10 +20,15 @@ use std::borrow::Cow;
 #[non_exhaustive]
 pub enum Error {
 	/// The clipboard contents were not available in the requested format.
-	/// This could either be due to the clipboard being empty or the clipboard contents having
-	/// an incompatible format to the requested one (eg when calling `get_image` on text)
+	///
+	/// This is returned when the clipboard is empty, or when the platform can't tell an empty
+	/// clipboard apart from one holding data in an incompatible format.
 	ContentNotAvailable,
 
+	/// The clipboard holds data, but not in the requested format (eg when calling `get_image`
+	/// while the clipboard contains text).
+	WrongFormat,
+
 	/// The selected clipboard is not supported by the current configuration (system and/or environment).
 	///
 	/// This can be caused by a few conditions:
@@ -45,26 +51,99 @@ pub enum Error {
 	/// converted to the appropriate format.
 	ConversionFailure,
 
+	/// The image that was about to be placed onto the clipboard has a different number of bytes
+	/// than its dimensions call for, which is `width * height * 4`.
+	InvalidImageDimensions {
+		/// The number of bytes required by the width and height of the image.
+		expected: usize,
+		/// The length of the image's `bytes`.
+		actual: usize,
+	},
+
+	/// The clipboard's contents are larger than the limit that was set with
+	/// [`Get::max_bytes`](crate::Get::max_bytes).
+	TooLarge,
+
 	/// Any error that doesn't fit the other error types.
 	///
 	/// The `description` field is only meant to help the developer and should not be relied on as a
 	/// means to identify an error case during runtime.
 	Unknown { description: String },
+
+	/// An error reported by the operating system or the library underlying the platform's
+	/// clipboard, which doesn't fit the other error types.
+	///
+	/// Like for [`Error::Unknown`], the `description` is only meant to help the developer. To
+	/// branch on specific failures, use [`Error::os_code`] or inspect the original error through
+	/// [`source`](std::error::Error::source).
+	#[non_exhaustive]
+	Platform {
+		description: String,
+		/// The error reported by the operating system or the underlying library.
+		source: Box<dyn std::error::Error + Send + Sync + 'static>,
+	},
+}
+
+/// The contents of the clipboard in the first of the representations which it's available in, as
+/// returned by [`Get::any`](crate::Get::any).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ClipboardContent {
+	/// The clipboard holds text.
+	Text(String),
+	/// The clipboard holds an image, but no text.
+	#[cfg(feature = "image-data")]
+	Image(ImageData<'static>),
+}
+
+/// A stable, fieldless classification of an [`Error`], as returned by [`Error::kind`].
+///
+/// Unlike the `Display` output, these don't change between versions, so they can be mapped to
+/// localized messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+	/// See [`Error::ContentNotAvailable`].
+	ContentNotAvailable,
+	/// See [`Error::WrongFormat`].
+	WrongFormat,
+	/// See [`Error::ClipboardNotSupported`].
+	NotSupported,
+	/// See [`Error::ClipboardOccupied`].
+	Occupied,
+	/// See [`Error::ConversionFailure`].
+	Conversion,
+	/// See [`Error::InvalidImageDimensions`].
+	InvalidImageDimensions,
+	/// See [`Error::TooLarge`].
+	TooLarge,
+	/// See [`Error::Unknown`] and [`Error::Platform`].
+	Unknown,
 }
 
 impl std::fmt::Display for Error {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
 			Error::ContentNotAvailable => f.write_str("The clipboard contents were not available in the requested format or the clipboard is empty."),
+			Error::WrongFormat => f.write_str("The clipboard is not empty, but its contents are not available in the requested format."),
 			Error::ClipboardNotSupported => f.write_str("The selected clipboard is not supported with the current system configuration."),
 			Error::ClipboardOccupied => f.write_str("The native clipboard is not accessible due to being held by an other party."),
 			Error::ConversionFailure => f.write_str("The image or the text that was about the be transferred to/from the clipboard could not be converted to the appropriate format."),
-			Error::Unknown { description } => f.write_fmt(format_args!("Unknown error while interacting with the clipboard: {description}")),
+			Error::InvalidImageDimensions { expected, actual } => f.write_fmt(format_args!("The image has {actual} bytes of pixel data, but its dimensions require {expected} bytes.")),
+			Error::TooLarge => f.write_str("The clipboard contents are larger than the allowed maximum size."),
+			Error::Unknown { description } | Error::Platform { description, .. } => f.write_fmt(format_args!("Unknown error while interacting with the clipboard: {description}")),
 		}
 	}
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Error::Platform { source, .. } => Some(&**source),
+			_ => None,
+		}
+	}
+}
 
 impl std::fmt::Debug for Error {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -80,20 +159,60 @@ impl std::fmt::Debug for Error {
 		}
 		let name = kind_to_str!(
 			ContentNotAvailable,
+			WrongFormat,
 			ClipboardNotSupported,
 			ClipboardOccupied,
 			ConversionFailure,
-			Unknown { .. }
+			InvalidImageDimensions { .. },
+			TooLarge,
+			Unknown { .. },
+			Platform { .. }
 		);
 		f.write_fmt(format_args!("{name} - \"{self}\""))
 	}
 }
 
 impl Error {
-	#[cfg(windows)]
+	/// Returns the kind of this error, to tell errors apart without matching on their messages.
+	pub fn kind(&self) -> ErrorKind {
+		match self {
+			Error::ContentNotAvailable => ErrorKind::ContentNotAvailable,
+			Error::WrongFormat => ErrorKind::WrongFormat,
+			Error::ClipboardNotSupported => ErrorKind::NotSupported,
+			Error::ClipboardOccupied => ErrorKind::Occupied,
+			Error::ConversionFailure => ErrorKind::Conversion,
+			Error::InvalidImageDimensions { .. } => ErrorKind::InvalidImageDimensions,
+			Error::TooLarge => ErrorKind::TooLarge,
+			Error::Unknown { .. } | Error::Platform { .. } => ErrorKind::Unknown,
+		}
+	}
+
+	/// Returns the operating system's error code, if this error was caused by a failed system
+	/// call. On Windows, these are the codes returned by `GetLastError`, like
+	/// `ERROR_CLIPBOARD_NOT_OPEN`.
+	pub fn os_code(&self) -> Option<i32> {
+		match self {
+			Error::Platform { source, .. } => {
+				source.downcast_ref::<std::io::Error>().and_then(std::io::Error::raw_os_error)
+			}
+			_ => None,
+		}
+	}
+
 	pub(crate) fn unknown<M: Into<String>>(message: M) -> Self {
 		Error::Unknown { description: message.into() }
 	}
+
+	/// Creates an [`Error::Platform`] which keeps `source` around, with its message appended to
+	/// the description.
+	#[cfg(windows)]
+	pub(crate) fn unknown_with_source<M, E>(message: M, source: E) -> Self
+	where
+		M: std::fmt::Display,
+		E: std::error::Error + Send + Sync + 'static,
+	{
+		Error::Platform { description: format!("{message}: {source}"), source: Box::new(source) }
+	}
 }
 
 /// Stores pixel data of an image.
@@ -102,10 +221,18 @@ impl Error {
 /// This struct stores four channels (red, green, blue, alpha) so
 /// a `3*3` image is going to be stored on `3*3*4 = 36` bytes of data.
 ///
+/// The channels are always in this RGBA order, on every platform. Images are converted from and
+/// to the order of the platform's formats, like the BGRA of Windows bitmaps, when they are read
+/// from and placed onto the clipboard.
+///
 /// The pixels are in row-major order meaning that the second pixel
 /// in `bytes` (starting at the fifth byte) corresponds to the pixel that's
 /// sitting to the right side of the top-left pixel (x=1, y=0)
 ///
+/// The rows are tightly packed, without any padding between them, and images read from the
+/// clipboard are always returned this way. Buffers with padded rows can be converted with
+/// [`ImageData::from_strided`].
+///
 /// Assigning a `2*1` image would for example look like this
 /// ```
 /// use arboard::ImageData;
@@ -123,8 +250,11 @@ impl Error {
 ///     bytes: Cow::from(bytes.as_ref())
 /// };
 /// ```
+///
+/// [`ImageData::new`] checks the length of `bytes` right away, rather than the image being
+/// rejected once it's placed onto the clipboard.
 #[cfg(feature = "image-data")]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ImageData<'a> {
 	pub width: usize,
 	pub height: usize,
@@ -132,7 +262,124 @@ pub struct ImageData<'a> {
 }
 
 #[cfg(feature = "image-data")]
-impl ImageData<'_> {
+impl<'a> ImageData<'a> {
+	/// Creates an image from tightly packed RGBA pixels.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if `bytes` isn't `width * height * 4` bytes
+	/// long.
+	///
+	/// ```
+	/// use arboard::{Error, ImageData};
+	///
+	/// // A red and a green pixel.
+	/// let bytes = [255, 0, 0, 255, 0, 255, 0, 255];
+	/// let img = ImageData::new(2, 1, bytes.as_ref()).unwrap();
+	/// assert_eq!((img.width, img.height), (2, 1));
+	///
+	/// // A pixel is missing.
+	/// let result = ImageData::new(2, 2, bytes.as_ref());
+	/// assert!(matches!(result, Err(Error::InvalidImageDimensions { expected: 16, actual: 8 })));
+	/// ```
+	pub fn new(
+		width: usize,
+		height: usize,
+		bytes: impl Into<Cow<'a, [u8]>>,
+	) -> Result<Self, Error> {
+		let image = ImageData { width, height, bytes: bytes.into() };
+		image.check_dimensions()?;
+		Ok(image)
+	}
+
+	/// Creates an image from pixel data whose rows are `stride` bytes apart, like the
+	/// DWORD-aligned rows of Windows bitmaps.
+	///
+	/// Any padding at the end of the rows is dropped. If the rows are already tightly packed,
+	/// `bytes` is borrowed instead of copied.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if `stride` is shorter than a row of `width`
+	/// pixels, or if `bytes` is too short to hold `height` rows.
+	pub fn from_strided(
+		width: usize,
+		height: usize,
+		stride: usize,
+		bytes: &'a [u8],
+	) -> Result<ImageData<'a>, Error> {
+		let row_len = width.saturating_mul(4);
+		if stride < row_len {
+			return Err(Error::InvalidImageDimensions {
+				expected: row_len.saturating_mul(height),
+				actual: stride.saturating_mul(height),
+			});
+		}
+		// The last row doesn't need to be padded.
+		let expected = match height {
+			0 => 0,
+			_ => stride.saturating_mul(height - 1).saturating_add(row_len),
+		};
+		if bytes.len() < expected {
+			return Err(Error::InvalidImageDimensions { expected, actual: bytes.len() });
+		}
+
+		let bytes = if stride == row_len {
+			Cow::Borrowed(&bytes[..expected])
+		} else {
+			let mut packed = Vec::with_capacity(row_len * height);
+			for row in bytes.chunks(stride).take(height) {
+				packed.extend_from_slice(&row[..row_len]);
+			}
+			Cow::Owned(packed)
+		};
+		Ok(ImageData { width, height, bytes })
+	}
+
+	/// Creates an image from tightly packed RGB pixels, with three bytes per pixel, making every
+	/// pixel opaque.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if `bytes` isn't `width * height * 3` bytes long.
+	pub fn from_rgb8(
+		width: usize,
+		height: usize,
+		bytes: &[u8],
+	) -> Result<ImageData<'static>, Error> {
+		Self::expand(width, height, bytes, 3, |pixel| [pixel[0], pixel[1], pixel[2], 255])
+	}
+
+	/// Creates an image from tightly packed grayscale pixels, with one byte per pixel, making
+	/// every pixel opaque.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if `bytes` isn't `width * height` bytes long.
+	pub fn from_luma8(
+		width: usize,
+		height: usize,
+		bytes: &[u8],
+	) -> Result<ImageData<'static>, Error> {
+		Self::expand(width, height, bytes, 1, |pixel| [pixel[0], pixel[0], pixel[0], 255])
+	}
+
+	/// Converts each pixel of `channels` bytes to RGBA with `to_rgba`.
+	fn expand(
+		width: usize,
+		height: usize,
+		bytes: &[u8],
+		channels: usize,
+		to_rgba: impl Fn(&[u8]) -> [u8; 4],
+	) -> Result<ImageData<'static>, Error> {
+		let expected = width.saturating_mul(height).saturating_mul(channels);
+		if bytes.len() != expected {
+			return Err(Error::InvalidImageDimensions { expected, actual: bytes.len() });
+		}
+		let bytes = bytes.chunks_exact(channels).flat_map(to_rgba).collect::<Vec<u8>>();
+		Ok(ImageData { width, height, bytes: bytes.into() })
+	}
+
 	/// Returns a the bytes field in a way that it's guaranteed to be owned.
 	/// It moves the bytes if they are already owned and clones them if they are borrowed.
 	pub fn into_owned_bytes(self) -> Cow<'static, [u8]> {
@@ -148,6 +395,439 @@ impl ImageData<'_> {
 			bytes: self.bytes.clone().into_owned().into(),
 		}
 	}
+
+	/// Checks that `bytes` holds exactly four bytes for each pixel of the image.
+	pub(crate) fn check_dimensions(&self) -> Result<(), Error> {
+		let expected = self.width.saturating_mul(self.height).saturating_mul(4);
+		let actual = self.bytes.len();
+		if expected == actual {
+			Ok(())
+		} else {
+			Err(Error::InvalidImageDimensions { expected, actual })
+		}
+	}
+
+	/// Scales the color channels of every pixel by its alpha.
+	pub(crate) fn premultiply_alpha(&mut self) {
+		for pixel in self.bytes.to_mut().chunks_exact_mut(4) {
+			let alpha = u16::from(pixel[3]);
+			for channel in &mut pixel[..3] {
+				*channel = ((u16::from(*channel) * alpha + 127) / 255) as u8;
+			}
+		}
+	}
+
+	/// Undoes [`premultiply_alpha`](Self::premultiply_alpha). Fully transparent pixels are left
+	/// as they are, because their color can't be recovered.
+	#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+	pub(crate) fn unpremultiply_alpha(&mut self) {
+		for pixel in self.bytes.to_mut().chunks_exact_mut(4) {
+			let alpha = u16::from(pixel[3]);
+			if alpha == 0 {
+				continue;
+			}
+			for channel in &mut pixel[..3] {
+				*channel = ((u16::from(*channel) * 255 + alpha / 2) / alpha).min(255) as u8;
+			}
+		}
+	}
+
+	/// Returns a copy of the image, scaled down to fit within `max_width` and `max_height`.
+	///
+	/// The aspect ratio is preserved. Images which already fit within the bounds are returned
+	/// unchanged; images are never scaled up.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if the length of `bytes` doesn't match the image's
+	/// dimensions.
+	pub fn resized(
+		&self,
+		max_width: usize,
+		max_height: usize,
+	) -> Result<ImageData<'static>, Error> {
+		// Also checked for images that fit, so that they aren't passed on unvalidated.
+		self.check_dimensions().map_err(|_| Error::ConversionFailure)?;
+		if self.width <= max_width && self.height <= max_height {
+			return Ok(self.to_owned_img());
+		}
+
+		let image = self.to_owned_img().into_dynamic_image()?;
+		let resized = image.resize(
+			u32::try_from(max_width).unwrap_or(u32::MAX),
+			u32::try_from(max_height).unwrap_or(u32::MAX),
+			image::imageops::FilterType::Triangle,
+		);
+		Ok(ImageData::from_dynamic_image(resized))
+	}
+
+	/// Returns a copy of the `width` by `height` region of the image whose top left corner is at
+	/// `x` and `y`.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if the length of `bytes` doesn't match the
+	/// image's dimensions, and [`Error::ConversionFailure`] if the region doesn't lie within the
+	/// image.
+	pub fn crop(
+		&self,
+		x: usize,
+		y: usize,
+		width: usize,
+		height: usize,
+	) -> Result<ImageData<'static>, Error> {
+		self.check_dimensions()?;
+		let fits = |start: usize, len: usize, bound: usize| {
+			start.checked_add(len).map_or(false, |end| end <= bound)
+		};
+		if !fits(x, width, self.width) || !fits(y, height, self.height) {
+			return Err(Error::ConversionFailure);
+		}
+
+		// Also keeps `chunks_exact` from being called with a length of zero.
+		if width == 0 || height == 0 {
+			return Ok(ImageData { width, height, bytes: Vec::new().into() });
+		}
+
+		let row_len = width * 4;
+		let mut bytes = Vec::with_capacity(row_len * height);
+		for row in self.bytes.chunks_exact(self.width * 4).skip(y).take(height) {
+			bytes.extend_from_slice(&row[x * 4..][..row_len]);
+		}
+		Ok(ImageData { width, height, bytes: bytes.into() })
+	}
+
+	/// Converts the image into an [`image::DynamicImage`], for use with the `image` crate.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if the length of `bytes` doesn't match the image's
+	/// dimensions.
+	pub fn into_dynamic_image(self) -> Result<image::DynamicImage, Error> {
+		self.into_rgba_image().map(image::DynamicImage::ImageRgba8)
+	}
+
+	/// Converts the image into an [`image::RgbaImage`], for use with the `image` crate, without
+	/// copying the pixels if they are owned.
+	///
+	/// # Errors
+	///
+	/// Returns [`Error::ConversionFailure`] if the length of `bytes` doesn't match the image's
+	/// dimensions.
+	///
+	/// # Examples
+	///
+	/// ```
+	/// # use arboard::ImageData;
+	/// # fn main() -> Result<(), arboard::Error> {
+	/// let pixels = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 255]));
+	/// let image = ImageData::from(pixels.clone());
+	/// assert_eq!((image.width, image.height), (2, 1));
+	/// assert_eq!(image.into_rgba_image()?, pixels);
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn into_rgba_image(self) -> Result<image::RgbaImage, Error> {
+		let width = u32::try_from(self.width).map_err(|_| Error::ConversionFailure)?;
+		let height = u32::try_from(self.height).map_err(|_| Error::ConversionFailure)?;
+		image::RgbaImage::from_raw(width, height, self.bytes.into_owned())
+			.ok_or(Error::ConversionFailure)
+	}
+
+	/// Creates an image from an [`image::DynamicImage`], converting its pixels to RGBA if needed.
+	pub fn from_dynamic_image(image: image::DynamicImage) -> ImageData<'static> {
+		image.into_rgba8().into()
+	}
+}
+
+#[cfg(feature = "image-data")]
+impl From<image::RgbaImage> for ImageData<'static> {
+	fn from(image: image::RgbaImage) -> Self {
+		let (width, height) = image.dimensions();
+		ImageData { width: width as usize, height: height as usize, bytes: image.into_raw().into() }
+	}
+}
+
+/// How the alpha channel of an image read from the clipboard is applied to its colors.
+///
+/// See [`Get::alpha_mode`](crate::Get::alpha_mode).
+#[cfg(feature = "image-data")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AlphaMode {
+	/// The color channels are independent of the alpha, which is what [`ImageData`] holds by
+	/// default.
+	#[default]
+	Straight,
+
+	/// The color channels are already multiplied by the alpha, as expected by many graphics
+	/// APIs for blending.
+	Premultiplied,
+}
+
+/// An image read from the clipboard, along with its resolution if the clipboard data
+/// specified one.
+///
+/// Returned by [`Get::image_with_dpi`](crate::Get::image_with_dpi).
+#[cfg(feature = "image-data")]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ImageDataWithMeta {
+	/// The pixels of the image.
+	pub image: ImageData<'static>,
+
+	/// The horizontal resolution in dots per inch, or `None` if it's unknown.
+	pub x_dpi: Option<f64>,
+
+	/// The vertical resolution in dots per inch, or `None` if it's unknown.
+	pub y_dpi: Option<f64>,
+}
+
+#[cfg(feature = "image-data")]
+impl ImageDataWithMeta {
+	pub(crate) fn new(image: ImageData<'static>) -> Self {
+		ImageDataWithMeta { image, x_dpi: None, y_dpi: None }
+	}
+
+	/// Converts a resolution in pixels per meter, as used by both bitmaps and PNGs, to dots per
+	/// inch. Zero means that the resolution is unknown.
+	#[cfg_attr(target_os = "macos", allow(dead_code))]
+	pub(crate) fn dpi_from_pixels_per_meter(pixels_per_meter: u32) -> Option<f64> {
+		const METERS_PER_INCH: f64 = 0.0254;
+
+		if pixels_per_meter == 0 {
+			None
+		} else {
+			Some(f64::from(pixels_per_meter) * METERS_PER_INCH)
+		}
+	}
+}
+
+/// The image which was last read from the clipboard, kept to skip decoding it again as long as
+/// the clipboard's contents stay the same.
+///
+/// See [`ClipboardBuilder::cache_images`](crate::ClipboardBuilder::cache_images).
+#[cfg(feature = "image-data")]
+#[derive(Default)]
+pub(crate) struct ImageCache {
+	/// The key which identified the clipboard's contents and the options when the image was read.
+	entry: Option<(ImageCacheKey, ImageDataWithMeta)>,
+}
+
+/// Identifies an image read from the clipboard: the clipboard's contents at the time, and the
+/// options which change what's read from them.
+#[cfg(feature = "image-data")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ImageCacheKey {
+	/// Changes whenever the clipboard's contents are replaced.
+	pub(crate) change_key: u64,
+	pub(crate) max_bytes: Option<usize>,
+	/// See [`GetExtWindows::assume_opaque_when_no_alpha`](crate::GetExtWindows::assume_opaque_when_no_alpha).
+	#[cfg(windows)]
+	pub(crate) assume_opaque: bool,
+}
+
+#[cfg(feature = "image-data")]
+impl ImageCache {
+	/// Reads the image with `get`, unless the clipboard's contents weren't replaced since the
+	/// cached image was read with the same options.
+	pub(crate) fn image(
+		&mut self,
+		get: crate::platform::Get<'_>,
+	) -> Result<ImageDataWithMeta, Error> {
+		let Some(key) = get.image_cache_key() else {
+			self.entry = None;
+			return get.image_with_dpi();
+		};
+		match self.entry.take() {
+			Some((cached_key, image)) if cached_key == key => {
+				self.entry = Some((key, image.clone()));
+				Ok(image)
+			}
+			// The outdated image is dropped before decoding the new one.
+			_ => {
+				let image = get.image_with_dpi()?;
+				self.entry = Some((key, image.clone()));
+				Ok(image)
+			}
+		}
+	}
+}
+
+/// Decodes a GIF file read from the clipboard. Animated GIFs are read as their first frame.
+#[cfg(feature = "image-data")]
+pub(crate) fn decode_gif(gif: &[u8]) -> Result<ImageData<'static>, Error> {
+	use image::{codecs::gif::GifDecoder, AnimationDecoder as _};
+
+	let decoder =
+		GifDecoder::new(std::io::Cursor::new(gif)).map_err(|_| Error::ConversionFailure)?;
+	// Each frame is composed onto the whole canvas, so it has the size of the image.
+	let first_frame = decoder.into_frames().next().ok_or(Error::ConversionFailure)?;
+	Ok(first_frame.map_err(|_| Error::ConversionFailure)?.into_buffer().into())
+}
+
+/// Inserts `icc` into `png`, a PNG file written by the `image` crate, as its `iCCP` chunk so
+/// that readers interpret the colors in that color space.
+#[cfg(all(feature = "image-data", any(windows, all(unix, not(target_os = "macos")))))]
+pub(crate) fn embed_icc_profile(png: &mut Vec<u8>, icc: &[u8]) -> Result<(), Error> {
+	use std::io::Write as _;
+
+	const SIGNATURE_LEN: usize = 8;
+	// `IHDR` is always the first chunk and has a fixed size: the length, the chunk type, 13 bytes
+	// of data and the CRC. `iCCP` has to come after it, but before the image data.
+	const IHDR_END: usize = SIGNATURE_LEN + 4 + 4 + 13 + 4;
+	const PROFILE_NAME: &[u8] = b"ICC profile";
+
+	if png.len() < IHDR_END || &png[SIGNATURE_LEN + 4..SIGNATURE_LEN + 8] != b"IHDR" {
+		return Err(Error::ConversionFailure);
+	}
+
+	let mut data = PROFILE_NAME.to_vec();
+	// The NUL terminator of the name, followed by the compression method, which is always zlib.
+	data.extend_from_slice(&[0, 0]);
+	let mut encoder = flate2::write::ZlibEncoder::new(data, flate2::Compression::default());
+	encoder.write_all(icc).map_err(|_| Error::ConversionFailure)?;
+	let data = encoder.finish().map_err(|_| Error::ConversionFailure)?;
+	let data_len = u32::try_from(data.len()).map_err(|_| Error::ConversionFailure)?;
+
+	let mut chunk = Vec::with_capacity(data.len() + 12);
+	chunk.extend_from_slice(&data_len.to_be_bytes());
+	chunk.extend_from_slice(b"iCCP");
+	chunk.extend_from_slice(&data);
+	// The CRC covers the chunk type and the data, but not the length.
+	let crc = crc32fast::hash(&chunk[4..]);
+	chunk.extend_from_slice(&crc.to_be_bytes());
+
+	png.splice(IHDR_END..IHDR_END, chunk);
+	Ok(())
+}
+
+/// The clipboard to operate on, for code which works the same way on every platform.
+///
+/// Only Linux has more than one clipboard. The other platforms only support
+/// [`Clipboard`](Self::Clipboard), and operations on any other selection return
+/// [`Error::ClipboardNotSupported`].
+///
+/// See [`Get::selection`](crate::Get::selection) and [`Set::selection`](crate::Set::selection).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SelectionKind {
+	/// The clipboard used for explicit cut/copy/paste actions.
+	#[default]
+	Clipboard,
+
+	/// The selection for the currently selected text, pasted with a middle click.
+	///
+	/// *Only available on Linux.*
+	Primary,
+
+	/// The rarely used secondary selection.
+	///
+	/// *Only available on X11.*
+	Secondary,
+}
+
+/// The settings for constructing a [`Clipboard`](crate::Clipboard), set through the
+/// [`ClipboardBuilder`](crate::ClipboardBuilder).
+///
+/// Every platform reads the settings which apply to it from here, so that the builder doesn't
+/// need to know which ones those are.
+#[derive(Debug, Default, Clone)]
+// Each platform only reads its own settings, the others are left unused.
+#[allow(dead_code)]
+pub(crate) struct ClipboardConfig {
+	/// The X11 display to connect to, or `None` to use the `DISPLAY` environment variable.
+	pub(crate) x11_display: Option<String>,
+
+	/// The screen of the X11 display to create the clipboard's window on, or `None` to use the
+	/// display's default screen.
+	pub(crate) x11_screen: Option<usize>,
+
+	/// How long to wait for the selection owner to respond when reading over X11.
+	pub(crate) x11_read_timeout: Option<Duration>,
+
+	/// Whether to skip handing the data over to the clipboard manager when the last X11
+	/// clipboard is dropped.
+	pub(crate) x11_skip_handover: bool,
+
+	/// How long to wait for the clipboard manager to take over the data when the last X11
+	/// clipboard is dropped.
+	pub(crate) x11_handover_timeout: Option<Duration>,
+
+	/// The name of the hidden X11 window which owns the clipboard, or `None` to name it after
+	/// the executable.
+	pub(crate) x11_window_name: Option<String>,
+
+	/// The Wayland seat to operate on, or `None` to let the compositor decide.
+	pub(crate) wayland_seat: Option<String>,
+
+	/// How long to wait for a clipboard manager to take over the data of a Wayland copy that is
+	/// served in the background, when the last clipboard is dropped.
+	pub(crate) wayland_flush_timeout: Option<Duration>,
+
+	/// Whether to use X11 even when the Wayland data control protocol is available.
+	pub(crate) force_x11: bool,
+
+	/// How many times to retry opening the Windows clipboard if it's held by another process,
+	/// or `None` for the default.
+	pub(crate) windows_open_attempts: Option<usize>,
+
+	/// The name of the macOS pasteboard to use, or `None` for the general pasteboard.
+	pub(crate) macos_pasteboard_name: Option<String>,
+}
+
+/// Produces the bytes of data that was placed onto the clipboard with [`Set::lazy`](crate::Set::lazy).
+pub(crate) type DataProducer = Box<dyn FnOnce() -> Vec<u8> + Send + 'static>;
+
+/// Runs a clipboard operation, which is recorded as a `tracing` span when the `tracing` feature
+/// is enabled.
+///
+/// The span holds the `operation` ("get", "set" or "clear"), the `format` it works with, the
+/// number of `bytes` as told by `bytes` on success, how long it took in `elapsed_us`, and the
+/// `error` otherwise.
+pub(crate) fn traced<T>(
+	operation: &'static str,
+	format: &'static str,
+	run: impl FnOnce() -> Result<T, Error>,
+	bytes: impl FnOnce(&T) -> usize,
+) -> Result<T, Error> {
+	#[cfg(feature = "tracing")]
+	{
+		use tracing::field::{display, Empty};
+
+		let span = tracing::debug_span!(
+			"clipboard",
+			operation,
+			format,
+			bytes = Empty,
+			elapsed_us = Empty,
+			error = Empty
+		);
+		let _entered = span.enter();
+		let start = std::time::Instant::now();
+		let result = run();
+		span.record("elapsed_us", start.elapsed().as_micros() as u64);
+		match &result {
+			Ok(value) => span.record("bytes", bytes(value)),
+			Err(e) => span.record("error", display(e)),
+		};
+		result
+	}
+
+	#[cfg(not(feature = "tracing"))]
+	{
+		let _ = (operation, format, bytes);
+		run()
+	}
+}
+
+/// Removes the byte order mark which some applications put at the start of text, unless `keep`
+/// is set.
+pub(crate) fn strip_bom(mut text: String, keep: bool) -> String {
+	if !keep && text.starts_with('\u{feff}') {
+		text.drain(..'\u{feff}'.len_utf8());
+	}
+	text
 }
 
 #[cfg(any(windows, all(unix, not(target_os = "macos"))))]
@@ -180,3 +860,205 @@ pub(crate) mod private {
 	impl Sealed for crate::Set<'_> {}
 	impl Sealed for crate::Clear<'_> {}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn os_error_is_kept_as_source() {
+		let error = Error::Platform {
+			description: String::from("failed"),
+			source: Box::new(std::io::Error::from_raw_os_error(1418)),
+		};
+		assert_eq!(error.os_code(), Some(1418));
+		assert!(std::error::Error::source(&error).is_some());
+
+		assert_eq!(Error::unknown("failed").os_code(), None);
+		assert!(std::error::Error::source(&Error::ContentNotAvailable).is_none());
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn gif_is_read_as_first_frame() {
+		use image::{codecs::gif::GifEncoder, Delay, Frame, RgbaImage};
+
+		let frames = [[255, 0, 0, 255], [0, 0, 255, 255]].map(|pixel| {
+			let buffer = RgbaImage::from_fn(3, 2, |_, _| image::Rgba(pixel));
+			Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+		});
+		let mut gif = Vec::new();
+		GifEncoder::new(&mut gif).encode_frames(frames).unwrap();
+
+		let image = decode_gif(&gif).unwrap();
+		assert_eq!((image.width, image.height), (3, 2));
+		assert_eq!(&image.bytes[..4], [255, 0, 0, 255]);
+	}
+
+	#[test]
+	fn leading_bom_is_stripped() {
+		assert_eq!(strip_bom(String::from("\u{feff}a\u{feff}"), false), "a\u{feff}");
+		assert_eq!(strip_bom(String::from("\u{feff}a"), true), "\u{feff}a");
+		assert_eq!(strip_bom(String::from("a"), false), "a");
+	}
+
+	#[test]
+	fn errors_have_a_kind() {
+		assert_eq!(Error::ClipboardOccupied.kind(), ErrorKind::Occupied);
+		assert_eq!(Error::unknown("failed").kind(), ErrorKind::Unknown);
+		let error = Error::InvalidImageDimensions { expected: 4, actual: 3 };
+		assert_eq!(error.kind(), ErrorKind::InvalidImageDimensions);
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn new_checks_the_length() {
+		let image = ImageData::new(1, 2, vec![9; 8]).unwrap();
+		assert_eq!((image.width, image.height, image.bytes.len()), (1, 2, 8));
+
+		assert!(matches!(
+			ImageData::new(2, 2, [0; 15].as_ref()),
+			Err(Error::InvalidImageDimensions { expected: 16, actual: 15 })
+		));
+		assert!(matches!(
+			ImageData::new(usize::MAX, 2, Vec::new()),
+			Err(Error::InvalidImageDimensions { expected: usize::MAX, actual: 0 })
+		));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn rgb_and_luma_are_expanded() {
+		let rgb = ImageData::from_rgb8(2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+		assert_eq!((rgb.width, rgb.height), (2, 1));
+		assert_eq!(rgb.bytes.as_ref(), [1, 2, 3, 255, 4, 5, 6, 255]);
+
+		let luma = ImageData::from_luma8(1, 2, &[7, 8]).unwrap();
+		assert_eq!(luma.bytes.as_ref(), [7, 7, 7, 255, 8, 8, 8, 255]);
+
+		assert!(matches!(
+			ImageData::from_rgb8(2, 2, &[0; 6]),
+			Err(Error::InvalidImageDimensions { expected: 12, actual: 6 })
+		));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn resized_preserves_aspect_ratio() {
+		let img = ImageData { width: 8, height: 4, bytes: vec![255; 8 * 4 * 4].into() };
+
+		let small = img.resized(4, 4).unwrap();
+		assert_eq!((small.width, small.height), (4, 2));
+		assert_eq!(small.bytes.len(), 4 * 2 * 4);
+
+		let same = img.resized(16, 16).unwrap();
+		assert_eq!((same.width, same.height), (8, 4));
+
+		// A bound beyond `u32` must not wrap around to a tiny one.
+		let huge = usize::try_from(u64::from(u32::MAX) + 2).unwrap_or(usize::MAX);
+		let tall = img.resized(4, huge).unwrap();
+		assert_eq!((tall.width, tall.height), (4, 2));
+
+		let invalid = ImageData { width: 8, height: 4, bytes: vec![255; 3].into() };
+		assert!(matches!(invalid.resized(4, 4), Err(Error::ConversionFailure)));
+		assert!(matches!(invalid.resized(16, 16), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn strided_rows_are_packed() {
+		// Two rows of one pixel each, padded to eight bytes.
+		let padded = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8];
+		let image = ImageData::from_strided(1, 2, 8, &padded).unwrap();
+		assert_eq!(image.bytes.as_ref(), &[1, 2, 3, 4, 5, 6, 7, 8]);
+		image.check_dimensions().unwrap();
+
+		let tight = ImageData::from_strided(1, 2, 4, &padded).unwrap();
+		assert!(matches!(tight.bytes, Cow::Borrowed(_)));
+		assert_eq!(tight.bytes.as_ref(), &[1, 2, 3, 4, 0, 0, 0, 0]);
+
+		assert!(matches!(
+			ImageData::from_strided(1, 2, 3, &padded),
+			Err(Error::InvalidImageDimensions { .. })
+		));
+		assert!(matches!(
+			ImageData::from_strided(1, 3, 8, &padded),
+			Err(Error::InvalidImageDimensions { expected: 20, actual: 12 })
+		));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn images_are_cropped() {
+		// A 3x2 image whose pixels hold their own index.
+		let bytes: Vec<u8> = (0..6).flat_map(|i| [i; 4]).collect();
+		let image = ImageData { width: 3, height: 2, bytes: bytes.into() };
+
+		let cropped = image.crop(1, 0, 2, 2).unwrap();
+		assert_eq!((cropped.width, cropped.height), (2, 2));
+		assert_eq!(cropped.bytes.as_ref(), &[1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 5, 5, 5, 5]);
+		assert_eq!(image.crop(0, 0, 3, 2).unwrap(), image);
+		assert_eq!(image.crop(3, 2, 0, 0).unwrap().bytes.len(), 0);
+		let empty = ImageData { width: 0, height: 2, bytes: Vec::new().into() };
+		assert_eq!(empty.crop(0, 1, 0, 1).unwrap().height, 1);
+
+		assert!(matches!(image.crop(2, 0, 2, 1), Err(Error::ConversionFailure)));
+		assert!(matches!(image.crop(0, usize::MAX, 1, 2), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn images_are_compared_by_content() {
+		use std::collections::HashSet;
+
+		let pixels = [255, 0, 0, 255, 0, 255, 0, 255];
+		let borrowed = ImageData { width: 2, height: 1, bytes: pixels.as_ref().into() };
+		let owned = ImageData { width: 2, height: 1, bytes: pixels.to_vec().into() };
+		let transposed = ImageData { width: 1, height: 2, bytes: pixels.as_ref().into() };
+		assert_eq!(borrowed, owned);
+		assert_ne!(borrowed, transposed);
+
+		let history: HashSet<_> = [borrowed, owned, transposed].into_iter().collect();
+		assert_eq!(history.len(), 2);
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn alpha_is_premultiplied_and_back() {
+		let straight = [200, 100, 50, 128, 10, 20, 30, 0, 1, 2, 3, 255];
+		let mut image = ImageData { width: 3, height: 1, bytes: straight.as_ref().into() };
+
+		image.premultiply_alpha();
+		assert_eq!(image.bytes.as_ref(), &[100, 50, 25, 128, 0, 0, 0, 0, 1, 2, 3, 255]);
+
+		image.unpremultiply_alpha();
+		assert_eq!(image.bytes.as_ref(), &[199, 100, 50, 128, 0, 0, 0, 0, 1, 2, 3, 255]);
+	}
+
+	#[test]
+	#[cfg(all(feature = "image-data", not(target_os = "macos")))]
+	fn icc_profile_is_embedded() {
+		use image::{codecs::png, ImageDecoder as _, ImageEncoder as _};
+		use std::io::Read as _;
+
+		let mut file = Vec::new();
+		png::PngEncoder::new(&mut file)
+			.write_image(&[255; 2 * 2 * 4], 2, 2, image::ExtendedColorType::Rgba8)
+			.unwrap();
+
+		let profile = b"not really an ICC profile, but the PNG doesn't care".repeat(8);
+		embed_icc_profile(&mut file, &profile).unwrap();
+
+		// Decoding checks the CRC and the placement of the chunk.
+		let mut decoder = png::PngDecoder::new(std::io::Cursor::new(&file)).unwrap();
+		assert!(decoder.icc_profile().unwrap().is_some());
+
+		let name_end = 8 + 25 + 8 + b"ICC profile".len();
+		assert_eq!(&file[8 + 25 + 4..8 + 25 + 8], b"iCCP");
+		assert_eq!(&file[name_end..name_end + 2], &[0, 0]);
+		let mut inflated = Vec::new();
+		flate2::read::ZlibDecoder::new(&file[name_end + 2..]).read_to_end(&mut inflated).unwrap();
+		assert_eq!(inflated, profile);
+		assert!(matches!(embed_icc_profile(&mut vec![0; 8], &[]), Err(Error::ConversionFailure)));
+	}
+}
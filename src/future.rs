@@ -0,0 +1,118 @@
+/*
+SPDX-License-Identifier: Apache-2.0 OR MIT
+
+Copyright 2022 The Arboard contributors
+
+The project to which this file belongs is licensed under either of
+the Apache 2.0 or the MIT license at the licensee's choice. The terms
+and conditions of the chosen license apply to this file.
+*/
+
+use std::{
+	future::Future,
+	pin::Pin,
+	sync::{Arc, Mutex, PoisonError},
+	task::{Context, Poll, Waker},
+	thread,
+};
+
+use crate::Error;
+
+/// A clipboard operation running in the background, returned by the `*_async` methods of
+/// [`Clipboard`](crate::Clipboard).
+///
+/// Each operation runs on its own thread, with a new `Clipboard` created using the same settings
+/// as the one it was started from. The future resolves once that thread finishes, so it doesn't
+/// block the executor, and it can be awaited on any executor, such as `tokio` or `async-std`.
+///
+/// The operation runs even if the future is never polled, or dropped before it completes.
+#[must_use = "the result of the clipboard operation is lost unless the future is awaited"]
+pub struct ClipboardFuture<T> {
+	shared: Arc<Mutex<Shared<T>>>,
+}
+
+struct Shared<T> {
+	result: Option<Result<T, Error>>,
+	waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> ClipboardFuture<T> {
+	pub(crate) fn spawn<F>(operation: F) -> Self
+	where
+		F: FnOnce() -> Result<T, Error> + Send + 'static,
+	{
+		let shared = Arc::new(Mutex::new(Shared { result: None, waker: None }));
+
+		let thread_shared = Arc::clone(&shared);
+		let spawned = thread::Builder::new().name("arboard-async".into()).spawn(move || {
+			let result = operation();
+
+			let mut shared = thread_shared.lock().unwrap_or_else(PoisonError::into_inner);
+			shared.result = Some(result);
+			if let Some(waker) = shared.waker.take() {
+				waker.wake();
+			}
+		});
+
+		if let Err(e) = spawned {
+			let mut state = shared.lock().unwrap_or_else(PoisonError::into_inner);
+			state.result =
+				Some(Err(Error::unknown(format!("Failed to spawn the clipboard thread: {e}"))));
+		}
+
+		Self { shared }
+	}
+}
+
+impl<T> Future for ClipboardFuture<T> {
+	type Output = Result<T, Error>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+		match shared.result.take() {
+			Some(result) => Poll::Ready(result),
+			None => {
+				shared.waker = Some(cx.waker().clone());
+				Poll::Pending
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::task::Wake;
+
+	struct ThreadWaker(thread::Thread);
+
+	impl Wake for ThreadWaker {
+		fn wake(self: Arc<Self>) {
+			self.0.unpark();
+		}
+	}
+
+	fn block_on<F: Future>(future: F) -> F::Output {
+		let mut future = Box::pin(future);
+		let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+		let mut cx = Context::from_waker(&waker);
+		loop {
+			match future.as_mut().poll(&mut cx) {
+				Poll::Ready(output) => return output,
+				Poll::Pending => thread::park(),
+			}
+		}
+	}
+
+	#[test]
+	fn resolves_with_the_result_of_the_operation() {
+		let future = ClipboardFuture::spawn(|| {
+			thread::sleep(std::time::Duration::from_millis(10));
+			Ok(42)
+		});
+		assert_eq!(block_on(future).unwrap(), 42);
+
+		let future = ClipboardFuture::<()>::spawn(|| Err(Error::ContentNotAvailable));
+		assert!(matches!(block_on(future), Err(Error::ContentNotAvailable)));
+	}
+}
@@ -10,25 +10,40 @@ and conditions of the chosen license apply to this file.
 #![warn(unreachable_pub)]
 
 mod common;
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 
-pub use common::Error;
 #[cfg(feature = "image-data")]
-pub use common::ImageData;
+pub use common::{AlphaMode, ImageData, ImageDataWithMeta};
+pub use common::{ClipboardContent, Error, ErrorKind, SelectionKind};
 
 mod platform;
 
+#[cfg(feature = "async")]
+mod future;
+#[cfg(feature = "async")]
+pub use future::ClipboardFuture;
+
+#[cfg(all(
+	unix,
+	not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	feature = "image-data"
+))]
+pub use platform::PngCompression;
 #[cfg(all(
 	unix,
 	not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
 ))]
-pub use platform::{ClearExtLinux, GetExtLinux, LinuxClipboardKind, SetExtLinux};
+pub use platform::{Backend, ClearExtLinux, GetExtLinux, LinuxClipboardKind, SetExtLinux};
 
+#[cfg(all(windows, feature = "image-data"))]
+pub use platform::WinImageFormat;
 #[cfg(windows)]
-pub use platform::SetExtWindows;
+pub use platform::{parse_cf_html, CfHtml, DropEffect, GetExtWindows, SetExtWindows};
 
+#[cfg(all(target_os = "macos", feature = "image-data"))]
+pub use platform::ImageRepresentation;
 #[cfg(target_os = "macos")]
-pub use platform::SetExtApple;
+pub use platform::{GetExtApple, SetExtApple};
 
 /// The OS independent struct for accessing the clipboard.
 ///
@@ -55,6 +70,17 @@ pub use platform::SetExtApple;
 /// that last put data onto it. This means that when the last `Clipboard` instance is dropped, the contents
 /// may become unavailable to other apps. See [SetExtLinux] for more details.
 ///
+/// On X11, the connection to the X server and the thread serving the clipboard's contents are only
+/// set up once a `Clipboard` is first used. Creating one that's never used is cheap, but it also
+/// means that a missing X server is only reported by the first operation.
+///
+/// On Wayland, the data control protocol is served by `wl-clipboard-rs` over a Wayland connection
+/// of its own. The contents are served by a background thread of the current process, or by the
+/// calling thread when [`SetExtLinux::wait`] is used, so no other process is ever spawned.
+/// Serving them through the application's own Wayland connection and event loop isn't possible,
+/// as `wl-clipboard-rs` can't be handed an existing connection. Applications that need this have
+/// to implement the `wl_data_device` protocol themselves.
+///
 /// ## Windows
 ///
 /// The clipboard on Windows is a global object, which may only be opened on one thread at once.
@@ -66,6 +92,15 @@ pub use platform::SetExtApple;
 #[allow(rustdoc::broken_intra_doc_links)]
 pub struct Clipboard {
 	pub(crate) platform: platform::Clipboard,
+
+	/// The last image that was read, if images are cached.
+	#[cfg(feature = "image-data")]
+	image_cache: Option<common::ImageCache>,
+
+	/// The settings this clipboard was built with, to create the clipboards which run
+	/// asynchronous operations.
+	#[cfg(feature = "async")]
+	builder: ClipboardBuilder,
 }
 
 impl Clipboard {
@@ -75,12 +110,36 @@ impl Clipboard {
 	///
 	/// On some platforms or desktop environments, an error can be returned if clipboards are not
 	/// supported. This may be retried.
+	///
+	/// On X11, the connection to the X server is only made when the clipboard is first used, so
+	/// this succeeds even if the server can't be reached. The first operation reports that
+	/// instead.
 	pub fn new() -> Result<Self, Error> {
-		Ok(Clipboard { platform: platform::Clipboard::new()? })
+		ClipboardBuilder::new().build()
+	}
+
+	/// Begins configuring a clipboard with non-default settings.
+	///
+	/// See [`ClipboardBuilder`] for the available options.
+	pub fn builder() -> ClipboardBuilder {
+		ClipboardBuilder::new()
+	}
+
+	/// Creates an instance of the clipboard with the settings of `config`.
+	///
+	/// This is the same as calling [`ClipboardBuilder::build`] on `config`.
+	///
+	/// # Errors
+	///
+	/// See [`Clipboard::new`].
+	pub fn new_with_config(config: ClipboardBuilder) -> Result<Self, Error> {
+		config.build()
 	}
 
 	/// Fetches UTF-8 text from the clipboard and returns it.
 	///
+	/// A leading byte order mark is removed, see [`Get::keep_bom`].
+	///
 	/// # Errors
 	///
 	/// Returns error if clipboard is empty or contents are not UTF-8 text.
@@ -88,6 +147,29 @@ impl Clipboard {
 		self.get().text()
 	}
 
+	/// Fetches UTF-8 text from the clipboard, if there is any.
+	///
+	/// Unlike [`get_text`](Self::get_text), this returns `Ok(None)` instead of
+	/// [`Error::ContentNotAvailable`] or [`Error::WrongFormat`] when the clipboard is empty or
+	/// doesn't contain text.
+	///
+	/// # Errors
+	///
+	/// Returns error if the contents are not UTF-8 text or the clipboard couldn't be read.
+	pub fn try_get_text(&mut self) -> Result<Option<String>, Error> {
+		content_if_available(self.get_text())
+	}
+
+	/// Fetches UTF-8 text from the given `selection`.
+	///
+	/// # Errors
+	///
+	/// Like [`get_text`](Self::get_text), and returns [`Error::ClipboardNotSupported`] if the
+	/// selection isn't available on this platform.
+	pub fn get_text_from(&mut self, selection: SelectionKind) -> Result<String, Error> {
+		self.get().selection(selection).text()
+	}
+
 	/// Places the text onto the clipboard. Any valid UTF-8 string is accepted.
 	///
 	/// # Errors
@@ -97,6 +179,20 @@ impl Clipboard {
 		self.set().text(text)
 	}
 
+	/// Places the text onto the given `selection`.
+	///
+	/// # Errors
+	///
+	/// Like [`set_text`](Self::set_text), and returns [`Error::ClipboardNotSupported`] if the
+	/// selection isn't available on this platform.
+	pub fn set_text_to<'a, T: Into<Cow<'a, str>>>(
+		&mut self,
+		selection: SelectionKind,
+		text: T,
+	) -> Result<(), Error> {
+		self.set().selection(selection).text(text)
+	}
+
 	/// Places the HTML as well as a plain-text alternative onto the clipboard.
 	///
 	/// Any valid UTF-8 string is accepted.
@@ -104,6 +200,10 @@ impl Clipboard {
 	/// # Errors
 	///
 	/// Returns error if both `html` and `alt_text` failed to be stored on the clipboard.
+	///
+	/// On Windows, returns [`Error::ConversionFailure`] if `html` contains a NUL character. Any
+	/// `<!--StartFragment-->` and `<!--EndFragment-->` markers in `html` are removed, because it's
+	/// wrapped into a document with its own markers.
 	pub fn set_html<'a, T: Into<Cow<'a, str>>>(
 		&mut self,
 		html: T,
@@ -112,6 +212,20 @@ impl Clipboard {
 		self.set().html(html, alt_text)
 	}
 
+	/// Fetches an SVG image from the clipboard, as its source text.
+	///
+	/// This is the same as `self.get().svg()`, see [`Get::svg`].
+	pub fn get_svg(&mut self) -> Result<String, Error> {
+		self.get().svg()
+	}
+
+	/// Places an SVG image onto the clipboard, given as its source text.
+	///
+	/// This is the same as `self.set().svg(svg)`, see [`Set::svg`].
+	pub fn set_svg<'a, T: Into<Cow<'a, str>>>(&mut self, svg: T) -> Result<(), Error> {
+		self.set().svg(svg)
+	}
+
 	/// Fetches image data from the clipboard, and returns the decoded pixels.
 	///
 	/// Any image data placed on the clipboard with `set_image` will be possible read back, using
@@ -127,18 +241,35 @@ impl Clipboard {
 		self.get().image()
 	}
 
+	/// Fetches image data from the clipboard if there is any, and returns the decoded pixels.
+	///
+	/// Unlike [`get_image`](Self::get_image), this returns `Ok(None)` instead of
+	/// [`Error::ContentNotAvailable`] or [`Error::WrongFormat`] when the clipboard is empty or
+	/// doesn't contain an image.
+	///
+	/// # Errors
+	///
+	/// Returns error if the contents cannot be converted to an appropriate format and stored in
+	/// the [`ImageData`] type, or the clipboard couldn't be read.
+	#[cfg(feature = "image-data")]
+	pub fn try_get_image(&mut self) -> Result<Option<ImageData<'static>>, Error> {
+		content_if_available(self.get_image())
+	}
+
 	/// Places an image to the clipboard.
 	///
 	/// The chosen output format, depending on the platform is the following:
 	///
 	/// - On macOS: `NSImage` object
 	/// - On Linux: PNG, under the atom `image/png`
-	/// - On Windows: In order of priority `CF_DIB` and `CF_BITMAP`
+	/// - On Windows: In order of priority PNG and `CF_DIBV5`, unless chosen otherwise with
+	///   `SetExtWindows::image_formats`
 	///
 	/// # Errors
 	///
 	/// Returns error if `image` cannot be converted to an appropriate format or if it failed to be
-	/// stored on the clipboard.
+	/// stored on the clipboard. [`Error::InvalidImageDimensions`] is returned if the length of its
+	/// `bytes` doesn't match its width and height.
 	#[cfg(feature = "image-data")]
 	pub fn set_image(&mut self, image: ImageData) -> Result<(), Error> {
 		self.set().image(image)
@@ -154,6 +285,185 @@ impl Clipboard {
 		self.clear_with().default()
 	}
 
+	/// Returns whether the Primary selection (the "middle-click paste" clipboard) is available.
+	///
+	/// - On X11: always `true`.
+	/// - On Wayland: whether the compositor supports the primary selection protocol. This is
+	///   checked once, when the clipboard is created.
+	/// - On Windows and macOS: always `false`.
+	///
+	/// When this returns `false`, using `LinuxClipboardKind::Primary` returns
+	/// [`Error::ClipboardNotSupported`].
+	pub fn primary_selection_supported(&self) -> bool {
+		self.platform.primary_selection_supported()
+	}
+
+	/// Returns which protocol is used to talk to the clipboard.
+	///
+	/// The Wayland data control protocol is only used with the `wayland-data-control` feature,
+	/// when the compositor supports it. Otherwise, X11 is used, which works through XWayland on
+	/// Wayland.
+	#[cfg(all(
+		unix,
+		not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+	))]
+	pub fn backend(&self) -> Backend {
+		self.platform.backend()
+	}
+
+	/// Returns the process id of the application that currently owns the clipboard's contents.
+	///
+	/// - On Windows: the process owning the window passed to `OpenClipboard` when the contents
+	///   were set.
+	/// - On X11: the `_NET_WM_PID` of the selection owner's window, if it was set.
+	/// - On Wayland and macOS: always `None`, as this isn't available.
+	///
+	/// `None` is also returned when the clipboard has no owner, or the owner can't be
+	/// identified.
+	pub fn owner_process_id(&mut self) -> Result<Option<u32>, Error> {
+		self.platform.owner_process_id()
+	}
+
+	/// Returns whether the clipboard holds nothing at all, in any format.
+	///
+	/// - On X11: whether the clipboard has no owner, or an owner which offers no formats in
+	///   reply to `TARGETS`.
+	/// - On Wayland: whether the clipboard has no offer, or an offer without any MIME types.
+	/// - On Windows: whether `CountClipboardFormats` is zero.
+	/// - On macOS: whether the pasteboard has no items.
+	///
+	/// On Linux, the contents of a selection can't be removed, so [`clear`](Self::clear) places
+	/// empty text instead. A clipboard which only offers empty text is considered empty there.
+	///
+	/// This saves checking for each format individually.
+	pub fn is_empty(&mut self) -> Result<bool, Error> {
+		self.platform.is_empty()
+	}
+
+	/// Runs `operations` with the clipboard, keeping it open on Windows until they are done.
+	///
+	/// On Windows, every operation opens and closes the clipboard, which takes a while and lets
+	/// other applications change the clipboard in between. Within a batch, the clipboard is only
+	/// opened once, so that several formats can be read quickly and consistently. Other
+	/// applications can't use the clipboard until the batch ends, so it should be kept short.
+	/// A batch started within another one just runs `operations`, and the clipboard stays open
+	/// until the outer batch ends.
+	/// Each "set" operation still replaces the clipboard's contents.
+	///
+	/// Elsewhere, `operations` are simply run with the clipboard.
+	///
+	/// Returns [`Error::ClipboardOccupied`] if the clipboard couldn't be opened.
+	///
+	/// # Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	/// let (text, png) = clipboard.batch(|clipboard| {
+	///     (clipboard.get_text(), clipboard.get().image_png())
+	/// })?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	pub fn batch<R>(&mut self, operations: impl FnOnce(&mut Clipboard) -> R) -> Result<R, Error> {
+		#[cfg(windows)]
+		let batch = self.platform.begin_batch()?;
+		// The batch has to end even if `operations` panic, so that the clipboard doesn't stay
+		// open.
+		let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| operations(self)));
+		#[cfg(windows)]
+		self.platform.end_batch(batch);
+		Ok(result.unwrap_or_else(|payload| std::panic::resume_unwind(payload)))
+	}
+
+	/// Returns the largest payload, in bytes, that can be handed to other applications in a
+	/// single transfer.
+	///
+	/// - On X11: the largest property the server accepts in one request. Larger payloads are
+	///   sent incrementally, which a few older applications don't support. `None` if the X
+	///   server can't be reached. Like any other operation, this connects to the X server if the
+	///   clipboard wasn't used yet.
+	/// - On Wayland, Windows and macOS: always `None`, as there is no such limit.
+	///
+	/// This can be used to decide whether to downscale an image before setting it.
+	pub fn max_request_bytes(&self) -> Option<usize> {
+		self.platform.max_request_bytes()
+	}
+
+	/// Fetches UTF-8 text from the clipboard in the background.
+	///
+	/// See [`ClipboardFuture`] for how asynchronous operations run, and
+	/// [`get_text`](Self::get_text) for the possible errors.
+	#[cfg(feature = "async")]
+	pub fn get_text_async(&self) -> ClipboardFuture<String> {
+		self.run_async(|clipboard| clipboard.get_text())
+	}
+
+	/// Places the text onto the clipboard in the background.
+	///
+	/// See [`ClipboardFuture`] for how asynchronous operations run.
+	#[cfg(feature = "async")]
+	pub fn set_text_async<T: Into<String>>(&self, text: T) -> ClipboardFuture<()> {
+		let text = text.into();
+		self.run_async(move |clipboard| clipboard.set_text(text))
+	}
+
+	/// Places the HTML as well as a plain-text alternative onto the clipboard in the background.
+	///
+	/// See [`ClipboardFuture`] for how asynchronous operations run, and
+	/// [`set_html`](Self::set_html) for the details.
+	#[cfg(feature = "async")]
+	pub fn set_html_async<T: Into<String>>(
+		&self,
+		html: T,
+		alt_text: Option<T>,
+	) -> ClipboardFuture<()> {
+		let html = html.into();
+		let alt_text = alt_text.map(Into::into);
+		self.run_async(move |clipboard| clipboard.set_html(html, alt_text))
+	}
+
+	/// Fetches image data from the clipboard in the background.
+	///
+	/// See [`ClipboardFuture`] for how asynchronous operations run, and
+	/// [`get_image`](Self::get_image) for the possible errors.
+	#[cfg(all(feature = "async", feature = "image-data"))]
+	pub fn get_image_async(&self) -> ClipboardFuture<ImageData<'static>> {
+		self.run_async(|clipboard| clipboard.get_image())
+	}
+
+	/// Places the image onto the clipboard in the background.
+	///
+	/// See [`ClipboardFuture`] for how asynchronous operations run, and
+	/// [`set_image`](Self::set_image) for the details.
+	#[cfg(all(feature = "async", feature = "image-data"))]
+	pub fn set_image_async(&self, image: ImageData) -> ClipboardFuture<()> {
+		let image =
+			ImageData { width: image.width, height: image.height, bytes: image.into_owned_bytes() };
+		self.run_async(move |clipboard| clipboard.set_image(image))
+	}
+
+	/// Clears the clipboard in the background.
+	///
+	/// See [`ClipboardFuture`] for how asynchronous operations run.
+	#[cfg(feature = "async")]
+	pub fn clear_async(&self) -> ClipboardFuture<()> {
+		self.run_async(|clipboard| clipboard.clear())
+	}
+
+	#[cfg(feature = "async")]
+	fn run_async<T, F>(&self, operation: F) -> ClipboardFuture<T>
+	where
+		T: Send + 'static,
+		F: FnOnce(&mut Clipboard) -> Result<T, Error> + Send + 'static,
+	{
+		let builder = self.builder.clone();
+		ClipboardFuture::spawn(move || {
+			builder.build().and_then(|mut clipboard| operation(&mut clipboard))
+		})
+	}
+
 	/// Begins a "clear" option to remove data from the clipboard.
 	pub fn clear_with(&mut self) -> Clear<'_> {
 		Clear { platform: platform::Clear::new(&mut self.platform) }
@@ -161,7 +471,14 @@ impl Clipboard {
 
 	/// Begins a "get" operation to retrieve data from the clipboard.
 	pub fn get(&mut self) -> Get<'_> {
-		Get { platform: platform::Get::new(&mut self.platform) }
+		Get {
+			platform: platform::Get::new(&mut self.platform),
+			keep_bom: false,
+			#[cfg(feature = "image-data")]
+			alpha_mode: AlphaMode::default(),
+			#[cfg(feature = "image-data")]
+			image_cache: self.image_cache.as_mut(),
+		}
 	}
 
 	/// Begins a "set" operation to set the clipboard's contents.
@@ -170,16 +487,367 @@ impl Clipboard {
 	}
 }
 
+/// Maps [`Error::ContentNotAvailable`] and [`Error::WrongFormat`] to `Ok(None)`, keeping all
+/// other errors.
+fn content_if_available<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+	match result {
+		Ok(content) => Ok(Some(content)),
+		Err(Error::ContentNotAvailable | Error::WrongFormat) => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// A builder for creating a [`Clipboard`] with non-default settings.
+///
+/// Most of the options only apply to a single platform or backend. Options which don't apply
+/// to the current one are accepted and ignored, so the same configuration can be used on every
+/// platform.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), arboard::Error> {
+/// let mut clipboard = arboard::Clipboard::builder()
+///     .x11_read_timeout(Duration::from_secs(1))
+///     .windows_open_attempts(10)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ClipboardBuilder {
+	config: common::ClipboardConfig,
+	#[cfg(feature = "image-data")]
+	cache_images: bool,
+}
+
+impl ClipboardBuilder {
+	/// Creates a builder with the default settings, which are the same that [`Clipboard::new`]
+	/// uses.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the X11 display to connect to, instead of the one named by the `DISPLAY`
+	/// environment variable.
+	///
+	/// The X11 connection is shared by all `Clipboard`s in the process, so this only takes effect
+	/// if no other `Clipboard` currently exists.
+	///
+	/// *Only used by the X11 backend on Linux.*
+	pub fn x11_display<T: Into<String>>(mut self, display: T) -> Self {
+		self.config.x11_display = Some(display.into());
+		self
+	}
+
+	/// Sets the screen of the X11 display to create the window which owns the clipboard's
+	/// contents on, instead of the default screen of the display.
+	///
+	/// This is only needed on displays with several screens, like some remote X and multi-seat
+	/// setups, not for multiple monitors. Like [`x11_display`](Self::x11_display), this only
+	/// takes effect if no other `Clipboard` currently exists.
+	///
+	/// *Only used by the X11 backend on Linux.*
+	pub fn x11_screen(mut self, screen: usize) -> Self {
+		self.config.x11_screen = Some(screen);
+		self
+	}
+
+	/// Sets how long to wait for the owner of the clipboard to respond when reading from it.
+	///
+	/// The default is 4 seconds, which leaves enough time for applications that are slow to
+	/// produce large images.
+	///
+	/// *Only used by the X11 backend on Linux.*
+	pub fn x11_read_timeout(mut self, timeout: Duration) -> Self {
+		self.config.x11_read_timeout = Some(timeout);
+		self
+	}
+
+	/// Sets whether the clipboard's contents are handed over to the clipboard manager when the
+	/// last `Clipboard` of the process is dropped, so that they stay available after the
+	/// process exits.
+	///
+	/// The handover is enabled by default and can delay dropping the `Clipboard` by up to 100ms.
+	/// Applications which serve their contents until they're replaced, through
+	/// [`SetExtLinux::wait`], or which don't need the contents to outlive them can disable it to
+	/// shut down faster.
+	///
+	/// Note that the setting of whichever `Clipboard` is dropped last applies.
+	///
+	/// *Only used by the X11 backend on Linux.*
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn x11_clipboard_manager_handover(mut self, enabled: bool) -> Self {
+		self.config.x11_skip_handover = !enabled;
+		self
+	}
+
+	/// Sets how long to wait for the clipboard manager to take over the clipboard's contents when
+	/// the last `Clipboard` of the process is dropped.
+	///
+	/// The default is 100ms. Increasing it makes the contents more likely to survive the process
+	/// exiting when the clipboard manager is slow to respond, like on heavily loaded systems, but
+	/// can delay dropping the `Clipboard` by that much longer.
+	///
+	/// Note that the setting of whichever `Clipboard` is dropped last applies.
+	///
+	/// *Only used by the X11 backend on Linux.*
+	pub fn x11_handover_timeout(mut self, timeout: Duration) -> Self {
+		self.config.x11_handover_timeout = Some(timeout);
+		self
+	}
+
+	/// Sets the name (`_NET_WM_NAME`) of the hidden window that owns the clipboard's contents,
+	/// which debugging tools like `xprop` and window managers show.
+	///
+	/// By default, the window is named `arboard (<executable name>)`. Its `WM_CLASS` is always
+	/// the executable name and `arboard`.
+	///
+	/// Like [`x11_display`](Self::x11_display), this only takes effect if no other `Clipboard`
+	/// currently exists.
+	///
+	/// *Only used by the X11 backend on Linux.*
+	pub fn x11_window_name<T: Into<String>>(mut self, name: T) -> Self {
+		self.config.x11_window_name = Some(name.into());
+		self
+	}
+
+	/// Sets the name of the seat to use, instead of letting the compositor pick one.
+	///
+	/// *Only used by the Wayland backend on Linux, which requires the `wayland-data-control`
+	/// feature.*
+	pub fn wayland_seat<T: Into<String>>(mut self, seat: T) -> Self {
+		self.config.wayland_seat = Some(seat.into());
+		self
+	}
+
+	/// Sets how long to wait for a clipboard manager to take over the clipboard's contents when
+	/// the last `Clipboard` of the process is dropped, after setting them without
+	/// [`SetExtLinux::wait`].
+	///
+	/// The default is 100ms, which is spent in full when no clipboard manager takes the contents
+	/// over. A zero timeout skips the wait, for applications which don't need the contents to
+	/// outlive them.
+	///
+	/// Note that the setting of whichever `Clipboard` is dropped last applies.
+	///
+	/// *Only used by the Wayland backend on Linux, which requires the `wayland-data-control`
+	/// feature.*
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn wayland_flush_timeout(mut self, timeout: Duration) -> Self {
+		self.config.wayland_flush_timeout = Some(timeout);
+		self
+	}
+
+	/// Sets whether to use X11, through XWayland, even when the Wayland data control protocol is
+	/// available.
+	///
+	/// This works around compositors whose data control implementation misbehaves, without
+	/// having to unset `WAYLAND_DISPLAY`. Setting the `ARBOARD_FORCE_X11` environment variable,
+	/// to any value, does the same without changing the application.
+	///
+	/// *Only used on Linux with the `wayland-data-control` feature, as X11 is always used
+	/// without it.*
+	pub fn force_x11(mut self, enabled: bool) -> Self {
+		self.config.force_x11 = enabled;
+		self
+	}
+
+	/// Sets how many times to retry opening the clipboard when another application is holding
+	/// it open. There is a short delay between each attempt.
+	///
+	/// The default is 5 attempts.
+	///
+	/// *Only used on Windows.*
+	pub fn windows_open_attempts(mut self, attempts: usize) -> Self {
+		self.config.windows_open_attempts = Some(attempts);
+		self
+	}
+
+	/// Sets the name of the pasteboard to use instead of the general pasteboard. The
+	/// pasteboard is created if it doesn't exist yet.
+	///
+	/// *Only used on macOS.*
+	pub fn macos_pasteboard_name<T: Into<String>>(mut self, name: T) -> Self {
+		self.config.macos_pasteboard_name = Some(name.into());
+		self
+	}
+
+	/// Sets whether the last image which was read from the clipboard is kept, so that reading
+	/// it again doesn't decode it again as long as the clipboard's contents weren't replaced.
+	///
+	/// This speeds up applications which read the same image several times in a row, like for
+	/// a preview and then for pasting it. The cost is that a copy of the decoded image stays in
+	/// memory for as long as the `Clipboard` exists, which is 4 bytes per pixel.
+	///
+	/// Whether the contents were replaced is told by the clipboard's sequence number on Windows,
+	/// the pasteboard's change count on macOS and the selection owner with the time it took
+	/// ownership on X11. Images are always decoded again on Wayland, where this can't be told,
+	/// and when reading any selection but the regular clipboard on Linux.
+	///
+	/// A cached image is only returned to reads with the same options which affect decoding, like
+	/// [`Get::max_bytes`].
+	///
+	/// The cache is disabled by default.
+	#[cfg(feature = "image-data")]
+	pub fn cache_images(mut self, enabled: bool) -> Self {
+		self.cache_images = enabled;
+		self
+	}
+
+	/// Creates the clipboard with the configured settings.
+	///
+	/// # Errors
+	///
+	/// On some platforms or desktop environments, an error can be returned if clipboards are not
+	/// supported. This may be retried.
+	pub fn build(self) -> Result<Clipboard, Error> {
+		Ok(Clipboard {
+			platform: platform::Clipboard::new(self.config.clone())?,
+			#[cfg(feature = "image-data")]
+			image_cache: self.cache_images.then(common::ImageCache::default),
+			#[cfg(feature = "async")]
+			builder: self,
+		})
+	}
+}
+
 /// A builder for an operation that gets a value from the clipboard.
 #[must_use]
 pub struct Get<'clipboard> {
 	pub(crate) platform: platform::Get<'clipboard>,
+	keep_bom: bool,
+	#[cfg(feature = "image-data")]
+	alpha_mode: AlphaMode,
+	#[cfg(feature = "image-data")]
+	image_cache: Option<&'clipboard mut common::ImageCache>,
 }
 
 impl Get<'_> {
+	/// Selects the clipboard to read from.
+	///
+	/// On Linux, this is the same as [`GetExtLinux::clipboard`]. Elsewhere, reading from any
+	/// selection but [`SelectionKind::Clipboard`] returns [`Error::ClipboardNotSupported`].
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn selection(mut self, selection: SelectionKind) -> Self {
+		self.platform = self.platform.selection(selection);
+		self
+	}
+
+	/// Limits how much data the operation may read from the clipboard. Data which is larger than
+	/// `max_bytes` is rejected with [`Error::TooLarge`], before it's read in full where the
+	/// platform allows it.
+	///
+	/// The size is that of the data as the clipboard holds it, before it's decoded. So on Windows,
+	/// text is measured in UTF-16, and images are measured in their encoded form everywhere.
+	///
+	/// - On X11: enforced while the data is received, including incremental transfers.
+	/// - On Wayland: enforced while reading from the owner's pipe.
+	/// - On Windows: checked before the data is copied out of the clipboard.
+	/// - On macOS: checked before the data is copied or decoded.
+	///
+	/// By default, there is no limit.
+	pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+		self.platform = self.platform.max_bytes(max_bytes);
+		self
+	}
+
+	/// Sets how the alpha channel is applied to the colors of images read by
+	/// [`image`](Self::image) and [`image_with_dpi`](Self::image_with_dpi).
+	///
+	/// The default is [`AlphaMode::Straight`] on every platform.
+	#[cfg(feature = "image-data")]
+	pub fn alpha_mode(mut self, mode: AlphaMode) -> Self {
+		self.alpha_mode = mode;
+		self
+	}
+
+	/// Keeps a byte order mark (`U+FEFF`) at the start of the text read by [`text`](Self::text)
+	/// and [`any`](Self::any).
+	///
+	/// By default, it's removed, because some applications, mostly on Windows, prepend it to
+	/// copied text and it's almost never wanted by whoever pastes it. A byte order mark anywhere
+	/// else in the text is always kept.
+	pub fn keep_bom(mut self) -> Self {
+		self.keep_bom = true;
+		self
+	}
+
 	/// Completes the "get" operation by fetching UTF-8 text from the clipboard.
+	///
+	/// A leading byte order mark is removed, unless [`keep_bom`](Self::keep_bom) was called.
+	///
+	/// On X11, text which this process placed onto the clipboard itself is copied straight from
+	/// memory, without a round-trip through the X server. For UTF-8 text without a byte order mark
+	/// the copy is the only allocation, as the returned `String` reuses its buffer. Latin-1,
+	/// compound text and UTF-16 contents are converted into a new `String`.
 	pub fn text(self) -> Result<String, Error> {
-		self.platform.text()
+		let keep_bom = self.keep_bom;
+		let text = || self.platform.text().map(|text| common::strip_bom(text, keep_bom));
+		common::traced("get", "text", text, String::len)
+	}
+
+	/// Completes the "get" operation by fetching the first `max_chars` characters of the text on
+	/// the clipboard, for showing a preview of text which might be large.
+	///
+	/// Characters are counted as `char`s. Where possible, the rest of the text isn't transferred:
+	///
+	/// - On X11: the reading stops once enough bytes arrived, which abandons the remaining INCR
+	///   segments of a large transfer.
+	/// - On Wayland: the pipe is closed once enough bytes were read.
+	/// - On Windows: only the start of the text is copied and converted from UTF-16.
+	/// - On macOS: the whole text is read, since the pasteboard hands it out at once.
+	///
+	/// The limit set with [`max_bytes`](Self::max_bytes) doesn't apply, except on macOS. A
+	/// leading byte order mark is removed like [`text`](Self::text) does.
+	pub fn text_preview(self, max_chars: usize) -> Result<String, Error> {
+		let keep_bom = self.keep_bom;
+		let preview = || {
+			let mut text = common::strip_bom(self.platform.text_preview(max_chars)?, keep_bom);
+			if let Some((end, _)) = text.char_indices().nth(max_chars) {
+				text.truncate(end);
+			}
+			Ok(text)
+		};
+		common::traced("get", "text", preview, String::len)
+	}
+
+	/// Completes the "get" operation by fetching the clipboard's contents in whichever
+	/// representation is available, for paste handlers that accept several kinds of content.
+	///
+	/// Text is preferred, because applications commonly offer an image rendition along with
+	/// copied text, like spreadsheets do for cells. Images are only read if there's no text and
+	/// the `image-data` feature is enabled.
+	pub fn any(mut self) -> Result<ClipboardContent, Error> {
+		let get = || {
+			let text = self.platform.reborrow().text();
+			match text {
+				Ok(text) => Ok(ClipboardContent::Text(common::strip_bom(text, self.keep_bom))),
+				#[cfg(feature = "image-data")]
+				Err(Error::ContentNotAvailable | Error::WrongFormat) => {
+					self.image().map(ClipboardContent::Image)
+				}
+				Err(e) => Err(e),
+			}
+		};
+		let len = |content: &ClipboardContent| match content {
+			ClipboardContent::Text(text) => text.len(),
+			#[cfg(feature = "image-data")]
+			ClipboardContent::Image(image) => image.bytes.len(),
+		};
+		common::traced("get", "any", get, len)
+	}
+
+	/// Completes the "get" operation by fetching an SVG image from the clipboard, as its source
+	/// text.
+	///
+	/// SVG images aren't read by [`text`](Self::text), and can't be rasterized by
+	/// [`image`](Self::image). This reads `image/svg+xml` on Linux and Windows, where it's the
+	/// name of a registered format, and `public.svg-image` on macOS.
+	pub fn svg(self) -> Result<String, Error> {
+		common::traced("get", "image/svg+xml", || self.platform.svg(), String::len)
 	}
 
 	/// Completes the "get" operation by fetching image data from the clipboard and returning the
@@ -187,10 +855,87 @@ impl Get<'_> {
 	///
 	/// Any image data placed on the clipboard with `set_image` will be possible read back, using
 	/// this function. However it's of not guaranteed that an image placed on the clipboard by any
-	/// other application will be of a supported format.
+	/// other application will be of a supported format. On Linux, PNG images are preferred, but
+	/// `image/bmp`, `image/x-bmp` and `image/gif` are read as well. On Windows and macOS, a GIF file
+	/// is read when the clipboard holds no bitmap or TIFF image.
+	///
+	/// The pixels have straight alpha, unless chosen otherwise with
+	/// [`alpha_mode`](Self::alpha_mode). Images which the platform provides premultiplied, like
+	/// TIFFs with associated alpha on macOS, are converted.
+	///
+	/// Images with several frames, like animated PNGs and GIFs or multi-page TIFFs, are read as
+	/// their first frame.
 	#[cfg(feature = "image-data")]
 	pub fn image(self) -> Result<ImageData<'static>, Error> {
-		self.platform.image()
+		let alpha_mode = self.alpha_mode;
+		let get = || {
+			let mut image = match self.image_cache {
+				Some(cache) => cache.image(self.platform)?.image,
+				None => self.platform.image()?,
+			};
+			debug_assert!(
+				image.check_dimensions().is_ok(),
+				"the platform returned a malformed image"
+			);
+			if alpha_mode == AlphaMode::Premultiplied {
+				image.premultiply_alpha();
+			}
+			Ok(image)
+		};
+		common::traced("get", "image", get, |image| image.bytes.len())
+	}
+
+	/// Completes the "get" operation by fetching a PNG file from the clipboard, without decoding
+	/// it.
+	///
+	/// This reads `image/png` on Linux, `public.png` on macOS and the registered `PNG` format on
+	/// Windows. It's available without the `image-data` feature, for applications that only move
+	/// already encoded images around.
+	pub fn image_png(self) -> Result<Vec<u8>, Error> {
+		common::traced("get", "image/png", || self.platform.image_png(), Vec::len)
+	}
+
+	/// Completes the "get" operation by fetching an image from the clipboard exactly as it was
+	/// provided, along with the name of its format.
+	///
+	/// Unlike [`image`](Self::image), this keeps the compression and metadata of the original
+	/// file. The formats are tried in the following order:
+	///
+	/// - On Linux: `image/png`, `image/bmp`, `image/x-bmp` and `image/gif`, returned as their MIME
+	///   type.
+	/// - On macOS: `public.png` and `public.tiff`, returned as `image/png` and `image/tiff`.
+	/// - On Windows: the registered `PNG` format, returned as `image/png`, and `CF_DIBV5`,
+	///   returned as `CF_DIBV5`.
+	///
+	/// This is available without the `image-data` feature.
+	pub fn image_raw(self) -> Result<(Vec<u8>, String), Error> {
+		common::traced("get", "image raw", || self.platform.image_raw(), |(bytes, _)| bytes.len())
+	}
+
+	/// Completes the "get" operation like [`image`](Self::image), but also returns the image's
+	/// resolution when the clipboard data specifies it.
+	///
+	/// - On Windows: read from the `CF_DIBV5` header.
+	/// - On Linux: read from the `pHYs` chunk of the PNG.
+	/// - On macOS: not available, so always `None`.
+	#[cfg(feature = "image-data")]
+	pub fn image_with_dpi(self) -> Result<ImageDataWithMeta, Error> {
+		let alpha_mode = self.alpha_mode;
+		let get = || {
+			let mut image = match self.image_cache {
+				Some(cache) => cache.image(self.platform)?,
+				None => self.platform.image_with_dpi()?,
+			};
+			debug_assert!(
+				image.image.check_dimensions().is_ok(),
+				"the platform returned a malformed image"
+			);
+			if alpha_mode == AlphaMode::Premultiplied {
+				image.image.premultiply_alpha();
+			}
+			Ok(image)
+		};
+		common::traced("get", "image", get, |image| image.image.bytes.len())
 	}
 }
 
@@ -201,11 +946,80 @@ pub struct Set<'clipboard> {
 }
 
 impl Set<'_> {
+	/// Keeps the clipboard's contents available after setting them, for as long as they aren't
+	/// replaced.
+	///
+	/// - On Linux: this is [`SetExtLinux::wait`], which blocks the operation until another
+	///   application takes over the clipboard.
+	/// - On Windows and macOS: the system keeps the clipboard's contents after the program exits,
+	///   so this does nothing.
+	///
+	/// This allows code which just wants to "copy and keep it available" to be written the same
+	/// way for all platforms.
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn wait(self) -> Self {
+		#[cfg(all(
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+		))]
+		{
+			SetExtLinux::wait(self)
+		}
+
+		#[cfg(not(all(
+			unix,
+			not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+		)))]
+		{
+			self
+		}
+	}
+
+	/// Selects the clipboard to write to.
+	///
+	/// On Linux, this is the same as [`SetExtLinux::clipboard`]. Elsewhere, writing to any
+	/// selection but [`SelectionKind::Clipboard`] returns [`Error::ClipboardNotSupported`].
+	#[allow(rustdoc::broken_intra_doc_links)]
+	pub fn selection(self, selection: SelectionKind) -> Self {
+		Self { platform: self.platform.selection(selection) }
+	}
+
 	/// Completes the "set" operation by placing text onto the clipboard. Any valid UTF-8 string
 	/// is accepted.
 	pub fn text<'a, T: Into<Cow<'a, str>>>(self, text: T) -> Result<(), Error> {
 		let text = text.into();
-		self.platform.text(text)
+		let len = text.len();
+		common::traced("set", "text", || self.platform.text(text), |_| len)
+	}
+
+	/// Completes the "set" operation by placing text, which is given as UTF-8 encoded bytes, onto
+	/// the clipboard.
+	///
+	/// This saves the caller from converting bytes which are already UTF-8 into a `String`. Like
+	/// with [`text`](Self::text), the platform may still copy the text, as Linux does to serve it
+	/// to other applications. Returns [`Error::ConversionFailure`] if the bytes aren't valid UTF-8.
+	pub fn utf8_bytes(self, bytes: &[u8]) -> Result<(), Error> {
+		let text = std::str::from_utf8(bytes).map_err(|_| Error::ConversionFailure)?;
+		common::traced("set", "text", || self.platform.text(Cow::Borrowed(text)), |_| text.len())
+	}
+
+	/// Completes the "set" operation by adding text to the clipboard, while keeping the formats
+	/// that are already on it, for example to offer a description alongside an image.
+	///
+	/// Existing text is replaced in every text format, not just the one the new text is offered
+	/// in. How the other formats are kept depends on the platform:
+	///
+	/// - On Windows: the text is added to the clipboard as is, along with the `CF_TEXT` and
+	///   `CF_OEMTEXT` conversions of it.
+	/// - On macOS: the pasteboard items are copied, with the text added to the first one.
+	/// - On X11: only the formats which this process placed onto the clipboard are kept, the
+	///   ones offered by another application are dropped.
+	/// - On Wayland: all formats are read from the current owner and offered again, which can
+	///   be slow for large contents.
+	pub fn add_text_keeping_others<'a, T: Into<Cow<'a, str>>>(self, text: T) -> Result<(), Error> {
+		let text = text.into();
+		let len = text.len();
+		common::traced("set", "text", || self.platform.text_keeping_others(text), |_| len)
 	}
 
 	/// Completes the "set" operation by placing HTML as well as a plain-text alternative onto the
@@ -219,7 +1033,8 @@ impl Set<'_> {
 	) -> Result<(), Error> {
 		let html = html.into();
 		let alt_text = alt_text.map(|e| e.into());
-		self.platform.html(html, alt_text)
+		let len = html.len() + alt_text.as_ref().map_or(0, |alt| alt.len());
+		common::traced("set", "html", || self.platform.html(html, alt_text), |_| len)
 	}
 
 	/// Completes the "set" operation by placing an image onto the clipboard.
@@ -228,10 +1043,110 @@ impl Set<'_> {
 	///
 	/// - On macOS: `NSImage` object
 	/// - On Linux: PNG, under the atom `image/png`
-	/// - On Windows: In order of priority `CF_DIB` and `CF_BITMAP`
+	/// - On Windows: In order of priority PNG and `CF_DIBV5`, unless chosen otherwise with
+	///   `SetExtWindows::image_formats`
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if `image.bytes` isn't `width * height * 4`
+	/// bytes long.
 	#[cfg(feature = "image-data")]
 	pub fn image(self, image: ImageData) -> Result<(), Error> {
-		self.platform.image(image)
+		image.check_dimensions()?;
+		let len = image.bytes.len();
+		common::traced("set", "image", || self.platform.image(image), |_| len)
+	}
+
+	/// Completes the "set" operation by placing an image onto the clipboard, tagged with the
+	/// `icc` color profile that its pixels are in.
+	///
+	/// The profile is embedded as the `iCCP` chunk of the PNG files and, on Windows, also into
+	/// the `CF_DIBV5` bitmap. Other Windows bitmap formats and macOS drop the profile.
+	#[cfg(feature = "image-data")]
+	pub fn image_with_profile(self, image: ImageData, icc: &[u8]) -> Result<(), Error> {
+		image.check_dimensions()?;
+		let len = image.bytes.len();
+		common::traced(
+			"set",
+			"image",
+			|| self.platform.image_with_profile(image, Some(icc)),
+			|_| len,
+		)
+	}
+
+	/// Completes the "set" operation by placing an image onto the clipboard along with a smaller
+	/// `thumbnail` of it, for applications which show a preview of large images.
+	///
+	/// `image` is placed like [`image`](Self::image) places it, so any application can paste
+	/// it. `thumbnail` is added as a PNG file under a format of its own, which is only read by
+	/// applications that know its name:
+	///
+	/// - On Linux: `image/x-arboard-thumbnail`
+	/// - On Windows: the registered `image/x-arboard-thumbnail` format
+	/// - On macOS: `com.1password.arboard.thumbnail`, on the same item as the image
+	///
+	/// The thumbnail is placed as it is, without checking that it's smaller than `image`.
+	///
+	/// Returns [`Error::InvalidImageDimensions`] if the bytes of either image don't match its
+	/// dimensions.
+	#[cfg(feature = "image-data")]
+	pub fn image_with_thumbnail(self, image: ImageData, thumbnail: ImageData) -> Result<(), Error> {
+		image.check_dimensions()?;
+		thumbnail.check_dimensions()?;
+		let len = image.bytes.len() + thumbnail.bytes.len();
+		common::traced(
+			"set",
+			"image",
+			|| self.platform.image_with_thumbnail(image, thumbnail),
+			|_| len,
+		)
+	}
+
+	/// Completes the "set" operation by placing an already encoded PNG file onto the clipboard.
+	///
+	/// The bytes are placed as they are, under `image/png` on Linux, `public.png` on macOS and
+	/// the registered `PNG` format on Windows. They aren't checked to be a valid PNG file. This
+	/// is available without the `image-data` feature.
+	pub fn image_png<'a, T: Into<Cow<'a, [u8]>>>(self, png: T) -> Result<(), Error> {
+		let png = png.into();
+		let len = png.len();
+		common::traced("set", "image/png", || self.platform.image_png(png), |_| len)
+	}
+
+	/// Completes the "set" operation by placing an SVG image, given as its source text, onto the
+	/// clipboard.
+	///
+	/// The text is placed as it is, under the formats which [`Get::svg`] reads. It isn't checked
+	/// to be a valid SVG image.
+	pub fn svg<'a, T: Into<Cow<'a, str>>>(self, svg: T) -> Result<(), Error> {
+		let svg = svg.into();
+		let len = svg.len();
+		common::traced("set", "image/svg+xml", || self.platform.svg(svg), |_| len)
+	}
+
+	/// Completes the "set" operation by announcing data in the `mime` format, without producing
+	/// it yet.
+	///
+	/// `producer` is called once the data is first asked for, and its result is kept to answer
+	/// any further requests. If the data is replaced before anyone asks for it, `producer` is
+	/// never called.
+	///
+	/// When another application asks for the data, `producer` runs on the thread which serves
+	/// the clipboard's contents. When this process reads the data itself, it runs on the reading
+	/// thread instead. Either way, the clipboard's contents are locked while it runs, so it must
+	/// not use the clipboard itself, which would deadlock.
+	///
+	/// This is currently only supported on Linux with X11, where the data is always served on
+	/// demand. Everywhere else, [`Error::ClipboardNotSupported`] is returned:
+	///
+	/// - Wayland's data control protocol is served by `wl-clipboard-rs`, which needs the data up
+	///   front.
+	/// - Delayed rendering on Windows needs a window that answers `WM_RENDERFORMAT`, which
+	///   requires a message loop that arboard doesn't run.
+	/// - On macOS, this would need an `NSPasteboardItemDataProvider`.
+	pub fn lazy<F>(self, mime: &str, producer: F) -> Result<(), Error>
+	where
+		F: FnOnce() -> Vec<u8> + Send + 'static,
+	{
+		self.platform.lazy(mime, Box::new(producer))
 	}
 }
 
@@ -245,7 +1160,7 @@ impl Clear<'_> {
 	/// Completes the "clear" operation by deleting any existing clipboard data,
 	/// regardless of the format.
 	pub fn default(self) -> Result<(), Error> {
-		self.platform.clear()
+		common::traced("clear", "all", || self.platform.clear(), |_| 0)
 	}
 }
 
@@ -265,6 +1180,21 @@ mod tests {
 			ctx.set_text(text).unwrap();
 			assert_eq!(ctx.get_text().unwrap(), text);
 
+			ctx.set().utf8_bytes("some bytes: ✓".as_bytes()).unwrap();
+			assert_eq!(ctx.get_text().unwrap(), "some bytes: ✓");
+			assert_eq!(ctx.get().max_bytes(64).text().unwrap(), "some bytes: ✓");
+			assert!(matches!(ctx.get().max_bytes(4).text(), Err(Error::TooLarge)));
+			assert!(matches!(ctx.set().utf8_bytes(&[b'a', 0xFF]), Err(Error::ConversionFailure)));
+
+			ctx.set_text("\u{feff}with a BOM").unwrap();
+			assert_eq!(ctx.get_text().unwrap(), "with a BOM");
+			assert_eq!(ctx.get().keep_bom().text().unwrap(), "\u{feff}with a BOM");
+
+			ctx.set_text("prévisualisation").unwrap();
+			assert_eq!(ctx.get().text_preview(3).unwrap(), "pré");
+			assert_eq!(ctx.get().text_preview(100).unwrap(), "prévisualisation");
+			ctx.set_text(text).unwrap();
+
 			// We also need to check that the content persists after the drop; this is
 			// especially important on X11
 			drop(ctx);
@@ -283,13 +1213,40 @@ mod tests {
 			ctx.set_text(text).unwrap();
 			assert_eq!(ctx.get_text().unwrap(), text);
 		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			let text = "explicit selection";
+			ctx.set_text_to(SelectionKind::Clipboard, text).unwrap();
+			assert_eq!(ctx.get_text_from(SelectionKind::Clipboard).unwrap(), text);
+
+			#[cfg(any(windows, target_os = "macos"))]
+			assert!(matches!(
+				ctx.get_text_from(SelectionKind::Primary),
+				Err(Error::ClipboardNotSupported)
+			));
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+			let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><circle r="1"/></svg>"#;
+			ctx.set_svg(svg).unwrap();
+			assert_eq!(ctx.get_svg().unwrap(), svg);
+			assert!(matches!(ctx.get_text(), Err(Error::WrongFormat)));
+		}
 		{
 			let mut ctx = Clipboard::new().unwrap();
 			let text = "hello world";
 
 			ctx.set_text(text).unwrap();
 			assert_eq!(ctx.get_text().unwrap(), text);
+			assert!(!ctx.is_empty().unwrap());
+
+			ctx.clear().unwrap();
+			// Linux clears the clipboard by placing empty text onto it, which counts as empty.
+			assert!(ctx.is_empty().unwrap());
 
+			// Elsewhere, empty text is still a format on the clipboard.
+			ctx.set_text("").unwrap();
+			assert_eq!(ctx.is_empty().unwrap(), cfg!(all(unix, not(target_os = "macos"))));
 			ctx.clear().unwrap();
 
 			match ctx.get_text() {
@@ -300,6 +1257,25 @@ mod tests {
 
 			// confirm it is OK to clear when already empty.
 			ctx.clear().unwrap();
+
+			let got = ctx
+				.batch(|ctx| {
+					ctx.set_text(text)?;
+					Ok::<_, Error>((ctx.get_text()?, ctx.get_text()?))
+				})
+				.unwrap()
+				.unwrap();
+			assert_eq!(got, (text.to_owned(), text.to_owned()));
+
+			// The inner batch leaves the clipboard open for the rest of the outer one.
+			let got = ctx
+				.batch(|ctx| {
+					ctx.batch(|ctx| ctx.set_text(text))??;
+					ctx.get_text()
+				})
+				.unwrap()
+				.unwrap();
+			assert_eq!(got, text);
 		}
 		{
 			let mut ctx = Clipboard::new().unwrap();
@@ -307,11 +1283,9 @@ mod tests {
 
 			ctx.set_html(html, None).unwrap();
 
-			match ctx.get_text() {
-				Ok(text) => assert!(text.is_empty()),
-				Err(Error::ContentNotAvailable) => {}
-				Err(e) => panic!("unexpected error: {e}"),
-			};
+			if let Some(text) = ctx.try_get_text().unwrap() {
+				assert!(text.is_empty());
+			}
 		}
 		{
 			let mut ctx = Clipboard::new().unwrap();
@@ -322,6 +1296,93 @@ mod tests {
 			ctx.set_html(html, Some(alt_text)).unwrap();
 			assert_eq!(ctx.get_text().unwrap(), alt_text);
 		}
+		#[cfg(target_os = "macos")]
+		{
+			use crate::{GetExtApple, SetExtApple};
+
+			let mut ctx = Clipboard::new().unwrap();
+
+			// Adding HTML keeps the plain text that's already on the pasteboard.
+			ctx.set_text("hello world!").unwrap();
+			ctx.set().html_keep_text("<b>hello</b> world!").unwrap();
+			assert_eq!(ctx.get_text().unwrap(), "hello world!");
+
+			let types = ctx.get().available_types().unwrap();
+			for ty in ["public.utf8-plain-text", "public.html"] {
+				assert!(types.iter().any(|name| name == ty), "{ty} is missing");
+			}
+
+			// The same holds for text which another application placed onto the pasteboard.
+			let mut pbcopy = std::process::Command::new("pbcopy")
+				.stdin(std::process::Stdio::piped())
+				.spawn()
+				.unwrap();
+			std::io::Write::write_all(&mut pbcopy.stdin.take().unwrap(), b"from pbcopy").unwrap();
+			assert!(pbcopy.wait().unwrap().success());
+			ctx.set().html_keep_text("<b>from</b> pbcopy").unwrap();
+			assert_eq!(ctx.get_text().unwrap(), "from pbcopy");
+			let html = ctx.get().data_for_type("public.html").unwrap();
+			assert!(String::from_utf8(html).unwrap().contains("<b>from</b> pbcopy"));
+
+			// And for text in more than one pasteboard item.
+			{
+				use objc2::runtime::ProtocolObject;
+				use objc2_app_kit::{NSPasteboard, NSPasteboardItem, NSPasteboardTypeString};
+				use objc2_foundation::{NSArray, NSString};
+
+				let pasteboard = unsafe { NSPasteboard::generalPasteboard() };
+				let items: Vec<_> = ["first item", "second item"]
+					.into_iter()
+					.map(|text| {
+						let item = unsafe { NSPasteboardItem::new() };
+						let text = NSString::from_str(text);
+						assert!(unsafe { item.setString_forType(&text, NSPasteboardTypeString) });
+						item
+					})
+					.map(ProtocolObject::from_id)
+					.collect();
+				unsafe { pasteboard.clearContents() };
+				assert!(unsafe { pasteboard.writeObjects(&NSArray::from_vec(items)) });
+			}
+			ctx.set().html_keep_text("<b>first</b> item").unwrap();
+			assert_eq!(ctx.get_text().unwrap(), "first item");
+			let html = ctx.get().data_for_type("public.html").unwrap();
+			assert!(String::from_utf8(html).unwrap().contains("<b>first</b> item"));
+
+			const CSV: &[u8] = b"a,b\n1,2\n";
+			ctx.set().data_for_type("public.comma-separated-values-text", CSV).unwrap();
+			let got = ctx.get().data_for_type("public.comma-separated-values-text").unwrap();
+			assert_eq!(got, CSV);
+			assert!(matches!(
+				ctx.get().data_for_type("com.example.missing"),
+				Err(Error::WrongFormat)
+			));
+
+			ctx.set().text_as_type("text/plain", "hello linux").unwrap();
+			assert_eq!(ctx.get().data_for_type("text/plain").unwrap(), b"hello linux");
+		}
+		#[cfg(windows)]
+		{
+			use crate::{GetExtWindows, SetExtWindows};
+
+			let mut ctx = Clipboard::new().unwrap();
+
+			// German (Germany), for the code page which legacy applications convert the text to.
+			const LCID: u32 = 0x0407;
+			ctx.set().locale(LCID).text("Grüße").unwrap();
+			assert_eq!(ctx.get().locale().unwrap(), LCID);
+			assert_eq!(ctx.get_text().unwrap(), "Grüße");
+		}
+		{
+			let mut ctx = Clipboard::new().unwrap();
+
+			// Encoded images are passed through without being decoded.
+			let png = b"\x89PNG\r\n\x1a\nnot really an image";
+			ctx.set().image_png(png.as_ref()).unwrap();
+			assert_eq!(ctx.get().image_png().unwrap(), png);
+			let (raw, format) = ctx.get().image_raw().unwrap();
+			assert_eq!((raw.as_slice(), format.as_str()), (png.as_ref(), "image/png"));
+		}
 		#[cfg(feature = "image-data")]
 		{
 			let mut ctx = Clipboard::new().unwrap();
@@ -336,16 +1397,82 @@ mod tests {
 
 			// Make sure that setting one format overwrites the other.
 			ctx.set_image(img_data.clone()).unwrap();
-			assert!(matches!(ctx.get_text(), Err(Error::ContentNotAvailable)));
+			assert!(matches!(ctx.get_text(), Err(Error::WrongFormat)));
 
 			ctx.set_text("clipboard test").unwrap();
-			assert!(matches!(ctx.get_image(), Err(Error::ContentNotAvailable)));
+			assert!(matches!(ctx.get_image(), Err(Error::WrongFormat)));
+			assert!(ctx.try_get_image().unwrap().is_none());
 
 			// Test if we get the same image that we put onto the clipboard
 			ctx.set_image(img_data.clone()).unwrap();
 			let got = ctx.get_image().unwrap();
 			assert_eq!(img_data.bytes, got.bytes);
 
+			// A pure red pixel tells the RGBA order apart from BGRA, in every format it's placed
+			// onto the clipboard in.
+			let red = ImageData { width: 1, height: 1, bytes: [255, 0, 0, 255].as_ref().into() };
+			ctx.set_image(red.clone()).unwrap();
+			assert_eq!(ctx.get_image().unwrap().bytes, red.bytes, "the channels were swapped");
+
+			// The thumbnail is placed alongside the image, which is still read as usual.
+			ctx.set().image_with_thumbnail(img_data.clone(), red.clone()).unwrap();
+			assert_eq!(ctx.get_image().unwrap().bytes, img_data.bytes);
+			#[cfg(all(
+				unix,
+				not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
+			))]
+			{
+				use crate::GetExtLinux;
+
+				let targets = ctx.get().targets().unwrap();
+				assert!(targets.iter().any(|target| target == "image/x-arboard-thumbnail"));
+			}
+			#[cfg(windows)]
+			{
+				let _open = clipboard_win::Clipboard::new_attempts(10).unwrap();
+				let format = clipboard_win::register_format("image/x-arboard-thumbnail").unwrap();
+				let mut png = Vec::new();
+				clipboard_win::raw::get_vec(format.get(), &mut png).unwrap();
+				let thumbnail = image::load_from_memory(&png).unwrap().into_rgba8();
+				assert_eq!(thumbnail.dimensions(), (1, 1));
+				assert_eq!(thumbnail.into_raw(), red.bytes);
+			}
+			#[cfg(windows)]
+			{
+				use crate::{SetExtWindows, WinImageFormat};
+
+				for format in [WinImageFormat::DibV5, WinImageFormat::Dib, WinImageFormat::Bitmap] {
+					ctx.set().image_formats(&[format]).image(red.clone()).unwrap();
+					let got = ctx.get_image().unwrap();
+					assert_eq!(got.bytes, red.bytes, "the channels were swapped in {format:?}");
+				}
+
+				// The legacy bitmap holds the same pixel as the `CF_DIBV5`, in BGRA order.
+				ctx.set().legacy_dib().image(red.clone()).unwrap();
+				assert_eq!(ctx.get_image().unwrap().bytes, red.bytes);
+				let _open = clipboard_win::Clipboard::new_attempts(10).unwrap();
+				let mut dib = Vec::new();
+				clipboard_win::raw::get_vec(clipboard_win::formats::CF_DIB, &mut dib).unwrap();
+				// A `BITMAPINFOHEADER` with `BI_RGB` compression.
+				assert_eq!(&dib[..4], 40u32.to_le_bytes());
+				assert_eq!(&dib[16..20], 0u32.to_le_bytes());
+				assert_eq!(&dib[40..44], [0, 0, 255, 255]);
+			}
+			ctx.set_image(img_data.clone()).unwrap();
+			assert_eq!(ctx.get().any().unwrap(), ClipboardContent::Image(img_data.to_owned_img()));
+
+			// Adding text keeps the image that's already on the clipboard.
+			ctx.set().add_text_keeping_others("image caption").unwrap();
+			assert_eq!(ctx.get_text().unwrap(), "image caption");
+			assert_eq!(ctx.get_image().unwrap().bytes, img_data.bytes);
+			assert_eq!(ctx.get().any().unwrap(), ClipboardContent::Text("image caption".into()));
+
+			// The semi-transparent pixels read back the same on every platform.
+			let mut premultiplied = img_data.clone();
+			premultiplied.premultiply_alpha();
+			let got = ctx.get().alpha_mode(AlphaMode::Premultiplied).image().unwrap();
+			assert_eq!(got.bytes, premultiplied.bytes);
+
 			#[rustfmt::skip]
 			let big_bytes = vec![
 				255, 100, 100, 255,
@@ -361,17 +1488,80 @@ mod tests {
 			ctx.set_image(big_img_data).unwrap();
 			let got = ctx.get_image().unwrap();
 			assert_eq!(bytes_cloned.as_slice(), got.bytes.as_ref());
+
+			// Cached images are replaced along with the clipboard's contents.
+			{
+				let mut ctx = Clipboard::builder().cache_images(true).build().unwrap();
+				assert_eq!(ctx.get_image().unwrap().bytes, bytes_cloned);
+				assert_eq!(ctx.get_image().unwrap().bytes, bytes_cloned);
+				ctx.set_image(img_data.clone()).unwrap();
+				assert_eq!(ctx.get_image().unwrap(), img_data);
+				let got = ctx.get().alpha_mode(AlphaMode::Premultiplied).image().unwrap();
+				assert_eq!(got.bytes, premultiplied.bytes);
+				// A cached image isn't returned to a read with other options.
+				assert!(matches!(ctx.get().max_bytes(4).image(), Err(Error::TooLarge)));
+			}
+
+			// Images only available as a device dependent bitmap are read back as opaque.
+			#[cfg(windows)]
+			{
+				use crate::{SetExtWindows, WinImageFormat};
+
+				#[rustfmt::skip]
+				let opaque_bytes = [
+					255, 100, 100, 255,
+					100, 255, 100, 255,
+					100, 100, 255, 255,
+					0, 0, 0, 255,
+				];
+				let opaque_img =
+					ImageData { width: 2, height: 2, bytes: opaque_bytes.as_ref().into() };
+				ctx.set().image_formats(&[WinImageFormat::Bitmap]).image(opaque_img).unwrap();
+				let got = ctx.get_image().unwrap();
+				assert_eq!(opaque_bytes.as_slice(), got.bytes.as_ref());
+
+				// Top-down bitmaps are read back the right way up.
+				let opaque_img =
+					ImageData { width: 2, height: 2, bytes: opaque_bytes.as_ref().into() };
+				ctx.set()
+					.image_formats(&[WinImageFormat::DibV5])
+					.top_down_dib()
+					.image(opaque_img)
+					.unwrap();
+				let got = ctx.get_image().unwrap();
+				assert_eq!(opaque_bytes.as_slice(), got.bytes.as_ref());
+			}
+
+			// Images can be placed on the pasteboard as PNG files only.
+			#[cfg(target_os = "macos")]
+			{
+				use crate::{ImageRepresentation, SetExtApple};
+
+				let image =
+					ImageData { width: 3, height: 2, bytes: bytes_cloned.as_slice().into() };
+				ctx.set().image_representation(ImageRepresentation::Png).image(image).unwrap();
+				let png = ctx.get().image_png().unwrap();
+				assert!(png.starts_with(b"\x89PNG"));
+			}
 		}
 		#[cfg(all(
 			unix,
 			not(any(target_os = "macos", target_os = "android", target_os = "emscripten")),
 		))]
 		{
-			use crate::{LinuxClipboardKind, SetExtLinux};
+			use crate::{Backend, ClearExtLinux, GetExtLinux, LinuxClipboardKind, SetExtLinux};
 			use std::sync::atomic::{self, AtomicBool};
 
 			let mut ctx = Clipboard::new().unwrap();
 
+			// Without Wayland, there is nothing but X11 to fall back to.
+			if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+				assert_eq!(ctx.backend(), Backend::X11);
+			}
+			let forced = Clipboard::builder().force_x11(true).build().unwrap();
+			assert_eq!(forced.backend(), Backend::X11);
+			drop(forced);
+
 			const TEXT1: &str = "I'm a little teapot,";
 			const TEXT2: &str = "short and stout,";
 			const TEXT3: &str = "here is my handle";
@@ -401,6 +1591,111 @@ mod tests {
 				);
 			}
 
+			// Excluding the data from history shouldn't change what other applications read.
+			ctx.set().exclude_from_history().text(TEXT1.to_string()).unwrap();
+			assert_eq!(TEXT1, &ctx.get_text().unwrap());
+			ctx.set().exclude_from_cloud().text(TEXT2).unwrap();
+			assert_eq!(TEXT2, &ctx.get_text().unwrap());
+			let targets = ctx.get().targets().unwrap();
+			assert!(targets.iter().any(|target| target == "x-kde-passwordManagerHint"));
+			ctx.set_text(TEXT1).unwrap();
+
+			// Text offered under a single plain MIME type can still be read.
+			ctx.set().text_mime("text/plain").text(TEXT3).unwrap();
+			assert_eq!(TEXT3, &ctx.get_text().unwrap());
+
+			// Adding text replaces the text in every format, not just `UTF8_STRING`.
+			ctx.set().add_text_keeping_others(TEXT1).unwrap();
+			let plain = ctx.get().text_targets(&["text/plain"]).text();
+			assert!(plain.map_or(true, |text| text == TEXT1));
+			assert_eq!(TEXT1, &ctx.get_text().unwrap());
+
+			// Lazily set data is only produced once it's requested.
+			if !cfg!(feature = "wayland-data-control")
+				|| std::env::var_os("WAYLAND_DISPLAY").is_none()
+			{
+				let produced = Arc::new(AtomicBool::new(false));
+				ctx.set()
+					.lazy("text/plain;charset=utf-8", {
+						let produced = produced.clone();
+						move || {
+							produced.store(true, atomic::Ordering::Release);
+							TEXT2.as_bytes().to_vec()
+						}
+					})
+					.unwrap();
+				assert!(!produced.load(atomic::Ordering::Acquire));
+				assert_eq!(TEXT2, &ctx.get_text().unwrap());
+				assert!(produced.load(atomic::Ordering::Acquire));
+
+				// Images offered only as BMP are read as well.
+				#[cfg(feature = "image-data")]
+				{
+					let pixels = vec![255, 0, 0, 255, 0, 0, 255, 128];
+					let mut bmp = Vec::new();
+					image::codecs::bmp::BmpEncoder::new(&mut bmp)
+						.encode(&pixels, 2, 1, image::ExtendedColorType::Rgba8)
+						.unwrap();
+					ctx.set().lazy("image/bmp", move || bmp).unwrap();
+					let got = ctx.get_image().unwrap();
+					assert_eq!((got.width, got.height), (2, 1));
+					assert_eq!(got.bytes.as_ref(), pixels.as_slice());
+				}
+
+				// The targets are listed as they are offered, aliases included.
+				ctx.set_text(TEXT1).unwrap();
+				let targets = ctx.get().targets().unwrap();
+				for target in ["TARGETS", "UTF8_STRING", "text/plain;charset=utf-8"] {
+					assert!(targets.iter().any(|name| name == target), "{target} is missing");
+				}
+			}
+
+			ctx.set_text(TEXT1).unwrap();
+			let text = ctx.get().text_targets(&["text/plain;charset=utf-8"]).text().unwrap();
+			assert_eq!(TEXT1, &text);
+			assert!(matches!(
+				ctx.get().text_targets(&["text/x-arboard-missing"]).text(),
+				Err(Error::WrongFormat)
+			));
+
+			ctx.set().confirm().text(TEXT1).unwrap();
+			assert_eq!(TEXT1, &ctx.get_text().unwrap());
+			let text = ctx.get().text_timeout(Duration::from_millis(500)).text().unwrap();
+			assert_eq!(TEXT1, &text);
+
+			ctx.set().also_primary().text(TEXT2).unwrap();
+			for selection in [LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary] {
+				assert_eq!(TEXT2, &ctx.get().clipboard(selection).text().unwrap());
+			}
+
+			ctx.set().mirror_to_primary().text(TEXT1).unwrap();
+			for selection in [LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary] {
+				assert_eq!(TEXT1, &ctx.get().clipboard(selection).text().unwrap());
+			}
+
+			ctx.set().primary_only().text("middle click").unwrap();
+			let primary = ctx.get().clipboard(LinuxClipboardKind::Primary).text().unwrap();
+			assert_eq!(primary, "middle click");
+			assert_eq!(TEXT1, &ctx.get_text().unwrap());
+			ctx.set_text("ctrl+v").unwrap();
+			ctx.clear_with().including_primary().unwrap();
+			for selection in [LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary] {
+				match ctx.get().clipboard(selection).text() {
+					Ok(text) => assert!(text.is_empty()),
+					Err(Error::ContentNotAvailable) => {}
+					Err(e) => panic!("unexpected error: {e}"),
+				}
+			}
+
+			ctx.clear_with().all().unwrap();
+			for selection in [LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary] {
+				match ctx.get().clipboard(selection).text() {
+					Ok(text) => assert!(text.is_empty()),
+					Err(Error::ContentNotAvailable) => {}
+					Err(e) => panic!("unexpected error: {e}"),
+				}
+			}
+
 			let was_replaced = Arc::new(AtomicBool::new(false));
 
 			let setter = thread::spawn({
@@ -448,6 +1743,33 @@ mod tests {
 		}
 	}
 
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn mismatched_image_is_rejected() {
+		let mut ctx = Clipboard::new().unwrap();
+
+		let undersized = ImageData { width: 2, height: 2, bytes: vec![255; 15].into() };
+		match ctx.set_image(undersized) {
+			Err(Error::InvalidImageDimensions { expected: 16, actual: 15 }) => {}
+			other => panic!("unexpected result: {other:?}"),
+		}
+
+		let oversized = ImageData { width: 2, height: 1, bytes: vec![255; 12].into() };
+		match ctx.set().image_with_profile(oversized, &[]) {
+			Err(Error::InvalidImageDimensions { expected: 8, actual: 12 }) => {}
+			other => panic!("unexpected result: {other:?}"),
+		}
+
+		let thumbnail = ImageData { width: 1, height: 1, bytes: vec![255; 3].into() };
+		match ctx
+			.set()
+			.image_with_thumbnail(ImageData::from_rgb8(1, 1, &[255; 3]).unwrap(), thumbnail)
+		{
+			Err(Error::InvalidImageDimensions { expected: 4, actual: 3 }) => {}
+			other => panic!("unexpected result: {other:?}"),
+		}
+	}
+
 	#[test]
 	fn clipboard_trait_consistently() {
 		fn assert_send_sync<T: Send + Sync + 'static>() {}
@@ -455,4 +1777,15 @@ mod tests {
 		assert_send_sync::<Clipboard>();
 		assert!(std::mem::needs_drop::<Clipboard>());
 	}
+
+	// The Windows clipboard can't be sent to another thread while it's open, which `Get` and `Set`
+	// do on creation.
+	#[test]
+	#[cfg(not(windows))]
+	fn get_and_set_are_send() {
+		fn assert_send<T: Send>() {}
+
+		assert_send::<Get<'static>>();
+		assert_send::<Set<'static>>();
+	}
 }
@@ -1,31 +1,124 @@
-use std::{borrow::Cow, time::Instant};
+use std::{
+	borrow::Cow,
+	time::{Duration, Instant},
+};
 
 #[cfg(feature = "wayland-data-control")]
-use log::{trace, warn};
+use log::trace;
+use log::warn;
 
+use crate::{
+	common::{private, ClipboardConfig, DataProducer},
+	Error, SelectionKind,
+};
 #[cfg(feature = "image-data")]
-use crate::ImageData;
-use crate::{common::private, Error};
+use crate::{ImageData, ImageDataWithMeta};
 
 mod x11;
 
 #[cfg(feature = "wayland-data-control")]
 mod wayland;
 
-fn into_unknown<E: std::fmt::Display>(error: E) -> Error {
-	Error::Unknown { description: error.to_string() }
+/// Hints KDE's clipboard manager (Klipper) that the data is sensitive and shouldn't be stored in
+/// its history.
+const KDE_EXCLUSION_MIME: &str = "x-kde-passwordManagerHint";
+const KDE_EXCLUSION_HINT: &[u8] = b"secret";
+
+/// The MIME type of SVG images, which are read and written as text.
+const SVG_MIME: &str = "image/svg+xml";
+
+/// The MIME type of the PNG file placed by [`Set::image_with_thumbnail`](crate::Set::image_with_thumbnail).
+const THUMBNAIL_MIME: &str = "image/x-arboard-thumbnail";
+
+fn into_unknown<E: std::error::Error + Send + Sync + 'static>(error: E) -> Error {
+	Error::Platform { description: error.to_string(), source: Box::new(error) }
+}
+
+/// Returns whether `len` bytes are more than the `max_bytes` which a read may return.
+fn exceeds(len: usize, max_bytes: Option<usize>) -> bool {
+	matches!(max_bytes, Some(max) if len > max)
+}
+
+/// Decodes text which is expected to be UTF-8.
+///
+/// Some applications, like Windows programs running under Wine, place UTF-16 text on the
+/// clipboard under the plain text types instead. Such text is recognized by its byte order mark.
+fn decode_text(bytes: Vec<u8>) -> Result<String, Error> {
+	let from_bytes: fn([u8; 2]) -> u16 = match bytes.get(..2) {
+		Some([0xFF, 0xFE]) => u16::from_le_bytes,
+		Some([0xFE, 0xFF]) => u16::from_be_bytes,
+		_ => return String::from_utf8(bytes).map_err(|_| Error::ConversionFailure),
+	};
+	if bytes.len() % 2 != 0 {
+		return Err(Error::ConversionFailure);
+	}
+	let units = bytes[2..].chunks_exact(2).map(|unit| from_bytes([unit[0], unit[1]]));
+	char::decode_utf16(units).collect::<Result<_, _>>().map_err(|_| Error::ConversionFailure)
+}
+
+/// Decodes the start of text like [`decode_text`], dropping a character at the end which was cut
+/// in the middle.
+fn decode_text_prefix(mut bytes: Vec<u8>) -> Result<String, Error> {
+	let from_bytes: fn([u8; 2]) -> u16 = match bytes.get(..2) {
+		Some([0xFF, 0xFE]) => u16::from_le_bytes,
+		Some([0xFE, 0xFF]) => u16::from_be_bytes,
+		_ => {
+			if let Err(e) = std::str::from_utf8(&bytes) {
+				if e.error_len().is_none() {
+					bytes.truncate(e.valid_up_to());
+				}
+			}
+			return decode_text(bytes);
+		}
+	};
+	bytes.truncate(bytes.len() & !1);
+	// A leading surrogate whose trailing one was cut off.
+	if let [.., first, second] = bytes[2..] {
+		if (0xD800..0xDC00).contains(&from_bytes([first, second])) {
+			bytes.truncate(bytes.len() - 2);
+		}
+	}
+	decode_text(bytes)
+}
+
+/// How much effort to spend on compressing images, which are placed on the clipboard as PNG files.
+///
+/// See [`SetExtLinux::png_compression`].
+#[cfg(feature = "image-data")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PngCompression {
+	/// A balance between the encoding speed and the file size.
+	#[default]
+	Default,
+
+	/// Encodes quickly, at the cost of larger files. This noticeably reduces the time it takes
+	/// to set large images, like screenshots.
+	Fast,
+
+	/// Produces the smallest files, but is the slowest to encode.
+	Best,
 }
 
 #[cfg(feature = "image-data")]
-fn encode_as_png(image: &ImageData) -> Result<Vec<u8>, Error> {
-	use image::ImageEncoder as _;
+fn encode_as_png(image: &ImageData, compression: PngCompression) -> Result<Vec<u8>, Error> {
+	use image::{
+		codecs::png::{CompressionType, FilterType, PngEncoder},
+		ImageEncoder as _,
+	};
 
 	if image.bytes.is_empty() || image.width == 0 || image.height == 0 {
 		return Err(Error::ConversionFailure);
 	}
 
+	let compression = match compression {
+		PngCompression::Default => CompressionType::Default,
+		PngCompression::Fast => CompressionType::Fast,
+		PngCompression::Best => CompressionType::Best,
+	};
+
 	let mut png_bytes = Vec::new();
-	let encoder = image::codecs::png::PngEncoder::new(&mut png_bytes);
+	let encoder = PngEncoder::new_with_quality(&mut png_bytes, compression, FilterType::Adaptive);
 	encoder
 		.write_image(
 			image.bytes.as_ref(),
@@ -38,6 +131,110 @@ fn encode_as_png(image: &ImageData) -> Result<Vec<u8>, Error> {
 	Ok(png_bytes)
 }
 
+/// Scales `image` down to at most `max_pixels` pixels, keeping its aspect ratio, or returns it
+/// as it is if it's small enough.
+#[cfg(feature = "image-data")]
+fn downscale(image: ImageData<'_>, max_pixels: usize) -> Result<ImageData<'_>, Error> {
+	let pixels = image.width.saturating_mul(image.height);
+	if pixels <= max_pixels {
+		return Ok(image);
+	}
+
+	let scale = (max_pixels as f64 / pixels as f64).sqrt();
+	let max_width = ((image.width as f64 * scale) as usize).max(1);
+	let max_height = ((image.height as f64 * scale) as usize).max(1);
+	let scaled = image.resized(max_width, max_height)?;
+	log::info!(
+		"Downscaled the image from {}x{} to {}x{} pixels before setting it.",
+		image.width,
+		image.height,
+		scaled.width,
+		scaled.height
+	);
+	Ok(scaled)
+}
+
+/// Decodes an image read from the clipboard under `mime_type`.
+///
+/// The decoder is chosen by the contents of the file, as some applications mislabel their
+/// images. `mime_type` is only used when the format can't be recognized.
+#[cfg(feature = "image-data")]
+fn decode_image(bytes: &[u8], mime_type: &str) -> Result<ImageDataWithMeta, Error> {
+	let format = image::guess_format(bytes).unwrap_or(match mime_type {
+		"image/png" => image::ImageFormat::Png,
+		"image/gif" => image::ImageFormat::Gif,
+		_ => image::ImageFormat::Bmp,
+	});
+	match format {
+		image::ImageFormat::Png => decode_png(bytes),
+		image::ImageFormat::Bmp => decode_bmp(bytes).map(ImageDataWithMeta::new),
+		image::ImageFormat::Gif => crate::common::decode_gif(bytes).map(ImageDataWithMeta::new),
+		_ => Err(Error::ConversionFailure),
+	}
+}
+
+/// Decodes a PNG file read from the clipboard, along with its resolution.
+///
+/// Animated PNGs are read as their first frame, which isn't necessarily the default image that
+/// decoders without animation support show.
+#[cfg(feature = "image-data")]
+fn decode_png(png: &[u8]) -> Result<ImageDataWithMeta, Error> {
+	use image::{codecs::png::PngDecoder, AnimationDecoder as _, DynamicImage};
+
+	let decoder =
+		PngDecoder::new(std::io::Cursor::new(png)).map_err(|_| Error::ConversionFailure)?;
+	let rgba = if decoder.is_apng().map_err(|_| Error::ConversionFailure)? {
+		let apng = decoder.apng().map_err(|_| Error::ConversionFailure)?;
+		let first_frame = apng.into_frames().next().ok_or(Error::ConversionFailure)?;
+		first_frame.map_err(|_| Error::ConversionFailure)?.into_buffer()
+	} else {
+		DynamicImage::from_decoder(decoder).map_err(|_| Error::ConversionFailure)?.into_rgba8()
+	};
+
+	let (width, height) = rgba.dimensions();
+	let image =
+		ImageData { width: width as usize, height: height as usize, bytes: rgba.into_raw().into() };
+	Ok(with_png_dpi(image, png))
+}
+
+/// Decodes a BMP file read from the clipboard, which some applications offer instead of PNG.
+#[cfg(feature = "image-data")]
+fn decode_bmp(bmp: &[u8]) -> Result<ImageData<'static>, Error> {
+	use image::{codecs::bmp::BmpDecoder, DynamicImage};
+
+	let decoder =
+		BmpDecoder::new(std::io::Cursor::new(bmp)).map_err(|_| Error::ConversionFailure)?;
+	let rgba =
+		DynamicImage::from_decoder(decoder).map_err(|_| Error::ConversionFailure)?.into_rgba8();
+
+	let (width, height) = rgba.dimensions();
+	Ok(ImageData { width: width as usize, height: height as usize, bytes: rgba.into_raw().into() })
+}
+
+/// Attaches the resolution stored in `png`, the file that `image` was decoded from.
+#[cfg(feature = "image-data")]
+fn with_png_dpi(image: ImageData<'static>, png: &[u8]) -> ImageDataWithMeta {
+	let mut image = ImageDataWithMeta::new(image);
+	if let Some((x, y)) = png_pixels_per_meter(png) {
+		image.x_dpi = ImageDataWithMeta::dpi_from_pixels_per_meter(x);
+		image.y_dpi = ImageDataWithMeta::dpi_from_pixels_per_meter(y);
+	}
+	image
+}
+
+/// Reads the resolution from the `pHYs` chunk of a PNG file.
+///
+/// Returns `None` if there's no such chunk, or if it only specifies the aspect ratio.
+#[cfg(feature = "image-data")]
+fn png_pixels_per_meter(png: &[u8]) -> Option<(u32, u32)> {
+	// Only the chunks before the image data are read, which is where the resolution is stored.
+	let reader = png::Decoder::new(png).read_info().ok()?;
+	match reader.info().pixel_dims? {
+		png::PixelDimensions { xppu, yppu, unit: png::Unit::Meter } => Some((xppu, yppu)),
+		_ => None,
+	}
+}
+
 /// Clipboard selection
 ///
 /// Linux has a concept of clipboard "selections" which tend to be used in different contexts. This
@@ -67,6 +264,45 @@ pub enum LinuxClipboardKind {
 	Secondary,
 }
 
+impl From<SelectionKind> for LinuxClipboardKind {
+	fn from(selection: SelectionKind) -> Self {
+		match selection {
+			SelectionKind::Clipboard => LinuxClipboardKind::Clipboard,
+			SelectionKind::Primary => LinuxClipboardKind::Primary,
+			SelectionKind::Secondary => LinuxClipboardKind::Secondary,
+		}
+	}
+}
+
+impl LinuxClipboardKind {
+	/// Returns every selection, for iterating over them.
+	pub fn all() -> &'static [LinuxClipboardKind] {
+		&[LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary, LinuxClipboardKind::Secondary]
+	}
+
+	/// Returns whether the selection can be used on Wayland at all.
+	///
+	/// The Primary selection is still only available with compositors which support it, see
+	/// [`Clipboard::primary_selection_supported`](crate::Clipboard::primary_selection_supported).
+	pub fn is_supported_on_wayland(self) -> bool {
+		!matches!(self, LinuxClipboardKind::Secondary)
+	}
+}
+
+/// The protocol which a [`Clipboard`](crate::Clipboard) uses to talk to the clipboard on Linux.
+///
+/// Returned by [`Clipboard::backend`](crate::Clipboard::backend).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+	/// The X11 protocol, used natively or through XWayland.
+	X11,
+
+	/// The Wayland data control protocol, used when the `wayland-data-control` feature is enabled
+	/// and the compositor supports it.
+	WaylandDataControl,
+}
+
 pub(crate) enum Clipboard {
 	X11(x11::Clipboard),
 
@@ -75,16 +311,22 @@ pub(crate) enum Clipboard {
 }
 
 impl Clipboard {
-	pub(crate) fn new() -> Result<Self, Error> {
+	pub(crate) fn new(config: ClipboardConfig) -> Result<Self, Error> {
 		#[cfg(feature = "wayland-data-control")]
 		{
-			if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+			let force_x11 = config.force_x11 || std::env::var_os("ARBOARD_FORCE_X11").is_some();
+			if force_x11 {
+				trace!("Using the X11 clipboard, as it was forced.");
+			} else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
 				// Wayland is available
-				match wayland::Clipboard::new() {
+				match wayland::Clipboard::new(&config) {
 					Ok(clipboard) => {
 						trace!("Successfully initialized the Wayland data control clipboard.");
 						return Ok(Self::WlDataControl(clipboard));
 					}
+					Err(Error::ClipboardNotSupported) => warn!(
+						"The Wayland compositor doesn't support the data control protocol. Falling back to the X11 clipboard protocol."
+					),
 					Err(e) => warn!(
 						"Tried to initialize the wayland data control protocol clipboard, but failed. Falling back to the X11 clipboard protocol. The error was: {}",
 						e
@@ -92,36 +334,206 @@ impl Clipboard {
 				}
 			}
 		}
-		Ok(Self::X11(x11::Clipboard::new()?))
+		Ok(Self::X11(x11::Clipboard::new(&config)?))
+	}
+
+	pub(crate) fn backend(&self) -> Backend {
+		match self {
+			Self::X11(_) => Backend::X11,
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => Backend::WaylandDataControl,
+		}
+	}
+
+	pub(crate) fn primary_selection_supported(&self) -> bool {
+		match self {
+			// The Primary selection is part of the core X11 protocol.
+			Self::X11(_) => true,
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(clipboard) => clipboard.primary_selection_supported(),
+		}
+	}
+
+	pub(crate) fn owner_process_id(&self) -> Result<Option<u32>, Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.owner_process_id(),
+			// The data control protocol doesn't tell who set the clipboard.
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => Ok(None),
+		}
+	}
+
+	pub(crate) fn is_empty(&mut self) -> Result<bool, Error> {
+		match self {
+			Self::X11(clipboard) => clipboard.is_empty(),
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(clipboard) => clipboard.is_empty(),
+		}
+	}
+
+	pub(crate) fn max_request_bytes(&self) -> Option<usize> {
+		match self {
+			Self::X11(clipboard) => clipboard.max_request_bytes(),
+			// The data is streamed through a pipe, so there's no limit.
+			#[cfg(feature = "wayland-data-control")]
+			Self::WlDataControl(_) => None,
+		}
 	}
 }
 
+/// The callback registered through [`GetExtLinux::on_progress`].
+type ProgressCallback = Box<dyn FnMut(usize, Option<usize>) + Send>;
+
 pub(crate) struct Get<'clipboard> {
 	clipboard: &'clipboard mut Clipboard,
 	selection: LinuxClipboardKind,
+	max_bytes: Option<usize>,
+	text_targets: Option<Vec<String>>,
+	text_timeout: Option<Duration>,
+	image_timeout: Option<Duration>,
+	progress: Option<ProgressCallback>,
 }
 
 impl<'clipboard> Get<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard, selection: LinuxClipboardKind::Clipboard }
+		Self {
+			clipboard,
+			selection: LinuxClipboardKind::Clipboard,
+			max_bytes: None,
+			text_targets: None,
+			text_timeout: None,
+			image_timeout: None,
+			progress: None,
+		}
+	}
+
+	/// Returns a `Get` with the same options, for one more operation.
+	pub(crate) fn reborrow(&mut self) -> Get<'_> {
+		Get {
+			clipboard: &mut *self.clipboard,
+			selection: self.selection,
+			max_bytes: self.max_bytes,
+			text_targets: self.text_targets.clone(),
+			text_timeout: self.text_timeout,
+			image_timeout: self.image_timeout,
+			// The callback can't be shared, so it stays with `self`.
+			progress: None,
+		}
+	}
+
+	pub(crate) fn selection(mut self, selection: SelectionKind) -> Self {
+		self.selection = selection.into();
+		self
+	}
+
+	pub(crate) fn max_bytes(mut self, max_bytes: usize) -> Self {
+		self.max_bytes = Some(max_bytes);
+		self
 	}
 
-	pub(crate) fn text(self) -> Result<String, Error> {
+	pub(crate) fn text(mut self) -> Result<String, Error> {
+		let targets = self.text_targets.as_deref();
 		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.get_text(self.selection),
+			Clipboard::X11(clipboard) => clipboard.get_text(
+				self.selection,
+				self.max_bytes,
+				targets,
+				self.text_timeout,
+				self.progress.as_mut().map(|progress| &mut **progress as x11::Progress<'_>),
+			),
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.get_text(self.selection),
+			Clipboard::WlDataControl(clipboard) => {
+				clipboard.get_text(self.selection, self.max_bytes, targets)
+			}
 		}
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
+	fn targets(self) -> Result<Vec<String>, Error> {
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_targets(self.selection),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => Err(Error::ClipboardNotSupported),
+		}
+	}
+
+	pub(crate) fn text_preview(self, max_chars: usize) -> Result<String, Error> {
+		// Enough bytes for `max_chars` characters after a byte order mark, in any of the encodings.
+		let max_bytes = max_chars.saturating_add(1).saturating_mul(4);
+		let targets = self.text_targets.as_deref();
+		match self.clipboard {
+			Clipboard::X11(clipboard) => {
+				clipboard.get_text_prefix(self.selection, max_bytes, targets, self.text_timeout)
+			}
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => {
+				clipboard.get_text_prefix(self.selection, max_bytes, targets)
+			}
+		}
+	}
+
+	pub(crate) fn image_png(mut self) -> Result<Vec<u8>, Error> {
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_image_png(
+				self.selection,
+				self.max_bytes,
+				self.image_timeout,
+				self.progress.as_mut().map(|progress| &mut **progress as x11::Progress<'_>),
+			),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_image_png(self.selection, self.max_bytes),
+		}
+	}
+
+	pub(crate) fn svg(self) -> Result<String, Error> {
 		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.get_image(self.selection),
+			Clipboard::X11(clipboard) => clipboard.get_svg(self.selection, self.max_bytes),
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.get_image(self.selection),
+			Clipboard::WlDataControl(clipboard) => clipboard.get_svg(self.selection, self.max_bytes),
 		}
 	}
+
+	pub(crate) fn image_raw(mut self) -> Result<(Vec<u8>, String), Error> {
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.get_image_raw(
+				self.selection,
+				self.max_bytes,
+				self.image_timeout,
+				self.progress.as_mut().map(|progress| &mut **progress as x11::Progress<'_>),
+			),
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.get_image_raw(self.selection, self.max_bytes),
+		}
+	}
+
+	/// Returns the key of the image to read, made of a key which changes whenever the contents of
+	/// the selection are replaced, or `None` if that can't be told.
+	///
+	/// Only the regular clipboard has keys, so that those of different selections can't be
+	/// confused.
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_cache_key(&self) -> Option<crate::common::ImageCacheKey> {
+		if !matches!(self.selection, LinuxClipboardKind::Clipboard) {
+			return None;
+		}
+		let change_key = match &self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.change_key(self.selection),
+			// The data control protocol has no way to tell.
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(_) => None,
+		}?;
+		Some(crate::common::ImageCacheKey { change_key, max_bytes: self.max_bytes })
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
+		self.image_with_dpi().map(|image| image.image)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_dpi(self) -> Result<ImageDataWithMeta, Error> {
+		let (bytes, mime_type) = self.image_raw()?;
+		decode_image(&bytes, &mime_type)
+	}
 }
 
 /// Linux-specific extensions to the [`Get`](super::Get) builder.
@@ -131,6 +543,57 @@ pub trait GetExtLinux: private::Sealed {
 	/// If wayland support is enabled and available, attempting to use the Secondary clipboard will
 	/// return an error.
 	fn clipboard(self, selection: LinuxClipboardKind) -> Self;
+
+	/// Completes the "get" operation by fetching the names of the targets which the owner of the
+	/// selection offers, as listed in its reply to `TARGETS`.
+	///
+	/// The list is returned as it is, including aliases for the same format and meta targets like
+	/// `TARGETS` itself. This helps to find out why a format was picked when reading the
+	/// clipboard.
+	///
+	/// This is only supported with X11. When using the Wayland data control protocol,
+	/// [`Error::ClipboardNotSupported`] is returned.
+	fn targets(self) -> Result<Vec<String>, Error>;
+
+	/// Sets the targets which [`text`](crate::Get::text) asks for, in order of preference, to
+	/// replace the default order.
+	///
+	/// The targets are given by their names, like `text/plain;charset=utf-8` or `UTF8_STRING`.
+	/// `STRING` is decoded as Latin-1 and `COMPOUND_TEXT` as far as it holds Latin-1, like by
+	/// default, while any other target is expected to hold UTF-8. By default, the UTF-8 targets
+	/// are tried first, starting with `UTF8_STRING`, and then the legacy ones.
+	///
+	/// With the Wayland data control protocol, the targets are taken as MIME types.
+	fn text_targets(self, targets: &[&str]) -> Self;
+
+	/// Sets how long to wait for the owner of the clipboard to respond if the operation is
+	/// completed with [`text`](crate::Get::text), instead of the
+	/// [`x11_read_timeout`](crate::ClipboardBuilder::x11_read_timeout).
+	///
+	/// Text is usually sent right away, so a short timeout keeps reading a clipboard which can't
+	/// provide any from stalling.
+	///
+	/// *Only used by the X11 backend.*
+	fn text_timeout(self, timeout: Duration) -> Self;
+
+	/// Sets how long to wait for the owner of the clipboard to respond if the operation is
+	/// completed with an image, instead of the
+	/// [`x11_read_timeout`](crate::ClipboardBuilder::x11_read_timeout).
+	///
+	/// Applications can take multiple seconds to produce large images.
+	///
+	/// *Only used by the X11 backend.*
+	fn image_timeout(self, timeout: Duration) -> Self;
+
+	/// Calls `f` while the data is being received in segments, for showing the progress of
+	/// pasting large images, which can take multiple seconds.
+	///
+	/// `f` is called with the number of bytes received so far and, if the owner of the clipboard
+	/// announced it, the size of the data. The size is only a lower bound, as applications can
+	/// send more than they announced. Small data is received at once, without calling `f`.
+	///
+	/// *Only used by the X11 backend, where large data is sent in INCR segments.*
+	fn on_progress(self, f: impl FnMut(usize, Option<usize>) + Send + 'static) -> Self;
 }
 
 impl GetExtLinux for crate::Get<'_> {
@@ -138,10 +601,35 @@ impl GetExtLinux for crate::Get<'_> {
 		self.platform.selection = selection;
 		self
 	}
+
+	fn targets(self) -> Result<Vec<String>, Error> {
+		self.platform.targets()
+	}
+
+	fn text_targets(mut self, targets: &[&str]) -> Self {
+		self.platform.text_targets =
+			Some(targets.iter().map(|&target| target.to_owned()).collect());
+		self
+	}
+
+	fn text_timeout(mut self, timeout: Duration) -> Self {
+		self.platform.text_timeout = Some(timeout);
+		self
+	}
+
+	fn image_timeout(mut self, timeout: Duration) -> Self {
+		self.platform.image_timeout = Some(timeout);
+		self
+	}
+
+	fn on_progress(mut self, f: impl FnMut(usize, Option<usize>) + Send + 'static) -> Self {
+		self.platform.progress = Some(Box::new(f));
+		self
+	}
 }
 
 /// Configuration on how long to wait for a new X11 copy event is emitted.
-#[derive(Default)]
+#[derive(Clone, Copy, Default)]
 pub(crate) enum WaitConfig {
 	/// Waits until the given [`Instant`] has reached.
 	Until(Instant),
@@ -158,40 +646,224 @@ pub(crate) struct Set<'clipboard> {
 	clipboard: &'clipboard mut Clipboard,
 	wait: WaitConfig,
 	selection: LinuxClipboardKind,
+	exclude_from_history: bool,
+	text_mime: Option<String>,
+	also_primary: bool,
+	mirror_primary: bool,
+	confirm: bool,
+	#[cfg(feature = "image-data")]
+	png_compression: PngCompression,
+	#[cfg(feature = "image-data")]
+	max_pixels: Option<usize>,
 }
 
 impl<'clipboard> Set<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard, wait: WaitConfig::default(), selection: LinuxClipboardKind::Clipboard }
+		Self {
+			clipboard,
+			wait: WaitConfig::default(),
+			selection: LinuxClipboardKind::Clipboard,
+			exclude_from_history: false,
+			text_mime: None,
+			also_primary: false,
+			mirror_primary: false,
+			confirm: false,
+			#[cfg(feature = "image-data")]
+			png_compression: PngCompression::default(),
+			#[cfg(feature = "image-data")]
+			max_pixels: None,
+		}
+	}
+
+	/// Places the same data onto the Primary selection with `set` first, if that was asked for
+	/// with [`SetExtLinux::also_primary`].
+	///
+	/// This is best-effort, so a failure is only logged.
+	fn also_set_primary(&mut self, set: impl FnOnce(Set<'_>) -> Result<(), Error>) {
+		if !self.also_primary || matches!(self.selection, LinuxClipboardKind::Primary) {
+			return;
+		}
+		let primary = Set {
+			clipboard: &mut *self.clipboard,
+			wait: WaitConfig::None,
+			selection: LinuxClipboardKind::Primary,
+			exclude_from_history: self.exclude_from_history,
+			text_mime: self.text_mime.clone(),
+			also_primary: false,
+			mirror_primary: false,
+			confirm: false,
+			#[cfg(feature = "image-data")]
+			png_compression: self.png_compression,
+			#[cfg(feature = "image-data")]
+			max_pixels: self.max_pixels,
+		};
+		if let Err(e) = set(primary) {
+			warn!("Failed to place the data onto the Primary selection as well: {e}");
+		}
+	}
+
+	pub(crate) fn selection(mut self, selection: SelectionKind) -> Self {
+		self.selection = selection.into();
+		self
 	}
 
-	pub(crate) fn text(self, text: Cow<'_, str>) -> Result<(), Error> {
+	/// The options to set the data with over X11, mirroring it to the Primary selection if
+	/// `mirror_primary` is set.
+	fn x11_options(&self, mirror_primary: bool) -> x11::SetOptions {
+		x11::SetOptions {
+			selection: self.selection,
+			wait: self.wait,
+			exclude_from_history: self.exclude_from_history,
+			confirm: self.confirm,
+			mirror_primary,
+		}
+	}
+
+	pub(crate) fn text(mut self, text: Cow<'_, str>) -> Result<(), Error> {
+		let mirror_primary =
+			self.mirror_primary && matches!(self.selection, LinuxClipboardKind::Clipboard);
+		if !mirror_primary {
+			self.also_set_primary(|set| set.text(Cow::Borrowed(&text)));
+		}
+		let mime = self.text_mime.as_deref();
+		let options = self.x11_options(mirror_primary);
 		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.set_text(text, self.selection, self.wait),
+			Clipboard::X11(clipboard) => clipboard.set_text(text, mime, options),
 
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.set_text(text, self.selection, self.wait),
+			Clipboard::WlDataControl(clipboard) => clipboard.set_text(
+				text,
+				mime,
+				self.selection,
+				self.wait,
+				self.exclude_from_history,
+				mirror_primary,
+			),
 		}
 	}
 
-	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
+	pub(crate) fn text_keeping_others(mut self, text: Cow<'_, str>) -> Result<(), Error> {
+		self.also_set_primary(|set| set.text_keeping_others(Cow::Borrowed(&text)));
+		let options = self.x11_options(false);
 		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.set_html(html, alt, self.selection, self.wait),
+			Clipboard::X11(clipboard) => clipboard.add_text(text, options),
 
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.set_html(html, alt, self.selection, self.wait),
+			Clipboard::WlDataControl(clipboard) => {
+				clipboard.add_text(text, self.selection, self.wait, self.exclude_from_history)
+			}
 		}
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn image(self, image: ImageData<'_>) -> Result<(), Error> {
+	pub(crate) fn html(
+		mut self,
+		html: Cow<'_, str>,
+		alt: Option<Cow<'_, str>>,
+	) -> Result<(), Error> {
+		self.also_set_primary(|set| {
+			set.html(Cow::Borrowed(&html), alt.as_deref().map(Cow::Borrowed))
+		});
+		let options = self.x11_options(false);
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.set_html(html, alt, options),
+
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => {
+				clipboard.set_html(html, alt, self.selection, self.wait, self.exclude_from_history)
+			}
+		}
+	}
+
+	pub(crate) fn image_png(self, png: Cow<'_, [u8]>) -> Result<(), Error> {
+		self.png_with_thumbnail(png, None)
+	}
+
+	fn png_with_thumbnail(
+		mut self,
+		png: Cow<'_, [u8]>,
+		thumbnail: Option<Cow<'_, [u8]>>,
+	) -> Result<(), Error> {
+		self.also_set_primary(|set| {
+			set.png_with_thumbnail(Cow::Borrowed(&png), thumbnail.as_deref().map(Cow::Borrowed))
+		});
+		let options = self.x11_options(false);
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.set_image_png(png, thumbnail, options),
+
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => clipboard.set_image_png(
+				png,
+				thumbnail,
+				self.selection,
+				self.wait,
+				self.exclude_from_history,
+			),
+		}
+	}
+
+	pub(crate) fn svg(mut self, svg: Cow<'_, str>) -> Result<(), Error> {
+		self.also_set_primary(|set| set.svg(Cow::Borrowed(&svg)));
+		let options = self.x11_options(false);
+		match self.clipboard {
+			Clipboard::X11(clipboard) => clipboard.set_svg(svg, options),
+
+			#[cfg(feature = "wayland-data-control")]
+			Clipboard::WlDataControl(clipboard) => {
+				clipboard.set_svg(svg, self.selection, self.wait, self.exclude_from_history)
+			}
+		}
+	}
+
+	pub(crate) fn lazy(self, mime: &str, producer: DataProducer) -> Result<(), Error> {
+		let options = self.x11_options(false);
 		match self.clipboard {
-			Clipboard::X11(clipboard) => clipboard.set_image(image, self.selection, self.wait),
+			Clipboard::X11(clipboard) => clipboard.set_lazy(mime, producer, options),
 
+			// wl-clipboard-rs needs all of the data up front.
 			#[cfg(feature = "wayland-data-control")]
-			Clipboard::WlDataControl(clipboard) => clipboard.set_image(image, self.selection, self.wait),
+			Clipboard::WlDataControl(_) => {
+				let _ = (mime, producer);
+				Err(Error::ClipboardNotSupported)
+			}
 		}
 	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image(self, image: ImageData<'_>) -> Result<(), Error> {
+		self.image_with_profile(image, None)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_profile(
+		self,
+		image: ImageData<'_>,
+		icc: Option<&[u8]>,
+	) -> Result<(), Error> {
+		let image = match self.max_pixels {
+			Some(max_pixels) => downscale(image, max_pixels)?,
+			None => image,
+		};
+		let mut png = encode_as_png(&image, self.png_compression)?;
+		if let Some(icc) = icc {
+			crate::common::embed_icc_profile(&mut png, icc)?;
+		}
+		self.image_png(png.into())
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_thumbnail(
+		self,
+		image: ImageData<'_>,
+		thumbnail: ImageData<'_>,
+	) -> Result<(), Error> {
+		let image = match self.max_pixels {
+			Some(max_pixels) => downscale(image, max_pixels)?,
+			None => image,
+		};
+		let png = encode_as_png(&image, self.png_compression)?;
+		let thumbnail = encode_as_png(&thumbnail, self.png_compression)?;
+		self.png_with_thumbnail(png.into(), Some(thumbnail.into()))
+	}
 }
 
 /// Linux specific extensions to the [`Set`](super::Set) builder.
@@ -218,6 +890,10 @@ pub trait SetExtLinux: private::Sealed {
 	/// all surprising situations where the clipboard's contents seemingly disappear from under your
 	/// feet.
 	///
+	/// Without waiting, the contents are served by a background thread and are lost when the
+	/// process exits, unless a clipboard manager took them over. Dropping the last `Clipboard`
+	/// gives the clipboard manager a brief moment to do that, on both X11 and Wayland.
+	///
 	/// See the [daemonize example] for a demo of how you could implement this.
 	///
 	/// [daemonize example]: https://github.com/1Password/arboard/blob/master/examples/daemonize.rs
@@ -254,6 +930,118 @@ pub trait SetExtLinux: private::Sealed {
 	/// # }
 	/// ```
 	fn clipboard(self, selection: LinuxClipboardKind) -> Self;
+
+	/// Stores the data in the Primary selection only, leaving the Clipboard selection as it is.
+	///
+	/// This is a clearer way to write `.clipboard(LinuxClipboardKind::Primary)`, for terminal
+	/// emulators and editors which implement "select to copy". Unlike the Clipboard selection,
+	/// the Primary selection is pasted with a middle click.
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// use arboard::{Clipboard, SetExtLinux};
+	/// # fn main() -> Result<(), arboard::Error> {
+	/// let mut ctx = Clipboard::new()?;
+	///
+	/// // The user selected some text, which must not replace what they copied with Ctrl+C.
+	/// ctx.set().primary_only().text("selected text")?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn primary_only(self) -> Self;
+
+	/// Excludes the data which will be set on the clipboard from being added to
+	/// the desktop clipboard managers' histories.
+	///
+	/// This is done by offering the `x-kde-passwordManagerHint` target with the value `secret`
+	/// along with the data.
+	///
+	/// ### Supported platforms
+	///
+	/// - KDE Plasma's Klipper, on both Wayland and X11.
+	fn exclude_from_history(self) -> Self;
+
+	/// Excludes the data which will be set on the clipboard from being synced to other devices,
+	/// like `SetExtWindows::exclude_from_cloud` does on Windows.
+	///
+	/// There is no separate hint for this on Linux, so this offers the same
+	/// `x-kde-passwordManagerHint` target as [`exclude_from_history`](Self::exclude_from_history),
+	/// which also keeps the data out of the clipboard history.
+	///
+	/// ### Supported platforms
+	///
+	/// - KDE Connect, on both Wayland and X11.
+	fn exclude_from_cloud(self) -> Self;
+
+	/// Places text set with [`text`](crate::Set::text) on the clipboard under `mime` only, instead
+	/// of the usual list of text formats (`UTF8_STRING`, `text/plain;charset=utf-8` and so on).
+	///
+	/// The text is still encoded as UTF-8. This is useful for applications which only accept one
+	/// exact target, or for a custom text type.
+	fn text_mime(self, mime: &str) -> Self;
+
+	/// Sets how much effort is spent on compressing images, which are placed on the clipboard as
+	/// PNG files by [`image`](crate::Set::image).
+	///
+	/// [`PngCompression::Fast`] can significantly reduce the time it takes to set large images.
+	#[cfg(feature = "image-data")]
+	fn png_compression(self, compression: PngCompression) -> Self;
+
+	/// Scales images set with [`image`](crate::Set::image) down to at most `max_pixels` pixels
+	/// before placing them on the clipboard, keeping their aspect ratio.
+	///
+	/// Large images, like exports from image editors, can take long to transfer and some X11
+	/// applications fail to receive them. See [`Clipboard::max_request_bytes`] for the size the
+	/// X server accepts in one request.
+	///
+	/// This is lossy, so it's disabled by default. Images which are small enough are placed as
+	/// they are.
+	///
+	/// [`Clipboard::max_request_bytes`]: crate::Clipboard::max_request_bytes
+	#[cfg(feature = "image-data")]
+	fn auto_downscale(self, max_pixels: usize) -> Self;
+
+	/// Places the data onto the Primary selection as well, so that it can also be pasted with a
+	/// middle click, like GTK applications do.
+	///
+	/// This is best-effort: the data is placed onto the Primary selection first, without waiting,
+	/// and a failure to do so, like on a Wayland compositor without Primary selection support, is
+	/// ignored. The Primary selection keeps the data even if setting the selected clipboard fails
+	/// afterwards. Data set with [`lazy`](crate::Set::lazy) can only be produced once, so it's
+	/// only placed onto the selected clipboard.
+	///
+	/// Use this for any kind of data when the Primary selection is a nice-to-have, and
+	/// [`mirror_to_primary`](Self::mirror_to_primary) for text that must land on both or neither.
+	fn also_primary(self) -> Self;
+
+	/// Places text onto the Clipboard and Primary selections together, so that it can be pasted
+	/// both with Ctrl+V and with a middle click, like terminal emulators do when copying.
+	///
+	/// Unlike [`also_primary`](Self::also_primary), both selections are taken over at once with
+	/// the same data, and the operation fails if either of them can't be set. When setting the
+	/// Clipboard selection fails, the Primary selection is put back as it was if this process
+	/// owned it, or is given up otherwise, so the two never hold different text because of this
+	/// operation. This only applies to [`text`](crate::Set::text) set onto the Clipboard
+	/// selection, other data and selections are set as usual.
+	///
+	/// When both this and [`also_primary`](Self::also_primary) are used, this takes precedence
+	/// for text.
+	fn mirror_to_primary(self) -> Self;
+
+	/// Reads the data back through a separate connection to the X server after setting it, and
+	/// returns an error if other applications can't read it.
+	///
+	/// This catches the rare cases where taking ownership of the clipboard silently failed,
+	/// for tools which set the clipboard and exit without [`wait`](Self::wait)ing. The
+	/// confirmation happens before any waiting, at the cost of two more round-trips. Only data
+	/// which was set eagerly and is small enough to be sent at once is read back. For other data,
+	/// the list of formats which the clipboard offers is checked, so that lazily set data isn't
+	/// produced.
+	///
+	/// This has no effect on Wayland, where setting the clipboard already fails if the
+	/// compositor doesn't accept the data.
+	fn confirm(self) -> Self;
 }
 
 impl SetExtLinux for crate::Set<'_> {
@@ -267,10 +1055,58 @@ impl SetExtLinux for crate::Set<'_> {
 		self
 	}
 
+	fn primary_only(mut self) -> Self {
+		self.platform.selection = LinuxClipboardKind::Primary;
+		self
+	}
+
 	fn wait_until(mut self, deadline: Instant) -> Self {
 		self.platform.wait = WaitConfig::Until(deadline);
 		self
 	}
+
+	fn exclude_from_history(mut self) -> Self {
+		self.platform.exclude_from_history = true;
+		self
+	}
+
+	fn exclude_from_cloud(mut self) -> Self {
+		// KDE uses the same hint for both.
+		self.platform.exclude_from_history = true;
+		self
+	}
+
+	fn text_mime(mut self, mime: &str) -> Self {
+		self.platform.text_mime = Some(mime.to_owned());
+		self
+	}
+
+	#[cfg(feature = "image-data")]
+	fn png_compression(mut self, compression: PngCompression) -> Self {
+		self.platform.png_compression = compression;
+		self
+	}
+
+	#[cfg(feature = "image-data")]
+	fn auto_downscale(mut self, max_pixels: usize) -> Self {
+		self.platform.max_pixels = Some(max_pixels);
+		self
+	}
+
+	fn also_primary(mut self) -> Self {
+		self.platform.also_primary = true;
+		self
+	}
+
+	fn mirror_to_primary(mut self) -> Self {
+		self.platform.mirror_primary = true;
+		self
+	}
+
+	fn confirm(mut self) -> Self {
+		self.platform.confirm = true;
+		self
+	}
 }
 
 pub(crate) struct Clear<'clipboard> {
@@ -282,11 +1118,25 @@ impl<'clipboard> Clear<'clipboard> {
 		Self { clipboard }
 	}
 
-	pub(crate) fn clear(self) -> Result<(), Error> {
+	pub(crate) fn clear(mut self) -> Result<(), Error> {
 		self.clear_inner(LinuxClipboardKind::Clipboard)
 	}
 
-	fn clear_inner(self, selection: LinuxClipboardKind) -> Result<(), Error> {
+	fn clear_selections(mut self, selections: &[LinuxClipboardKind]) -> Result<(), Error> {
+		let mut result = Ok(());
+		for &selection in selections {
+			match self.clear_inner(selection) {
+				// Not every selection is available everywhere, like the Secondary on Wayland.
+				Err(Error::ClipboardNotSupported) => {}
+				// Keep clearing the other selections, but report the first failure.
+				Err(e) if result.is_ok() => result = Err(e),
+				_ => {}
+			}
+		}
+		result
+	}
+
+	fn clear_inner(&mut self, selection: LinuxClipboardKind) -> Result<(), Error> {
 		let mut set = Set::new(self.clipboard);
 		set.selection = selection;
 
@@ -315,10 +1165,163 @@ pub trait ClearExtLinux: private::Sealed {
 	/// If wayland support is enabled and available, attempting to use the Secondary clipboard will
 	/// return an error.
 	fn clipboard(self, selection: LinuxClipboardKind) -> Result<(), Error>;
+
+	/// Performs the "clear" operation on every selection: the Clipboard, the Primary and the
+	/// Secondary.
+	///
+	/// Selections which aren't supported, like the Secondary on Wayland, are skipped. If clearing
+	/// a selection fails, the others are still cleared and the first error is returned.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, ClearExtLinux, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	///
+	/// clipboard.clear_with().all()?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn all(self) -> Result<(), Error>;
+
+	/// Performs the "clear" operation on both the Clipboard and the Primary selection, so that
+	/// the old contents can't be pasted with a middle click either.
+	///
+	/// [`Clear::default`](crate::Clear::default) only clears the Clipboard. As with
+	/// [`all`](Self::all), a Primary selection which isn't supported is skipped and the first
+	/// error is returned.
+	///
+	/// ### Example
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, ClearExtLinux, Error};
+	/// # fn main() -> Result<(), Error> {
+	/// let mut clipboard = Clipboard::new()?;
+	///
+	/// clipboard.clear_with().including_primary()?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	fn including_primary(self) -> Result<(), Error>;
 }
 
 impl ClearExtLinux for crate::Clear<'_> {
-	fn clipboard(self, selection: LinuxClipboardKind) -> Result<(), Error> {
+	fn clipboard(mut self, selection: LinuxClipboardKind) -> Result<(), Error> {
 		self.platform.clear_inner(selection)
 	}
+
+	fn all(self) -> Result<(), Error> {
+		self.platform.clear_selections(LinuxClipboardKind::all())
+	}
+
+	fn including_primary(self) -> Result<(), Error> {
+		self.platform
+			.clear_selections(&[LinuxClipboardKind::Clipboard, LinuxClipboardKind::Primary])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn large_images_are_downscaled() {
+		let image = ImageData { width: 40, height: 20, bytes: vec![255; 40 * 20 * 4].into() };
+
+		let scaled = downscale(image.clone(), 200).unwrap();
+		assert_eq!((scaled.width, scaled.height), (20, 10));
+		assert_eq!(scaled.bytes.len(), 20 * 10 * 4);
+
+		let kept = downscale(image.clone(), 800).unwrap();
+		assert_eq!((kept.width, kept.height), (40, 20));
+	}
+
+	#[test]
+	fn utf16_text_is_decoded() {
+		assert_eq!(decode_text(vec![0xFF, 0xFE, b'h', 0, b'i', 0]).unwrap(), "hi");
+		assert_eq!(decode_text(vec![0xFE, 0xFF, 0, b'h', 0, b'i']).unwrap(), "hi");
+		assert_eq!(decode_text(b"hi".to_vec()).unwrap(), "hi");
+		assert!(matches!(decode_text(vec![0xFF, 0xFE, b'h']), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	fn cut_off_text_is_decoded() {
+		// The last character was cut in the middle.
+		assert_eq!(decode_text_prefix("hé".as_bytes()[..2].to_vec()).unwrap(), "h");
+		assert_eq!(decode_text_prefix(vec![0xFF, 0xFE, b'h', 0, b'i']).unwrap(), "h");
+		assert_eq!(decode_text_prefix(vec![0xFF, 0xFE, b'h', 0, 0x3D, 0xD8]).unwrap(), "h");
+		assert_eq!(decode_text_prefix(b"hi".to_vec()).unwrap(), "hi");
+
+		// Invalid text is still rejected.
+		assert!(matches!(decode_text_prefix(vec![0xFF, b'h']), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn png_resolution_is_read() {
+		let image = ImageData { width: 1, height: 1, bytes: vec![0, 0, 0, 255].into() };
+		let png = encode_as_png(&image, PngCompression::Default).unwrap();
+		assert_eq!(png_pixels_per_meter(&png), None);
+
+		// Insert a pHYs chunk of 3780 pixels per meter (96 DPI) right after the IHDR chunk.
+		let ihdr_end = 8 + 12 + 13;
+		let mut phys = vec![0, 0, 0, 9];
+		phys.extend_from_slice(b"pHYs");
+		phys.extend_from_slice(&3780u32.to_be_bytes());
+		phys.extend_from_slice(&3780u32.to_be_bytes());
+		phys.push(1);
+		let crc = crc32fast::hash(&phys[4..]);
+		phys.extend_from_slice(&crc.to_be_bytes());
+		let png = [&png[..ihdr_end], &phys, &png[ihdr_end..]].concat();
+		assert_eq!(png_pixels_per_meter(&png), Some((3780, 3780)));
+
+		let dpi = with_png_dpi(image.to_owned_img(), &png).x_dpi.unwrap();
+		assert_eq!(dpi.round(), 96.0);
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn apng_is_read_as_first_frame() {
+		let default_image: &[u8] = &[0, 0, 0, 255, 0, 0, 0, 255];
+		let frames: [&[u8]; 2] =
+			[&[255, 0, 0, 255, 0, 255, 0, 255], &[0, 0, 255, 255, 0, 0, 255, 255]];
+
+		let mut png = Vec::new();
+		let mut encoder = png::Encoder::new(&mut png, 2, 1);
+		encoder.set_color(png::ColorType::Rgba);
+		encoder.set_depth(png::BitDepth::Eight);
+		encoder.set_animated(2, 0).unwrap();
+		// Give the file a default image that isn't part of the animation.
+		encoder.set_sep_def_img(true).unwrap();
+		let mut writer = encoder.write_header().unwrap();
+		writer.write_image_data(default_image).unwrap();
+		for frame in frames {
+			writer.write_image_data(frame).unwrap();
+		}
+		writer.finish().unwrap();
+
+		let image = decode_png(&png).unwrap().image;
+		assert_eq!((image.width, image.height), (2, 1));
+		assert_eq!(&*image.bytes, frames[0]);
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn bmp_is_decoded() {
+		use image::codecs::bmp::BmpEncoder;
+
+		let pixels = [255, 0, 0, 255, 0, 0, 255, 128];
+		let mut bmp = Vec::new();
+		BmpEncoder::new(&mut bmp).encode(&pixels, 2, 1, image::ExtendedColorType::Rgba8).unwrap();
+
+		let image = decode_bmp(&bmp).unwrap();
+		assert_eq!((image.width, image.height), (2, 1));
+		assert_eq!(&*image.bytes, pixels);
+
+		// The contents decide the decoder, even when the image is offered as a PNG.
+		let image = decode_image(&bmp, "image/png").unwrap().image;
+		assert_eq!(&*image.bytes, pixels);
+	}
 }
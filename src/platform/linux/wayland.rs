@@ -1,23 +1,92 @@
 use std::borrow::Cow;
 use std::io::Read;
+use std::sync::{
+	atomic::{AtomicUsize, Ordering},
+	mpsc::sync_channel,
+	Arc,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use parking_lot::{Condvar, Mutex};
 
 use wl_clipboard_rs::{
 	copy::{self, Error as CopyError, MimeSource, MimeType, Options, Source},
 	paste::{self, get_contents, Error as PasteError, Seat},
-	utils::is_primary_selection_supported,
+	utils::{is_primary_selection_supported, PrimarySelectionCheckError},
 };
 
-#[cfg(feature = "image-data")]
-use super::encode_as_png;
-use super::{into_unknown, LinuxClipboardKind, WaitConfig};
+use super::{
+	decode_text, decode_text_prefix, exceeds, into_unknown, ClipboardConfig, LinuxClipboardKind,
+	WaitConfig, KDE_EXCLUSION_HINT, KDE_EXCLUSION_MIME, SVG_MIME, THUMBNAIL_MIME,
+};
 use crate::common::Error;
-#[cfg(feature = "image-data")]
-use crate::common::ImageData;
 
-#[cfg(feature = "image-data")]
 const MIME_PNG: &str = "image/png";
 
-pub(crate) struct Clipboard {}
+/// How long dropping the last clipboard waits by default for a clipboard manager to take over the
+/// contents of a copy that is being served in the background.
+const FLUSH_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The number of `Clipboard`s that currently exist in the process.
+static CLIPBOARD_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// The most recent copy which didn't wait for its contents to be replaced.
+static BACKGROUND_COPY: Mutex<Option<Arc<BackgroundCopy>>> = parking_lot::const_mutex(None);
+
+/// Tracks a copy that is served by a background thread, which stops serving once the contents
+/// are replaced.
+#[derive(Default)]
+struct BackgroundCopy {
+	replaced: Mutex<bool>,
+	replaced_cv: Condvar,
+}
+
+impl BackgroundCopy {
+	fn finish(&self) {
+		*self.replaced.lock() = true;
+		self.replaced_cv.notify_all();
+	}
+
+	/// Blocks until the contents were replaced or the `deadline` passed.
+	fn wait_until(&self, deadline: Instant) {
+		let mut replaced = self.replaced.lock();
+		while !*replaced {
+			if self.replaced_cv.wait_until(&mut replaced, deadline).timed_out() {
+				break;
+			}
+		}
+	}
+}
+
+pub(crate) struct Clipboard {
+	/// The seat to operate on, or `None` to let the compositor decide.
+	seat: Option<String>,
+	/// Whether the compositor supports the Primary selection, checked at construction.
+	primary_selection_supported: bool,
+	/// How long dropping this clipboard waits for a clipboard manager, if it's the last one.
+	flush_timeout: Duration,
+}
+
+/// Reads all of the data which the selection's owner writes into `pipe`, but gives up with
+/// [`Error::TooLarge`] as soon as it's longer than `max_bytes`.
+fn read_pipe(pipe: impl Read, max_bytes: Option<usize>) -> Result<Vec<u8>, Error> {
+	let mut buffer = Vec::new();
+	match max_bytes {
+		Some(max) => {
+			let limit = u64::try_from(max).unwrap_or(u64::MAX).saturating_add(1);
+			pipe.take(limit).read_to_end(&mut buffer).map_err(into_unknown)?;
+			if exceeds(buffer.len(), max_bytes) {
+				return Err(Error::TooLarge);
+			}
+		}
+		None => {
+			let mut pipe = pipe;
+			pipe.read_to_end(&mut buffer).map_err(into_unknown)?;
+		}
+	}
+	Ok(buffer)
+}
 
 impl TryInto<copy::ClipboardType> for LinuxClipboardKind {
 	type Error = Error;
@@ -45,50 +114,276 @@ impl TryInto<paste::ClipboardType> for LinuxClipboardKind {
 
 impl Clipboard {
 	#[allow(clippy::unnecessary_wraps)]
-	pub(crate) fn new() -> Result<Self, Error> {
-		// Check if it's possible to communicate with the wayland compositor
-		if let Err(e) = is_primary_selection_supported() {
-			return Err(into_unknown(e));
+	pub(crate) fn new(config: &ClipboardConfig) -> Result<Self, Error> {
+		// Check if it's possible to communicate with the wayland compositor, and whether it
+		// supports the data control protocol at all.
+		let primary_selection_supported = match is_primary_selection_supported() {
+			Ok(supported) => supported,
+			Err(PrimarySelectionCheckError::MissingProtocol { .. }) => {
+				return Err(Error::ClipboardNotSupported)
+			}
+			Err(e) => return Err(into_unknown(e)),
+		};
+		CLIPBOARD_COUNT.fetch_add(1, Ordering::SeqCst);
+		Ok(Self {
+			seat: config.wayland_seat.clone(),
+			primary_selection_supported,
+			flush_timeout: config.wayland_flush_timeout.unwrap_or(FLUSH_TIMEOUT),
+		})
+	}
+
+	pub(crate) fn primary_selection_supported(&self) -> bool {
+		self.primary_selection_supported
+	}
+
+	/// Returns whether the clipboard has no offer, or one without any MIME types.
+	///
+	/// An offer of only empty text counts as empty too, since that's what clearing the clipboard
+	/// leaves behind.
+	pub(crate) fn is_empty(&mut self) -> Result<bool, Error> {
+		let result = paste::get_mime_types(paste::ClipboardType::Regular, self.paste_seat());
+		let mime_types = match result {
+			Ok(mime_types) => mime_types,
+
+			Err(PasteError::ClipboardEmpty) => return Ok(true),
+
+			Err(err) => return Err(into_unknown(err)),
+		};
+		let is_text = |mime_type: &str| {
+			mime_type.starts_with("text/plain")
+				|| ["UTF8_STRING", "STRING", "TEXT", "COMPOUND_TEXT"].contains(&mime_type)
+		};
+		if mime_types.is_empty() {
+			return Ok(true);
+		}
+		if !mime_types.iter().all(|mime_type| is_text(mime_type)) {
+			return Ok(false);
+		}
+		// A single character is enough to tell that the text isn't empty.
+		let text = self.get_text_prefix(LinuxClipboardKind::Clipboard, 4, None);
+		Ok(text.map_or(false, |text| text.is_empty()))
+	}
+
+	fn paste_seat(&self) -> Seat<'_> {
+		match &self.seat {
+			Some(name) => Seat::Specific(name),
+			None => Seat::Unspecified,
+		}
+	}
+
+	fn copy_options(
+		&self,
+		selection: LinuxClipboardKind,
+		wait: &WaitConfig,
+	) -> Result<Options, Error> {
+		let mut opts = Options::new();
+		opts.foreground(matches!(wait, WaitConfig::Forever));
+		opts.clipboard(selection.try_into()?);
+		if let Some(name) = &self.seat {
+			opts.seat(copy::Seat::Specific(name.clone()));
 		}
-		Ok(Self {})
+		Ok(opts)
 	}
 
-	pub(crate) fn get_text(&mut self, selection: LinuxClipboardKind) -> Result<String, Error> {
+	/// Places the `sources` onto the clipboard, waiting for them to be replaced as configured.
+	///
+	/// [`WaitConfig::Forever`] serves the copy on the current thread, otherwise it's served by a
+	/// background thread which the process's last `Clipboard` gives a moment to finish when it's
+	/// dropped.
+	fn copy(opts: Options, sources: Vec<MimeSource>, wait: &WaitConfig) -> Result<(), Error> {
+		let map_err = |e| match e {
+			CopyError::PrimarySelectionUnsupported => Error::ClipboardNotSupported,
+			other => into_unknown(other),
+		};
+
+		if let WaitConfig::Forever = wait {
+			return opts.copy_multi(sources).map_err(map_err);
+		}
+
+		// This mirrors what `copy_multi` does when not in the foreground, except that we keep
+		// track of when the serving thread stops.
+		let background = Arc::new(BackgroundCopy::default());
+		let (tx, rx) = sync_channel(1);
+		{
+			let background = Arc::clone(&background);
+			thread::spawn(move || {
+				// The copy must be prepared on the thread, because `PreparedCopy` isn't `Send`.
+				match opts.prepare_copy_multi(sources) {
+					Ok(prepared_copy) => {
+						drop(tx.send(None));
+						// Nobody is listening for errors anymore at this point.
+						drop(prepared_copy.serve());
+					}
+					Err(err) => drop(tx.send(Some(err))),
+				}
+				background.finish();
+			});
+		}
+		match rx.recv() {
+			Ok(None) => {}
+			Ok(Some(err)) => return Err(map_err(err)),
+			Err(_) => return Err(Error::unknown("the Wayland copy thread exited unexpectedly")),
+		}
+
+		*BACKGROUND_COPY.lock() = Some(Arc::clone(&background));
+		if let WaitConfig::Until(deadline) = wait {
+			background.wait_until(*deadline);
+		}
+		Ok(())
+	}
+
+	/// Reads text in the first of the `mime_types` which the selection offers, or in any text MIME
+	/// type if none are given.
+	pub(crate) fn get_text(
+		&mut self,
+		selection: LinuxClipboardKind,
+		max_bytes: Option<usize>,
+		mime_types: Option<&[String]>,
+	) -> Result<String, Error> {
+		self.read_text(selection, mime_types, |pipe| decode_text(read_pipe(pipe, max_bytes)?))
+	}
+
+	/// Reads the first `max_bytes` bytes of text like [`get_text`](Self::get_text). The pipe is
+	/// closed after that, which stops the owner from writing the rest of it.
+	pub(crate) fn get_text_prefix(
+		&mut self,
+		selection: LinuxClipboardKind,
+		max_bytes: usize,
+		mime_types: Option<&[String]>,
+	) -> Result<String, Error> {
+		self.read_text(selection, mime_types, |pipe| {
+			let mut buffer = Vec::new();
+			let limit = u64::try_from(max_bytes).unwrap_or(u64::MAX);
+			pipe.take(limit).read_to_end(&mut buffer).map_err(into_unknown)?;
+			decode_text_prefix(buffer)
+		})
+	}
+
+	/// Finds the text like [`get_text`](Self::get_text) and reads it from the pipe with `read`.
+	fn read_text(
+		&mut self,
+		selection: LinuxClipboardKind,
+		mime_types: Option<&[String]>,
+		read: impl FnOnce(&mut dyn Read) -> Result<String, Error>,
+	) -> Result<String, Error> {
 		use wl_clipboard_rs::paste::MimeType;
 
-		let result = get_contents(selection.try_into()?, Seat::Unspecified, MimeType::Text);
-		match result {
-			Ok((mut pipe, _)) => {
-				let mut contents = vec![];
-				pipe.read_to_end(&mut contents).map_err(into_unknown)?;
-				String::from_utf8(contents).map_err(|_| Error::ConversionFailure)
-			}
+		if let Some(mime_types) = mime_types {
+			for mime_type in mime_types {
+				let result = get_contents(
+					selection.try_into()?,
+					self.paste_seat(),
+					MimeType::Specific(mime_type),
+				);
+				match result {
+					Ok((mut pipe, _)) => return read(&mut pipe),
 
-			Err(PasteError::ClipboardEmpty) | Err(PasteError::NoMimeType) => {
-				Err(Error::ContentNotAvailable)
+					Err(PasteError::ClipboardEmpty) => return Err(Error::ContentNotAvailable),
+
+					Err(PasteError::NoMimeType) => continue,
+
+					Err(PasteError::PrimarySelectionUnsupported) => {
+						return Err(Error::ClipboardNotSupported)
+					}
+
+					Err(err) => return Err(into_unknown(err)),
+				}
 			}
+			return Err(Error::WrongFormat);
+		}
+
+		let result = get_contents(selection.try_into()?, self.paste_seat(), MimeType::Text);
+		match result {
+			Ok((mut pipe, _)) => read(&mut pipe),
+
+			Err(PasteError::ClipboardEmpty) => Err(Error::ContentNotAvailable),
+
+			Err(PasteError::NoMimeType) => Err(Error::WrongFormat),
 
 			Err(PasteError::PrimarySelectionUnsupported) => Err(Error::ClipboardNotSupported),
 
-			Err(err) => Err(Error::Unknown { description: err.to_string() }),
+			Err(err) => Err(into_unknown(err)),
 		}
 	}
 
 	pub(crate) fn set_text(
 		&self,
 		text: Cow<'_, str>,
+		mime: Option<&str>,
 		selection: LinuxClipboardKind,
 		wait: WaitConfig,
+		exclude_from_history: bool,
+		mirror_primary: bool,
 	) -> Result<(), Error> {
-		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
-		opts.clipboard(selection.try_into()?);
+		let mut opts = self.copy_options(selection, &wait)?;
+		if mirror_primary {
+			opts.clipboard(copy::ClipboardType::Both);
+		}
 		let source = Source::Bytes(text.into_owned().into_bytes().into_boxed_slice());
-		opts.copy(source, MimeType::Text).map_err(|e| match e {
-			CopyError::PrimarySelectionUnsupported => Error::ClipboardNotSupported,
-			other => into_unknown(other),
-		})?;
-		Ok(())
+		let mime_type = match mime {
+			Some(mime) => MimeType::Specific(mime.to_owned()),
+			None => MimeType::Text,
+		};
+		let mut sources = vec![MimeSource { source, mime_type }];
+		add_clipboard_exclusions(exclude_from_history, &mut sources);
+		Self::copy(opts, sources, &wait)
+	}
+
+	pub(crate) fn add_text(
+		&self,
+		text: Cow<'_, str>,
+		selection: LinuxClipboardKind,
+		wait: WaitConfig,
+		exclude_from_history: bool,
+	) -> Result<(), Error> {
+		use wl_clipboard_rs::paste::get_mime_types;
+
+		// The text types which are replaced by the new text. Besides the ones `wl-clipboard-rs`
+		// offers along with any text, these include the ones other applications commonly use.
+		const TEXT_MIME_TYPES: &[&str] = &[
+			"text/plain;charset=utf-8",
+			"text/plain;charset=UTF-8",
+			"text/plain",
+			"STRING",
+			"UTF8_STRING",
+			"TEXT",
+			"COMPOUND_TEXT",
+		];
+
+		// Offers can't be extended, so the current contents are read and offered again.
+		let paste_clipboard = selection.try_into()?;
+		let mime_types = match get_mime_types(paste_clipboard, self.paste_seat()) {
+			Ok(mime_types) => mime_types,
+			Err(PasteError::ClipboardEmpty) | Err(PasteError::NoSeats) => Default::default(),
+			Err(PasteError::PrimarySelectionUnsupported) => {
+				return Err(Error::ClipboardNotSupported)
+			}
+			Err(err) => return Err(into_unknown(err)),
+		};
+
+		let mut sources = Vec::with_capacity(mime_types.len() + 2);
+		for mime_type in mime_types {
+			if TEXT_MIME_TYPES.contains(&mime_type.as_str()) || mime_type == KDE_EXCLUSION_MIME {
+				continue;
+			}
+			let (mut pipe, _) = get_contents(
+				paste_clipboard,
+				self.paste_seat(),
+				paste::MimeType::Specific(&mime_type),
+			)
+			.map_err(into_unknown)?;
+			let mut bytes = Vec::new();
+			pipe.read_to_end(&mut bytes).map_err(into_unknown)?;
+			sources.push(MimeSource {
+				source: Source::Bytes(bytes.into_boxed_slice()),
+				mime_type: MimeType::Specific(mime_type),
+			});
+		}
+
+		let source = Source::Bytes(text.into_owned().into_bytes().into_boxed_slice());
+		sources.push(MimeSource { source, mime_type: MimeType::Text });
+		add_clipboard_exclusions(exclude_from_history, &mut sources);
+		Self::copy(self.copy_options(selection, &wait)?, sources, &wait)
 	}
 
 	pub(crate) fn set_html(
@@ -97,79 +392,151 @@ impl Clipboard {
 		alt: Option<Cow<'_, str>>,
 		selection: LinuxClipboardKind,
 		wait: WaitConfig,
+		exclude_from_history: bool,
 	) -> Result<(), Error> {
 		let html_mime = MimeType::Specific(String::from("text/html"));
-		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
-		opts.clipboard(selection.try_into()?);
+		let opts = self.copy_options(selection, &wait)?;
 		let html_source = Source::Bytes(html.into_owned().into_bytes().into_boxed_slice());
-		match alt {
-			Some(alt_text) => {
-				let alt_source =
-					Source::Bytes(alt_text.into_owned().into_bytes().into_boxed_slice());
-				opts.copy_multi(vec![
-					MimeSource { source: alt_source, mime_type: MimeType::Text },
-					MimeSource { source: html_source, mime_type: html_mime },
-				])
-			}
-			None => opts.copy(html_source, html_mime),
+		let mut sources = Vec::with_capacity(3);
+		if let Some(alt_text) = alt {
+			let alt_source = Source::Bytes(alt_text.into_owned().into_bytes().into_boxed_slice());
+			sources.push(MimeSource { source: alt_source, mime_type: MimeType::Text });
 		}
-		.map_err(|e| match e {
-			CopyError::PrimarySelectionUnsupported => Error::ClipboardNotSupported,
-			other => into_unknown(other),
-		})?;
-		Ok(())
+		sources.push(MimeSource { source: html_source, mime_type: html_mime });
+		add_clipboard_exclusions(exclude_from_history, &mut sources);
+		Self::copy(opts, sources, &wait)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn get_image(
+	pub(crate) fn get_image_png(
 		&mut self,
 		selection: LinuxClipboardKind,
-	) -> Result<ImageData<'static>, Error> {
-		use std::io::Cursor;
+		max_bytes: Option<usize>,
+	) -> Result<Vec<u8>, Error> {
 		use wl_clipboard_rs::paste::MimeType;
 
 		let result =
-			get_contents(selection.try_into()?, Seat::Unspecified, MimeType::Specific(MIME_PNG));
+			get_contents(selection.try_into()?, self.paste_seat(), MimeType::Specific(MIME_PNG));
 		match result {
-			Ok((mut pipe, _mime_type)) => {
-				let mut buffer = vec![];
-				pipe.read_to_end(&mut buffer).map_err(into_unknown)?;
-				let image = image::io::Reader::new(Cursor::new(buffer))
-					.with_guessed_format()
-					.map_err(|_| Error::ConversionFailure)?
-					.decode()
-					.map_err(|_| Error::ConversionFailure)?;
-				let image = image.into_rgba8();
-
-				Ok(ImageData {
-					width: image.width() as usize,
-					height: image.height() as usize,
-					bytes: image.into_raw().into(),
-				})
-			}
+			Ok((pipe, _mime_type)) => read_pipe(pipe, max_bytes),
+
+			Err(PasteError::ClipboardEmpty) => Err(Error::ContentNotAvailable),
+
+			Err(PasteError::NoMimeType) => Err(Error::WrongFormat),
 
-			Err(PasteError::ClipboardEmpty) | Err(PasteError::NoMimeType) => {
-				Err(Error::ContentNotAvailable)
+			Err(err) => Err(into_unknown(err)),
+		}
+	}
+
+	pub(crate) fn get_svg(
+		&mut self,
+		selection: LinuxClipboardKind,
+		max_bytes: Option<usize>,
+	) -> Result<String, Error> {
+		use wl_clipboard_rs::paste::MimeType;
+
+		let result =
+			get_contents(selection.try_into()?, self.paste_seat(), MimeType::Specific(SVG_MIME));
+		match result {
+			Ok((pipe, _mime_type)) => decode_text(read_pipe(pipe, max_bytes)?),
+
+			Err(PasteError::ClipboardEmpty) => Err(Error::ContentNotAvailable),
+
+			Err(PasteError::NoMimeType) => Err(Error::WrongFormat),
+
+			Err(err) => Err(into_unknown(err)),
+		}
+	}
+
+	/// Reads an image in the first of the supported formats which the selection offers, along
+	/// with the MIME type of that format.
+	pub(crate) fn get_image_raw(
+		&mut self,
+		selection: LinuxClipboardKind,
+		max_bytes: Option<usize>,
+	) -> Result<(Vec<u8>, String), Error> {
+		use wl_clipboard_rs::paste::MimeType;
+
+		for mime_type in [MIME_PNG, "image/bmp", "image/x-bmp", "image/gif"] {
+			let result = get_contents(
+				selection.try_into()?,
+				self.paste_seat(),
+				MimeType::Specific(mime_type),
+			);
+			match result {
+				Ok((pipe, _mime_type)) => {
+					return Ok((read_pipe(pipe, max_bytes)?, mime_type.to_owned()));
+				}
+
+				Err(PasteError::ClipboardEmpty) => return Err(Error::ContentNotAvailable),
+
+				Err(PasteError::NoMimeType) => continue,
+
+				Err(err) => return Err(into_unknown(err)),
 			}
+		}
+		Err(Error::WrongFormat)
+	}
 
-			Err(err) => Err(Error::Unknown { description: err.to_string() }),
+	pub(crate) fn set_image_png(
+		&mut self,
+		png: Cow<'_, [u8]>,
+		thumbnail: Option<Cow<'_, [u8]>>,
+		selection: LinuxClipboardKind,
+		wait: WaitConfig,
+		exclude_from_history: bool,
+	) -> Result<(), Error> {
+		let opts = self.copy_options(selection, &wait)?;
+		let source = Source::Bytes(png.into_owned().into_boxed_slice());
+		let mut sources =
+			vec![MimeSource { source, mime_type: MimeType::Specific(MIME_PNG.into()) }];
+		if let Some(thumbnail) = thumbnail {
+			let source = Source::Bytes(thumbnail.into_owned().into_boxed_slice());
+			sources
+				.push(MimeSource { source, mime_type: MimeType::Specific(THUMBNAIL_MIME.into()) });
 		}
+		add_clipboard_exclusions(exclude_from_history, &mut sources);
+		Self::copy(opts, sources, &wait)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn set_image(
+	pub(crate) fn set_svg(
 		&mut self,
-		image: ImageData,
+		svg: Cow<'_, str>,
 		selection: LinuxClipboardKind,
 		wait: WaitConfig,
+		exclude_from_history: bool,
 	) -> Result<(), Error> {
-		let image = encode_as_png(&image)?;
-		let mut opts = Options::new();
-		opts.foreground(matches!(wait, WaitConfig::Forever));
-		opts.clipboard(selection.try_into()?);
-		let source = Source::Bytes(image.into());
-		opts.copy(source, MimeType::Specific(MIME_PNG.into())).map_err(into_unknown)?;
-		Ok(())
+		let opts = self.copy_options(selection, &wait)?;
+		let source = Source::Bytes(svg.into_owned().into_bytes().into_boxed_slice());
+		let mut sources =
+			vec![MimeSource { source, mime_type: MimeType::Specific(SVG_MIME.into()) }];
+		add_clipboard_exclusions(exclude_from_history, &mut sources);
+		Self::copy(opts, sources, &wait)
+	}
+}
+
+impl Drop for Clipboard {
+	fn drop(&mut self) {
+		if CLIPBOARD_COUNT.fetch_sub(1, Ordering::SeqCst) != 1 {
+			return;
+		}
+
+		// The contents of a background copy disappear when the process exits, unless a
+		// clipboard manager takes them over first. Give it a chance to do that, like the X11
+		// backend does with its handover.
+		let background = BACKGROUND_COPY.lock().take();
+		if let Some(background) = background {
+			if !self.flush_timeout.is_zero() {
+				background.wait_until(Instant::now() + self.flush_timeout);
+			}
+		}
+	}
+}
+
+fn add_clipboard_exclusions(exclude_from_history: bool, sources: &mut Vec<MimeSource>) {
+	if exclude_from_history {
+		sources.push(MimeSource {
+			source: Source::Bytes(Box::from(KDE_EXCLUSION_HINT)),
+			mime_type: MimeType::Specific(String::from(KDE_EXCLUSION_MIME)),
+		})
 	}
 }
@@ -17,7 +17,7 @@ use std::{
 	cell::RefCell,
 	collections::{hash_map::Entry, HashMap},
 	sync::{
-		atomic::{AtomicBool, Ordering},
+		atomic::{AtomicBool, AtomicU32, Ordering},
 		Arc,
 	},
 	thread::JoinHandle,
@@ -28,12 +28,13 @@ use std::{
 use log::{error, trace, warn};
 use parking_lot::{Condvar, Mutex, MutexGuard, RwLock};
 use x11rb::{
-	connection::Connection,
+	connection::{Connection, RequestConnection as _},
+	errors::ReplyError,
 	protocol::{
 		xproto::{
-			Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, Property,
-			PropertyNotifyEvent, SelectionNotifyEvent, SelectionRequestEvent, Time, WindowClass,
-			SELECTION_NOTIFY_EVENT,
+			Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux,
+			EventMask, PropMode, Property, PropertyNotifyEvent, SelectionNotifyEvent,
+			SelectionRequestEvent, Time, Window, WindowClass, SELECTION_NOTIFY_EVENT,
 		},
 		Event,
 	},
@@ -42,12 +43,14 @@ use x11rb::{
 	COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT, NONE,
 };
 
-#[cfg(feature = "image-data")]
-use super::encode_as_png;
-use super::{into_unknown, LinuxClipboardKind, WaitConfig};
-#[cfg(feature = "image-data")]
-use crate::ImageData;
-use crate::{common::ScopeGuard, Error};
+use super::{
+	decode_text, decode_text_prefix, exceeds, into_unknown, ClipboardConfig, LinuxClipboardKind,
+	WaitConfig, KDE_EXCLUSION_HINT, KDE_EXCLUSION_MIME, SVG_MIME, THUMBNAIL_MIME,
+};
+use crate::{
+	common::{DataProducer, ScopeGuard},
+	Error,
+};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -62,7 +65,10 @@ x11rb::atom_manager! {
 		CLIPBOARD_MANAGER,
 		SAVE_TARGETS,
 		TARGETS,
+		MULTIPLE,
+		TIMESTAMP,
 		ATOM,
+		INTEGER,
 		INCR,
 
 		UTF8_STRING,
@@ -71,6 +77,9 @@ x11rb::atom_manager! {
 		// Text in ISO Latin-1 encoding
 		// See: https://tronche.com/gui/x/icccm/sec-2.html#s-2.6.2
 		STRING,
+		// Text in an ISO 2022 based encoding, mostly used by older applications
+		// See: https://tronche.com/gui/x/icccm/sec-2.html#s-2.6.2
+		COMPOUND_TEXT,
 		// Text in unknown encoding
 		// See: https://tronche.com/gui/x/icccm/sec-2.html#s-2.6.2
 		TEXT,
@@ -79,6 +88,19 @@ x11rb::atom_manager! {
 		HTML: b"text/html",
 
 		PNG_MIME: b"image/png",
+		BMP_MIME: b"image/bmp",
+		X_BMP_MIME: b"image/x-bmp",
+		GIF_MIME: b"image/gif",
+		SVG_MIME: SVG_MIME.as_bytes(),
+		THUMBNAIL_MIME: THUMBNAIL_MIME.as_bytes(),
+
+		KDE_EXCLUSION: KDE_EXCLUSION_MIME.as_bytes(),
+
+		// The process id of the client owning a window.
+		// See: https://specifications.freedesktop.org/wm-spec/latest/
+		_NET_WM_PID,
+		// The title of a window, in UTF-8.
+		_NET_WM_NAME,
 
 		// This is just some random name for the property on our window, into which
 		// the clipboard owner writes the data we requested.
@@ -94,6 +116,14 @@ thread_local! {
 // `SelectionNotify`. Multiple seconds long.
 const LONG_TIMEOUT_DUR: Duration = Duration::from_millis(4000);
 const SHORT_TIMEOUT_DUR: Duration = Duration::from_millis(10);
+const HANDOVER_TIMEOUT_DUR: Duration = Duration::from_millis(100);
+
+// The size of the segments that data too large for a single request is sent in.
+// Kept small enough that each segment arrives well within `SHORT_TIMEOUT_DUR`.
+const INCR_SEGMENT_LEN: usize = 1024 * 1024;
+
+// The size of a `ChangeProperty` request without its data.
+const CHANGE_PROPERTY_HEADER_LEN: usize = 24;
 
 #[derive(Debug, PartialEq, Eq)]
 enum ManagerHandoverState {
@@ -120,6 +150,11 @@ struct Inner {
 	server: XContext,
 	atoms: Atoms,
 
+	/// The display which the server is connected to, so that readers connect to the same one.
+	display: Option<String>,
+	/// The screen which the server's window is on, or `None` for the display's default screen.
+	screen: Option<usize>,
+
 	clipboard: Selection,
 	primary: Selection,
 	secondary: Selection,
@@ -128,22 +163,29 @@ struct Inner {
 	handover_cv: Condvar,
 
 	serve_stopped: AtomicBool,
+
+	/// Counts the writes to any selection, to tell our own contents apart.
+	generation: AtomicU32,
+
+	/// The connection which asks other owners for the key of their contents. It's kept around,
+	/// as connecting for every image read would cost more than the cache saves.
+	#[cfg(feature = "image-data")]
+	change_key_reader: Mutex<Option<XContext>>,
 }
 
 impl XContext {
-	fn new() -> Result<Self> {
+	/// Connects to `display` and creates a window on its `screen`, or on the default screen.
+	fn new(display: Option<&str>, screen: Option<usize>) -> Result<Self> {
 		// create a new connection to an X11 server
-		let (conn, screen_num): (RustConnection, _) =
-			RustConnection::connect(None).map_err(|_| Error::Unknown {
-				description: String::from(
-					"X11 server connection timed out because it was unreachable",
-				),
+		let (conn, default_screen): (RustConnection, _) = RustConnection::connect(display)
+			.map_err(|_| {
+				Error::unknown("X11 server connection timed out because it was unreachable")
+			})?;
+		let screen_num = screen.unwrap_or(default_screen);
+		let screen =
+			conn.setup().roots.get(screen_num).ok_or_else(|| {
+				Error::unknown(format!("the X11 display has no screen {screen_num}"))
 			})?;
-		let screen = conn
-			.setup()
-			.roots
-			.get(screen_num)
-			.ok_or(Error::Unknown { description: String::from("no screen found") })?;
 		let win_id = conn.generate_id().map_err(into_unknown)?;
 
 		let event_mask =
@@ -173,61 +215,189 @@ impl XContext {
 
 		Ok(Self { conn, win_id })
 	}
+
+	/// Names the window, so that it can be told apart from other windows by debugging tools and
+	/// window managers.
+	///
+	/// Without an explicit `name`, the window is named after the running executable.
+	fn set_window_name(&self, atoms: &Atoms, name: Option<&str>) -> Result<()> {
+		let executable = std::env::current_exe()
+			.ok()
+			.and_then(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()));
+		let name = match (name, &executable) {
+			(Some(name), _) => name.to_owned(),
+			(None, Some(executable)) => format!("arboard ({executable})"),
+			(None, None) => String::from("arboard"),
+		};
+
+		// `WM_CLASS` holds two NUL-terminated strings: the instance name and the class name.
+		// See: https://tronche.com/gui/x/icccm/sec-4.html#WM_CLASS
+		let instance = executable.as_deref().unwrap_or("arboard");
+		let class = format!("{instance}\0arboard\0");
+
+		self.conn
+			.change_property8(
+				PropMode::REPLACE,
+				self.win_id,
+				AtomEnum::WM_CLASS,
+				AtomEnum::STRING,
+				class.as_bytes(),
+			)
+			.map_err(into_unknown)?;
+		self.conn
+			.change_property8(
+				PropMode::REPLACE,
+				self.win_id,
+				atoms._NET_WM_NAME,
+				atoms.UTF8_STRING,
+				name.as_bytes(),
+			)
+			.map_err(into_unknown)?;
+		self.conn.flush().map_err(into_unknown)?;
+		Ok(())
+	}
 }
 
 #[derive(Default)]
 struct Selection {
 	data: RwLock<Option<Vec<ClipboardData>>>,
-	/// Mutex around nothing to use with the below condvar.
-	mutex: Mutex<()>,
+	/// The number of times that `data` was changed, which is also used with the below condvar.
+	///
+	/// This lets a writer tell whether its contents were replaced while it didn't hold the lock.
+	mutex: Mutex<u32>,
 	/// A condvar that is notified when the contents of this clipboard are changed.
 	///
 	/// This is associated with `Self::mutex`.
 	data_changed: Condvar,
 }
 
-#[derive(Debug, Clone)]
 struct ClipboardData {
 	bytes: Vec<u8>,
 
 	/// The atom representing the format in which the data is encoded.
 	format: Atom,
+
+	/// For data that was set lazily, the function which produces `bytes` the first time that
+	/// the data is requested.
+	///
+	/// The mutex is only there to make the data `Sync`, it's never locked.
+	producer: Option<Mutex<DataProducer>>,
+}
+
+impl ClipboardData {
+	/// Runs the producer of lazily set data, unless it already ran.
+	fn render(&mut self) {
+		if let Some(producer) = self.producer.take() {
+			self.bytes = producer.into_inner()();
+		}
+	}
+}
+
+/// Data that is being sent to a requestor in INCR segments, because it's too large to fit into a
+/// single request.
+///
+/// See: https://tronche.com/gui/x/icccm/sec-2.html#s-2.7.2
+struct IncrTransfer {
+	requestor: Window,
+	property: Atom,
+	target: Atom,
+	bytes: Vec<u8>,
+	/// How many of the bytes were already sent.
+	sent: usize,
+	/// When the requestor last asked for a segment, to abandon the transfer once it stops.
+	last_request: Instant,
+}
+
+/// Called with the number of bytes which an INCR transfer delivered so far, and the size which
+/// the owner announced for it, if any.
+pub(crate) type Progress<'a> = &'a mut dyn FnMut(usize, Option<usize>);
+
+/// How much data a read accepts.
+#[derive(Debug, Clone, Copy)]
+enum ReadLimit {
+	/// Longer data is rejected with [`Error::TooLarge`], without receiving all of it.
+	Reject(usize),
+	/// Only the first bytes are received. The rest of an INCR transfer is abandoned, so the reader
+	/// must be dropped afterwards. Destroying its window lets the owner drop the transfer, as
+	/// `serve_requests` does for ours.
+	Truncate(usize),
+}
+
+impl ReadLimit {
+	/// Returns the size above which data is rejected.
+	fn max_bytes(limit: Option<Self>) -> Option<usize> {
+		match limit {
+			Some(ReadLimit::Reject(max)) => Some(max),
+			_ => None,
+		}
+	}
+
+	fn bytes(self) -> usize {
+		match self {
+			ReadLimit::Reject(bytes) | ReadLimit::Truncate(bytes) => bytes,
+		}
+	}
+}
+
+/// The progress of reading one target from the selection owner.
+struct ReadState {
+	using_incr: bool,
+	incr_data: Vec<u8>,
+	/// The size which the owner announced for an INCR transfer.
+	incr_size: Option<usize>,
+	timeout_end: Instant,
+	limit: Option<ReadLimit>,
 }
 
 enum ReadSelNotifyResult {
 	GotData(Vec<u8>),
-	IncrStarted,
+	/// The data is sent in INCR segments, which add up to the given size if the owner
+	/// announced it.
+	IncrStarted(Option<usize>),
 	EventNotRecognized,
 }
 
 impl Inner {
-	fn new() -> Result<Self> {
-		let server = XContext::new()?;
+	fn new(
+		display: Option<String>,
+		screen: Option<usize>,
+		window_name: Option<&str>,
+	) -> Result<Self> {
+		let server = XContext::new(display.as_deref(), screen)?;
 		let atoms =
 			Atoms::new(&server.conn).map_err(into_unknown)?.reply().map_err(into_unknown)?;
+		server.set_window_name(&atoms, window_name)?;
 
 		Ok(Self {
 			server,
 			atoms,
+			display,
+			screen,
 			clipboard: Selection::default(),
 			primary: Selection::default(),
 			secondary: Selection::default(),
 			handover_state: Mutex::new(ManagerHandoverState::Idle),
 			handover_cv: Condvar::new(),
 			serve_stopped: AtomicBool::new(false),
+			generation: AtomicU32::new(0),
+			#[cfg(feature = "image-data")]
+			change_key_reader: Mutex::new(None),
 		})
 	}
 
+	/// Places `data` onto `selection`.
+	///
+	/// If `confirm` is given, the data is read back through a separate connection before
+	/// waiting, waiting for up to that long for the reply.
 	fn write(
 		&self,
 		data: Vec<ClipboardData>,
 		selection: LinuxClipboardKind,
 		wait: WaitConfig,
+		confirm: Option<Duration>,
 	) -> Result<()> {
 		if self.serve_stopped.load(Ordering::Relaxed) {
-			return Err(Error::Unknown {
-                description: "The clipboard handler thread seems to have stopped. Logging messages may reveal the cause. (See the `log` crate.)".into()
-            });
+			return Err(Error::unknown("The clipboard handler thread seems to have stopped. Logging messages may reveal the cause. (See the `log` crate.)"));
 		}
 
 		let server_win = self.server.win_id;
@@ -241,7 +411,10 @@ impl Inner {
 
 		self.server.conn.flush().map_err(into_unknown)?;
 
+		self.generation.fetch_add(1, Ordering::Relaxed);
+
 		// Just setting the data, and the `serve_requests` will take care of the rest.
+		let kind = selection;
 		let selection = self.selection_of(selection);
 		let mut data_guard = selection.data.write();
 		*data_guard = Some(data);
@@ -250,20 +423,33 @@ impl Inner {
 		// dropping the `data_guard` and calling `wait[_for]` and that we don't we wake other
 		// threads in that position.
 		let mut guard = selection.mutex.lock();
+		*guard = guard.wrapping_add(1);
+		let change = *guard;
 
 		// Notify any existing waiting threads that we have changed the data in the selection.
 		// It is important that the mutex is locked to prevent this notification getting lost.
 		selection.data_changed.notify_all();
 
+		// The serving thread needs to read the data to answer any requests, including our own.
+		drop(data_guard);
+
+		if let Some(timeout) = confirm {
+			// The serving thread locks the mutex when our contents are replaced, so it must not
+			// be held while waiting for the serving thread to answer.
+			MutexGuard::unlocked(&mut guard, || self.confirm(kind, timeout))?;
+			// Contents which were replaced in the meantime have already ended the wait below.
+			if *guard != change {
+				return Ok(());
+			}
+		}
+
 		match wait {
 			WaitConfig::None => {}
 			WaitConfig::Forever => {
-				drop(data_guard);
 				selection.data_changed.wait(&mut guard);
 			}
 
 			WaitConfig::Until(deadline) => {
-				drop(data_guard);
 				selection.data_changed.wait_until(&mut guard, deadline);
 			}
 		}
@@ -271,20 +457,153 @@ impl Inner {
 		Ok(())
 	}
 
+	/// Places a copy of `data` onto `selection` without flushing the request for its ownership,
+	/// so that the following [`write`](Self::write) takes over both selections at once.
+	///
+	/// The data must not be produced lazily. Returns the data which we previously placed onto
+	/// `selection`, if we owned it, for [`restore`](Self::restore).
+	fn mirror(
+		&self,
+		data: &[ClipboardData],
+		selection: LinuxClipboardKind,
+	) -> Result<Option<Vec<ClipboardData>>> {
+		let copy = data
+			.iter()
+			.map(|data| ClipboardData {
+				bytes: data.bytes.clone(),
+				format: data.format,
+				producer: None,
+			})
+			.collect();
+
+		self.generation.fetch_add(1, Ordering::Relaxed);
+		let kind = selection;
+		let selection = self.selection_of(selection);
+		let previous = selection.data.write().replace(copy);
+		let mut guard = selection.mutex.lock();
+		*guard = guard.wrapping_add(1);
+		selection.data_changed.notify_all();
+		drop(guard);
+
+		// The data is in place before the ownership, so that no request finds the selection empty.
+		let owned = self.server.conn.set_selection_owner(
+			self.server.win_id,
+			self.atom_of(kind),
+			Time::CURRENT_TIME,
+		);
+		if owned.is_err() {
+			self.restore(kind, previous);
+			return Err(Error::ClipboardOccupied);
+		}
+		Ok(previous)
+	}
+
+	/// Undoes a [`mirror`](Self::mirror) onto `selection`, putting back the `previous` data which
+	/// it returned, or giving up the selection if we didn't own it before.
+	fn restore(&self, selection: LinuxClipboardKind, previous: Option<Vec<ClipboardData>>) {
+		let kind = selection;
+		let owned = previous.is_some();
+		let selection = self.selection_of(selection);
+		*selection.data.write() = previous;
+		let mut guard = selection.mutex.lock();
+		*guard = guard.wrapping_add(1);
+		selection.data_changed.notify_all();
+		drop(guard);
+
+		if !owned {
+			let conn = &self.server.conn;
+			let released = conn.set_selection_owner(NONE, self.atom_of(kind), Time::CURRENT_TIME);
+			if released.is_err() || conn.flush().is_err() {
+				warn!("Failed to give up the {kind:?} selection after setting it failed");
+			}
+		}
+	}
+
+	/// Checks that other clients can read the contents which we just placed onto `selection`,
+	/// by asking for its `TARGETS` and, if one is small and set eagerly, one of its data targets
+	/// through a separate connection.
+	fn confirm(&self, selection: LinuxClipboardKind, timeout: Duration) -> Result<()> {
+		let reader = XContext::new(self.display.as_deref(), self.screen)?;
+		let owner = reader
+			.conn
+			.get_selection_owner(self.atom_of(selection))
+			.map_err(into_unknown)?
+			.reply()
+			.map_err(into_unknown)?
+			.owner;
+		if owner != self.server.win_id {
+			return Err(Error::ClipboardOccupied);
+		}
+		let unreadable =
+			|| Error::unknown("The clipboard's contents couldn't be read back after setting them");
+		let formats = self.data_formats(&reader, selection, timeout);
+		if formats.is_empty() {
+			return Err(unreadable());
+		}
+		// Answering `TARGETS` doesn't show that the data itself can be transferred, so one of
+		// the data targets is requested too. Lazily produced data isn't requested, since
+		// confirming the contents shouldn't produce it, and neither is data which would be sent
+		// in INCR segments, as reading only part of it would leave the transfer unfinished.
+		let max_len = self.max_property_len();
+		let format = self.selection_of(selection).data.read().as_ref().and_then(|data| {
+			data.iter()
+				.find(|data| {
+					data.producer.is_none()
+						&& data.bytes.len() <= max_len
+						&& formats.contains(&data.format)
+				})
+				.map(|data| data.format)
+		});
+		let Some(format) = format else {
+			return Ok(());
+		};
+		// A single byte shows that the transfer works, without copying all of the data.
+		let limit = Some(ReadLimit::Truncate(1));
+		self.read_single(&reader, selection, format, timeout, limit, None)
+			.map_err(|_| unreadable())?;
+		Ok(())
+	}
+
 	/// `formats` must be a slice of atoms, where each atom represents a target format.
 	/// The first format from `formats`, which the clipboard owner supports will be the
 	/// format of the return value.
-	fn read(&self, formats: &[Atom], selection: LinuxClipboardKind) -> Result<ClipboardData> {
+	///
+	/// Data longer than `limit` is rejected with [`Error::TooLarge`] or truncated, without
+	/// receiving all of it.
+	fn read(
+		&self,
+		formats: &[Atom],
+		selection: LinuxClipboardKind,
+		timeout: Duration,
+		limit: Option<ReadLimit>,
+		mut progress: Option<Progress<'_>>,
+	) -> Result<ClipboardData> {
 		// if we are the current owner, we can get the current clipboard ourselves
 		if self.is_owner(selection)? {
-			let data = self.selection_of(selection).data.read();
-			if let Some(data_list) = &*data {
-				for data in data_list {
-					for format in formats {
-						if *format == data.format {
-							return Ok(data.clone());
+			let mut data = self.selection_of(selection).data.write();
+			if let Some(data_list) = &mut *data {
+				// The text is also offered under the aliases of `UTF8_STRING`.
+				let utf8_aliases = [self.atoms.UTF8_MIME_0, self.atoms.UTF8_MIME_1];
+				let offers = |format: Atom| {
+					formats.contains(&format)
+						|| (format == self.atoms.UTF8_STRING
+							&& formats.iter().any(|format| utf8_aliases.contains(format)))
+				};
+				if let Some(data) = data_list.iter_mut().find(|data| offers(data.format)) {
+					data.render();
+					let bytes = match limit {
+						Some(ReadLimit::Reject(max)) if data.bytes.len() > max => {
+							return Err(Error::TooLarge)
 						}
-					}
+						Some(ReadLimit::Truncate(max)) => {
+							data.bytes[..data.bytes.len().min(max)].to_vec()
+						}
+						_ => data.bytes.clone(),
+					};
+					return Ok(ClipboardData { bytes, format: data.format, producer: None });
+				}
+				if !data_list.is_empty() {
+					return Err(Error::WrongFormat);
 				}
 			}
 			return Err(Error::ContentNotAvailable);
@@ -292,13 +611,14 @@ impl Inner {
 		// if let Some(data) = self.data.read().clone() {
 		//     return Ok(data)
 		// }
-		let reader = XContext::new()?;
+		let reader = XContext::new(self.display.as_deref(), self.screen)?;
 
 		trace!("Trying to get the clipboard data.");
 		for format in formats {
-			match self.read_single(&reader, selection, *format) {
+			let progress = progress.as_mut().map(|progress| &mut **progress as Progress<'_>);
+			match self.read_single(&reader, selection, *format, timeout, limit, progress) {
 				Ok(bytes) => {
-					return Ok(ClipboardData { bytes, format: *format });
+					return Ok(ClipboardData { bytes, format: *format, producer: None });
 				}
 				Err(Error::ContentNotAvailable) => {
 					continue;
@@ -306,14 +626,141 @@ impl Inner {
 				Err(e) => return Err(e),
 			}
 		}
+		if self.offers_other_formats(&reader, selection, timeout) {
+			return Err(Error::WrongFormat);
+		}
 		Err(Error::ContentNotAvailable)
 	}
 
+	/// Asks the owner of `selection` for its `TARGETS` and returns whether it offers
+	/// any actual data format.
+	///
+	/// Any failure along the way is treated as the selection being empty.
+	fn offers_other_formats(
+		&self,
+		reader: &XContext,
+		selection: LinuxClipboardKind,
+		timeout: Duration,
+	) -> bool {
+		!self.data_formats(reader, selection, timeout).is_empty()
+	}
+
+	/// Asks the owner of `selection` for its `TARGETS` and returns the actual data formats among
+	/// them.
+	///
+	/// Any failure along the way is treated as the selection being empty.
+	fn data_formats(
+		&self,
+		reader: &XContext,
+		selection: LinuxClipboardKind,
+		timeout: Duration,
+	) -> Vec<Atom> {
+		let owner = reader
+			.conn
+			.get_selection_owner(self.atom_of(selection))
+			.ok()
+			.and_then(|cookie| cookie.reply().ok())
+			.map(|reply| reply.owner);
+		if matches!(owner, None | Some(NONE)) {
+			return Vec::new();
+		}
+		let Ok(bytes) =
+			self.read_single(reader, selection, self.atoms.TARGETS, timeout, None, None)
+		else {
+			return Vec::new();
+		};
+		let meta_targets = [
+			self.atoms.TARGETS,
+			self.atoms.SAVE_TARGETS,
+			self.atoms.MULTIPLE,
+			self.atoms.TIMESTAMP,
+		];
+		bytes
+			.chunks_exact(4)
+			.map(|atom| Atom::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]))
+			.filter(|atom| !meta_targets.contains(atom))
+			.collect()
+	}
+
+	/// Returns a key which changes whenever the contents of `selection` are replaced, for caching
+	/// what was read from it.
+	///
+	/// The key is made of the owner's window and the time at which it took ownership, as it
+	/// reports in reply to `TIMESTAMP`. `None` is returned when the contents can't be told apart
+	/// from earlier ones, like when the owner doesn't report a time.
+	#[cfg(feature = "image-data")]
+	fn change_key(&self, selection: LinuxClipboardKind, timeout: Duration) -> Option<u64> {
+		let owner =
+			self.server.conn.get_selection_owner(self.atom_of(selection)).ok()?.reply().ok()?.owner;
+		let time = if owner == NONE {
+			return None;
+		} else if owner == self.server.win_id {
+			self.generation.load(Ordering::Relaxed)
+		} else {
+			let mut reader = self.change_key_reader.lock();
+			let reader_context = match reader.take() {
+				Some(reader) => reader,
+				None => XContext::new(self.display.as_deref(), self.screen).ok()?,
+			};
+			let limit = Some(ReadLimit::Reject(4));
+			let bytes = self
+				.read_single(&reader_context, selection, self.atoms.TIMESTAMP, timeout, limit, None)
+				.ok()?;
+			// A connection that failed is replaced the next time, as it may have been lost.
+			*reader = Some(reader_context);
+			u32::from_ne_bytes(bytes.get(..4)?.try_into().ok()?)
+		};
+		// `CurrentTime`, which some owners report, doesn't tell the contents apart.
+		if time == 0 {
+			return None;
+		}
+		Some(u64::from(owner) << 32 | u64::from(time))
+	}
+
+	/// Returns the names of the targets which the owner of `selection` lists, in its order.
+	fn targets(&self, selection: LinuxClipboardKind, timeout: Duration) -> Result<Vec<String>> {
+		let targets = if self.is_owner(selection)? {
+			self.supported_targets(selection)
+		} else {
+			let reader = XContext::new(self.display.as_deref(), self.screen)?;
+			let bytes =
+				self.read_single(&reader, selection, self.atoms.TARGETS, timeout, None, None)?;
+			bytes
+				.chunks_exact(4)
+				.map(|atom| Atom::from_ne_bytes([atom[0], atom[1], atom[2], atom[3]]))
+				.collect()
+		};
+		targets.into_iter().map(|atom| self.atom_name(atom)).collect()
+	}
+
+	/// The targets which we answer a `TARGETS` request for `selection` with.
+	fn supported_targets(&self, selection: LinuxClipboardKind) -> Vec<Atom> {
+		let mut targets = Vec::with_capacity(10);
+		targets.push(self.atoms.TARGETS);
+		targets.push(self.atoms.SAVE_TARGETS);
+		let data = self.selection_of(selection).data.read();
+		if let Some(data_list) = &*data {
+			for data in data_list {
+				targets.push(data.format);
+				if data.format == self.atoms.UTF8_STRING {
+					// When we are storing a UTF8 string,
+					// add all equivalent formats to the supported targets
+					targets.push(self.atoms.UTF8_MIME_0);
+					targets.push(self.atoms.UTF8_MIME_1);
+				}
+			}
+		}
+		targets
+	}
+
 	fn read_single(
 		&self,
 		reader: &XContext,
 		selection: LinuxClipboardKind,
 		target_format: Atom,
+		timeout: Duration,
+		limit: Option<ReadLimit>,
+		mut progress: Option<Progress<'_>>,
 	) -> Result<Vec<u8>> {
 		// Delete the property so that we can detect (using property notify)
 		// when the selection owner receives our request.
@@ -337,17 +784,20 @@ impl Inner {
 
 		trace!("Finished `convert_selection`");
 
-		let mut incr_data: Vec<u8> = Vec::new();
-		let mut using_incr = false;
-
-		let mut timeout_end = Instant::now() + LONG_TIMEOUT_DUR;
+		let mut state = ReadState {
+			using_incr: false,
+			incr_data: Vec::new(),
+			incr_size: None,
+			timeout_end: Instant::now() + timeout,
+			limit,
+		};
 
-		while Instant::now() < timeout_end {
+		while Instant::now() < state.timeout_end {
 			let event = reader.conn.poll_for_event().map_err(into_unknown)?;
 			let event = match event {
 				Some(e) => e,
 				None => {
-					std::thread::sleep(Duration::from_millis(1));
+					wait_for_server(&reader.conn, state.timeout_end)?;
 					continue;
 				}
 			};
@@ -358,17 +808,17 @@ impl Inner {
 					let result = self.handle_read_selection_notify(
 						reader,
 						target_format,
-						&mut using_incr,
-						&mut incr_data,
+						&mut state,
 						event,
 					)?;
 					match result {
 						ReadSelNotifyResult::GotData(data) => return Ok(data),
-						ReadSelNotifyResult::IncrStarted => {
+						ReadSelNotifyResult::IncrStarted(size) => {
 							// This means we received an indication that an the
 							// data is going to be sent INCRementally. Let's
 							// reset our timeout.
-							timeout_end += SHORT_TIMEOUT_DUR;
+							state.timeout_end += SHORT_TIMEOUT_DUR;
+							state.incr_size = size;
 						}
 						ReadSelNotifyResult::EventNotRecognized => (),
 					}
@@ -377,16 +827,15 @@ impl Inner {
 				// will be sent in INCR segments, each segment is transferred in
 				// a PropertyNotify event.
 				Event::PropertyNotify(event) => {
-					let result = self.handle_read_property_notify(
-						reader,
-						target_format,
-						using_incr,
-						&mut incr_data,
-						&mut timeout_end,
-						event,
-					)?;
+					let result =
+						self.handle_read_property_notify(reader, target_format, &mut state, event)?;
 					if result {
-						return Ok(incr_data);
+						return Ok(state.incr_data);
+					}
+					if let Some(progress) = progress.as_deref_mut() {
+						if state.using_incr && !state.incr_data.is_empty() {
+							progress(state.incr_data.len(), state.incr_size);
+						}
 					}
 				}
 				_ => log::trace!("An unexpected event arrived while reading the clipboard."),
@@ -434,6 +883,78 @@ impl Inner {
 		Ok(current == self.server.win_id)
 	}
 
+	/// The formats which text is read from, in the order of preference.
+	fn text_formats(&self) -> [Atom; 7] {
+		[
+			self.atoms.UTF8_STRING,
+			self.atoms.UTF8_MIME_0,
+			self.atoms.UTF8_MIME_1,
+			self.atoms.STRING,
+			self.atoms.COMPOUND_TEXT,
+			self.atoms.TEXT,
+			self.atoms.TEXT_MIME_UNKNOWN,
+		]
+	}
+
+	/// Returns whether `selection` has no owner, or one which offers no data formats.
+	///
+	/// An owner which only offers empty text counts as empty too, since that's what clearing a
+	/// selection leaves behind: it can't be taken away from the other clients otherwise.
+	fn is_empty(&self, selection: LinuxClipboardKind, timeout: Duration) -> Result<bool> {
+		if self.is_owner(selection)? {
+			let data = self.selection_of(selection).data.read();
+			return Ok(data.as_ref().map_or(true, |data| {
+				data.iter().all(|data| data.producer.is_none() && data.bytes.is_empty())
+			}));
+		}
+		let reader = XContext::new(self.display.as_deref(), self.screen)?;
+		let formats = self.data_formats(&reader, selection, timeout);
+		let text_formats = self.text_formats();
+		let Some(&text_format) = formats.first() else {
+			return Ok(true);
+		};
+		if !formats.iter().all(|format| text_formats.contains(format)) {
+			return Ok(false);
+		}
+		// Only a single byte is needed to tell that the text isn't empty.
+		let limit = Some(ReadLimit::Truncate(1));
+		let text = self.read_single(&reader, selection, text_format, timeout, limit, None);
+		Ok(text.map_or(false, |text| text.is_empty()))
+	}
+
+	/// Returns the process id of the selection owner, as advertised by the `_NET_WM_PID`
+	/// property of its window.
+	fn owner_process_id(&self, selection: LinuxClipboardKind) -> Result<Option<u32>> {
+		let owner = self
+			.server
+			.conn
+			.get_selection_owner(self.atom_of(selection))
+			.map_err(into_unknown)?
+			.reply()
+			.map_err(into_unknown)?
+			.owner;
+
+		if owner == NONE {
+			return Ok(None);
+		}
+		if owner == self.server.win_id {
+			return Ok(Some(std::process::id()));
+		}
+
+		let reply = self
+			.server
+			.conn
+			.get_property(false, owner, self.atoms._NET_WM_PID, AtomEnum::CARDINAL, 0, 1)
+			.map_err(into_unknown)?
+			.reply();
+		match reply {
+			Ok(reply) => Ok(reply.value32().and_then(|mut values| values.next())),
+			// The owner's window may have been destroyed in the meantime.
+			Err(ReplyError::X11Error(_)) => Ok(None),
+			Err(e) => Err(into_unknown(e)),
+		}
+	}
+
 	fn atom_name(&self, atom: x11rb::protocol::xproto::Atom) -> Result<String> {
 		String::from_utf8(
 			self.server
@@ -467,8 +988,7 @@ impl Inner {
 		&self,
 		reader: &XContext,
 		target_format: u32,
-		using_incr: &mut bool,
-		incr_data: &mut Vec<u8>,
+		state: &mut ReadState,
 		event: SelectionNotifyEvent,
 	) -> Result<ReadSelNotifyResult> {
 		// The property being set to NONE means that the `convert_selection`
@@ -483,14 +1003,32 @@ impl Inner {
 			log::info!("Received a SelectionNotify for a selection other than CLIPBOARD, PRIMARY or SECONDARY. This is unexpected.");
 			return Ok(ReadSelNotifyResult::EventNotRecognized);
 		}
-		if *using_incr {
-			log::warn!("Received a SelectionNotify while already expecting INCR segments.");
-			return Ok(ReadSelNotifyResult::EventNotRecognized);
+		if state.using_incr {
+			// Some owners start the transfer over, so the segments received so far are stale.
+			log::info!("The selection owner restarted the transfer, discarding the received data.");
+			state.using_incr = false;
+			state.incr_data.clear();
 		}
-		// request the selection
+		let limit = state.limit;
+		// The owner replies to `TARGETS` with a list of type `ATOM` and to
+		// `TIMESTAMP` with an `INTEGER`, every other target is expected to
+		// come back with its own type.
+		let expected_type = if target_format == self.atoms.TARGETS {
+			self.atoms.ATOM
+		} else if target_format == self.atoms.TIMESTAMP {
+			self.atoms.INTEGER
+		} else {
+			target_format
+		};
+
+		// request the selection, but not more than one unit past the limit
+		let max_bytes = ReadLimit::max_bytes(limit);
+		let long_length = limit
+			.and_then(|limit| u32::try_from(limit.bytes() / 4 + 1).ok())
+			.map_or(u32::MAX / 4, |units| units.min(u32::MAX / 4));
 		let mut reply = reader
 			.conn
-			.get_property(true, event.requestor, event.property, event.target, 0, u32::MAX / 4)
+			.get_property(true, event.requestor, event.property, expected_type, 0, long_length)
 			.map_err(into_unknown)?
 			.reply()
 			.map_err(into_unknown)?;
@@ -498,7 +1036,12 @@ impl Inner {
 		// trace!("Property.type: {:?}", self.atom_name(reply.type_));
 
 		// we found something
-		if reply.type_ == target_format {
+		if reply.type_ == expected_type {
+			if let Some(ReadLimit::Truncate(max)) = limit {
+				reply.value.truncate(max);
+			} else if reply.bytes_after > 0 || exceeds(reply.value.len(), max_bytes) {
+				return Err(Error::TooLarge);
+			}
 			Ok(ReadSelNotifyResult::GotData(reply.value))
 		} else if reply.type_ == self.atoms.INCR {
 			// Note that we call the get_property again because we are
@@ -519,17 +1062,23 @@ impl Inner {
 				.reply()
 				.map_err(into_unknown)?;
 			log::trace!("Receiving INCR segments");
-			*using_incr = true;
+			state.using_incr = true;
+			let mut size = None;
 			if reply.value_len == 4 {
-				let min_data_len = reply.value32().and_then(|mut vals| vals.next()).unwrap_or(0);
-				incr_data.reserve(min_data_len as usize);
+				let min_data_len =
+					reply.value32().and_then(|mut vals| vals.next()).unwrap_or(0) as usize;
+				if exceeds(min_data_len, max_bytes) {
+					return Err(Error::TooLarge);
+				}
+				state
+					.incr_data
+					.reserve(limit.map_or(min_data_len, |limit| min_data_len.min(limit.bytes())));
+				size = Some(min_data_len);
 			}
-			Ok(ReadSelNotifyResult::IncrStarted)
+			Ok(ReadSelNotifyResult::IncrStarted(size))
 		} else {
 			// this should never happen, we have sent a request only for supported types
-			Err(Error::Unknown {
-				description: String::from("incorrect type received from clipboard"),
-			})
+			Err(Error::unknown("incorrect type received from clipboard"))
 		}
 	}
 
@@ -538,15 +1087,13 @@ impl Inner {
 		&self,
 		reader: &XContext,
 		target_format: u32,
-		using_incr: bool,
-		incr_data: &mut Vec<u8>,
-		timeout_end: &mut Instant,
+		state: &mut ReadState,
 		event: PropertyNotifyEvent,
 	) -> Result<bool> {
 		if event.atom != self.atoms.ARBOARD_CLIPBOARD || event.state != Property::NEW_VALUE {
 			return Ok(false);
 		}
-		if !using_incr {
+		if !state.using_incr {
 			// This must mean the selection owner received our request, and is
 			// now preparing the data
 			return Ok(false);
@@ -558,21 +1105,41 @@ impl Inner {
 			.reply()
 			.map_err(into_unknown)?;
 
+		if reply.type_ == self.atoms.INCR {
+			// The owner is restarting the transfer. The property is read once its
+			// `SelectionNotify` arrives.
+			return Ok(false);
+		}
+
 		// log::trace!("Received segment. value_len {}", reply.value_len,);
 		if reply.value_len == 0 {
 			// This indicates that all the data has been sent.
 			return Ok(true);
 		}
-		incr_data.extend(reply.value);
+		state.incr_data.extend(reply.value);
+		if let Some(ReadLimit::Truncate(max)) = state.limit {
+			if state.incr_data.len() >= max {
+				// The remaining segments aren't asked for.
+				state.incr_data.truncate(max);
+				return Ok(true);
+			}
+		}
+		if exceeds(state.incr_data.len(), ReadLimit::max_bytes(state.limit)) {
+			return Err(Error::TooLarge);
+		}
 
 		// Let's reset our timeout, since we received a valid chunk.
-		*timeout_end = Instant::now() + SHORT_TIMEOUT_DUR;
+		state.timeout_end = Instant::now() + SHORT_TIMEOUT_DUR;
 
 		// Not yet complete
 		Ok(false)
 	}
 
-	fn handle_selection_request(&self, event: SelectionRequestEvent) -> Result<()> {
+	fn handle_selection_request(
+		&self,
+		event: SelectionRequestEvent,
+		incr_transfers: &mut Vec<IncrTransfer>,
+	) -> Result<()> {
 		let selection = match self.kind_of(event.selection) {
 			Some(kind) => kind,
 			None => {
@@ -585,21 +1152,7 @@ impl Inner {
 		// we are asked for a list of supported conversion targets
 		if event.target == self.atoms.TARGETS {
 			trace!("Handling TARGETS, dst property is {}", self.atom_name_dbg(event.property));
-			let mut targets = Vec::with_capacity(10);
-			targets.push(self.atoms.TARGETS);
-			targets.push(self.atoms.SAVE_TARGETS);
-			let data = self.selection_of(selection).data.read();
-			if let Some(data_list) = &*data {
-				for data in data_list {
-					targets.push(data.format);
-					if data.format == self.atoms.UTF8_STRING {
-						// When we are storing a UTF8 string,
-						// add all equivalent formats to the supported targets
-						targets.push(self.atoms.UTF8_MIME_0);
-						targets.push(self.atoms.UTF8_MIME_1);
-					}
-				}
-			}
+			let targets = self.supported_targets(selection);
 			self.server
 				.conn
 				.change_property32(
@@ -615,9 +1168,17 @@ impl Inner {
 			success = true;
 		} else {
 			trace!("Handling request for (probably) the clipboard contents.");
-			let data = self.selection_of(selection).data.read();
-			if let Some(data_list) = &*data {
-				success = match data_list.iter().find(|d| d.format == event.target) {
+			let mut data = self.selection_of(selection).data.write();
+			if let Some(data_list) = &mut *data {
+				let requested = data_list.iter_mut().find(|d| d.format == event.target);
+				success = match requested.map(|data| {
+					data.render();
+					&*data
+				}) {
+					Some(data) if data.bytes.len() > self.max_property_len() => {
+						self.start_incr_transfer(&event, data, incr_transfers)?;
+						true
+					}
 					Some(data) => {
 						self.server
 							.conn
@@ -665,19 +1226,148 @@ impl Inner {
 		self.server.conn.flush().map_err(into_unknown)
 	}
 
-	fn ask_clipboard_manager_to_request_our_data(&self) -> Result<()> {
+	/// Returns the atom which represents `name`, creating it if it doesn't exist yet.
+	fn intern_atom(&self, name: &str) -> Result<Atom> {
+		let cookie = self.server.conn.intern_atom(false, name.as_bytes()).map_err(into_unknown)?;
+		Ok(cookie.reply().map_err(into_unknown)?.atom)
+	}
+
+	/// The largest amount of data that fits into a single `ChangeProperty` request.
+	fn max_property_len(&self) -> usize {
+		self.server.conn.maximum_request_bytes().saturating_sub(CHANGE_PROPERTY_HEADER_LEN)
+	}
+
+	/// Announces to the requestor that the data is going to be sent in INCR segments.
+	///
+	/// The segments are sent by `send_incr_segment` whenever the requestor deleted the previous
+	/// one.
+	fn start_incr_transfer(
+		&self,
+		event: &SelectionRequestEvent,
+		data: &ClipboardData,
+		incr_transfers: &mut Vec<IncrTransfer>,
+	) -> Result<()> {
+		trace!("Sending {} bytes in INCR segments", data.bytes.len());
+
+		// We need to be notified when the requestor deletes the property, and when its window is
+		// destroyed, which abandons the transfer.
+		self.server
+			.conn
+			.change_window_attributes(
+				event.requestor,
+				&ChangeWindowAttributesAux::new()
+					.event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY),
+			)
+			.map_err(into_unknown)?;
+
+		// The value of the INCR property is a lower bound on the size of the data.
+		let len = u32::try_from(data.bytes.len()).unwrap_or(u32::MAX);
+		self.server
+			.conn
+			.change_property32(
+				PropMode::REPLACE,
+				event.requestor,
+				event.property,
+				self.atoms.INCR,
+				&[len],
+			)
+			.map_err(into_unknown)?;
+
+		// A new request for the same property supersedes any transfer that was abandoned.
+		incr_transfers.retain(|t| t.requestor != event.requestor || t.property != event.property);
+		incr_transfers.push(IncrTransfer {
+			requestor: event.requestor,
+			property: event.property,
+			target: event.target,
+			bytes: data.bytes.clone(),
+			sent: 0,
+			last_request: Instant::now(),
+		});
+		Ok(())
+	}
+
+	/// Sends the next segment of an INCR transfer. Once all of the data was sent, an empty
+	/// segment is sent to mark the end of the transfer.
+	///
+	/// Returns `Ok(true)` when the transfer is complete.
+	fn send_incr_segment(&self, transfer: &mut IncrTransfer) -> Result<bool> {
+		let segment_len = INCR_SEGMENT_LEN.min(self.max_property_len());
+		let end = transfer.bytes.len().min(transfer.sent + segment_len);
+		let segment = &transfer.bytes[transfer.sent..end];
+		self.server
+			.conn
+			.change_property8(
+				PropMode::REPLACE,
+				transfer.requestor,
+				transfer.property,
+				transfer.target,
+				segment,
+			)
+			.map_err(into_unknown)?;
+		transfer.sent = end;
+		transfer.last_request = Instant::now();
+
+		let complete = segment.is_empty();
+		if complete {
+			trace!("Finished sending INCR segments");
+		}
+		self.server.conn.flush().map_err(into_unknown)?;
+		Ok(complete)
+	}
+
+	/// Stops listening to the property changes of the requestor of a `finished` transfer, unless
+	/// one of the `remaining` transfers is still sent to the same window.
+	fn finish_incr_transfer(
+		&self,
+		finished: &IncrTransfer,
+		remaining: &[IncrTransfer],
+	) -> Result<()> {
+		if remaining.iter().any(|transfer| transfer.requestor == finished.requestor) {
+			return Ok(());
+		}
+		self.server
+			.conn
+			.change_window_attributes(
+				finished.requestor,
+				&ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+			)
+			.map_err(into_unknown)?;
+		self.server.conn.flush().map_err(into_unknown)?;
+		Ok(())
+	}
+
+	/// Drops the transfers whose requestor didn't ask for a segment for longer than the default
+	/// read timeout, as it must have stopped reading.
+	fn expire_incr_transfers(&self, incr_transfers: &mut Vec<IncrTransfer>) -> Result<()> {
+		while let Some(index) =
+			incr_transfers.iter().position(|t| t.last_request.elapsed() > LONG_TIMEOUT_DUR)
+		{
+			let transfer = incr_transfers.swap_remove(index);
+			trace!("Abandoning an INCR transfer, as its requestor stopped reading");
+			self.finish_incr_transfer(&transfer, incr_transfers)?;
+		}
+		Ok(())
+	}
+
+	fn ask_clipboard_manager_to_request_our_data(
+		&self,
+		max_handover_duration: Duration,
+	) -> Result<()> {
 		if self.server.win_id == 0 {
 			// This shouldn't really ever happen but let's just check.
 			error!("The server's window id was 0. This is unexpected");
 			return Ok(());
 		}
 
-		if !self.is_owner(LinuxClipboardKind::Clipboard)? {
-			// We are not owning the clipboard, nothing to do.
+		if self.selection_of(LinuxClipboardKind::Clipboard).data.read().is_none() {
+			// If we don't have any data, there's nothing to do. This is also the case once another
+			// application took the clipboard over, like after `wait`ing for that, and checking it
+			// first saves a round-trip to the X server.
+			trace!("Skipping the clipboard manager handover, as we have no data.");
 			return Ok(());
 		}
-		if self.selection_of(LinuxClipboardKind::Clipboard).data.read().is_none() {
-			// If we don't have any data, there's nothing to do.
+		if !self.is_owner(LinuxClipboardKind::Clipboard)? {
+			// We are not owning the clipboard, nothing to do.
 			return Ok(());
 		}
 
@@ -700,7 +1390,6 @@ impl Inner {
 		self.server.conn.flush().map_err(into_unknown)?;
 
 		*handover_state = ManagerHandoverState::InProgress;
-		let max_handover_duration = Duration::from_millis(100);
 
 		// Note that we are using a parking_lot condvar here, which doesn't wake up
 		// spuriously
@@ -714,9 +1403,7 @@ impl Inner {
 			return Ok(());
 		}
 
-		Err(Error::Unknown {
-			description: "The handover was not finished and the condvar didn't time out, yet the condvar wait ended. This should be unreachable.".into()
-		})
+		Err(Error::unknown("The handover was not finished and the condvar didn't time out, yet the condvar wait ended. This should be unreachable."))
 	}
 }
 
@@ -731,22 +1418,43 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 		clip.handover_cv.notify_all();
 	}
 
+	/// Returns whether the clipboard manager has requested every format of the clipboard's data,
+	/// so that nothing is lost when our window goes away.
+	fn everything_written(clip: &Inner, written_targets: &[Atom]) -> bool {
+		let data = clip.selection_of(LinuxClipboardKind::Clipboard).data.read();
+		data.iter()
+			.flatten()
+			// The exclusion hint isn't content, so managers may well skip it.
+			.filter(|data| data.format != clip.atoms.KDE_EXCLUSION)
+			.all(|data| written_targets.contains(&data.format))
+	}
+
 	trace!("Started serve requests thread.");
 
 	let _guard = ScopeGuard::new(|| {
 		context.serve_stopped.store(true, Ordering::Relaxed);
 	});
 
-	let mut written = false;
+	// The targets which were sent to the clipboard manager.
+	let mut written_targets = Vec::new();
 	let mut notified = false;
+	let mut incr_transfers = Vec::new();
 
 	loop {
-		match context.server.conn.wait_for_event().map_err(into_unknown)? {
-			Event::DestroyNotify(_) => {
+		let event = context.server.conn.wait_for_event().map_err(into_unknown)?;
+		// Otherwise, the data of abandoned transfers would be kept, and the handover would wait
+		// for them, forever.
+		context.expire_incr_transfers(&mut incr_transfers).map_err(into_unknown)?;
+		match event {
+			Event::DestroyNotify(event) if event.window == context.server.win_id => {
 				// This window is being destroyed.
 				trace!("Clipboard server window is being destroyed x_x");
 				return Ok(());
 			}
+			Event::DestroyNotify(event) => {
+				// The requestor of INCR transfers went away before receiving all of the data.
+				incr_transfers.retain(|t| t.requestor != event.window);
+			}
 			Event::SelectionClear(event) => {
 				// TODO: check if this works
 				// Someone else has new content in the clipboard, so it is
@@ -763,7 +1471,8 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 					// thread has unlocked its `data_guard` and is just about to sleep.
 					// It is also important that the RwLock is kept write-locked for the same
 					// reason.
-					let _guard = selection.mutex.lock();
+					let mut guard = selection.mutex.lock();
+					*guard = guard.wrapping_add(1);
 					selection.data_changed.notify_all();
 				}
 			}
@@ -774,24 +1483,55 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 					context.atom_name_dbg(event.target),
 				);
 				// Someone is requesting the clipboard content from us.
-				context.handle_selection_request(event).map_err(into_unknown)?;
+				context
+					.handle_selection_request(event, &mut incr_transfers)
+					.map_err(into_unknown)?;
 
 				// if we are in the progress of saving to the clipboard manager
 				// make sure we save that we have finished writing
 				let handover_state = context.handover_state.lock();
 				if *handover_state == ManagerHandoverState::InProgress {
 					// Only set written, when the actual contents were written,
-					// not just a response to what TARGETS we have.
-					if event.target != context.atoms.TARGETS {
-						trace!("The contents were written to the clipboard manager.");
-						written = true;
-						// if we have written and notified, make sure to notify that we are done
-						if notified {
+					// not just a response to what TARGETS we have, or the start of
+					// an INCR transfer.
+					let incr_started = incr_transfers
+						.iter()
+						.any(|t| t.requestor == event.requestor && t.property == event.property);
+					if event.target != context.atoms.TARGETS && !incr_started {
+						trace!(
+							"The {} contents were written to the clipboard manager.",
+							context.atom_name_dbg(event.target)
+						);
+						written_targets.push(event.target);
+						// Managers may request one format after another, so the handover is only
+						// done once all of them were written.
+						if notified && everything_written(&context, &written_targets) {
 							handover_finished(&context, handover_state);
 						}
 					}
 				}
 			}
+			Event::PropertyNotify(event) if event.state == Property::DELETE => {
+				// The requestor of an INCR transfer is ready for the next segment.
+				let index = incr_transfers
+					.iter()
+					.position(|t| t.requestor == event.window && t.property == event.atom);
+				if let Some(index) = index {
+					if context.send_incr_segment(&mut incr_transfers[index])? {
+						let transfer = incr_transfers.swap_remove(index);
+						context.finish_incr_transfer(&transfer, &incr_transfers)?;
+
+						let handover_state = context.handover_state.lock();
+						if *handover_state == ManagerHandoverState::InProgress {
+							trace!("The contents were written to the clipboard manager.");
+							written_targets.push(transfer.target);
+							if notified && everything_written(&context, &written_targets) {
+								handover_finished(&context, handover_state);
+							}
+						}
+					}
+				}
+			}
 			Event::SelectionNotify(event) => {
 				// We've requested the clipboard content and this is the answer.
 				// Considering that this thread is not responsible for reading
@@ -815,7 +1555,14 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 					// immediately get a SelectionNotify with property set to 0,
 					// but following that, we also get a valid SelectionRequest
 					// from the clipboard manager.
-					if written {
+					//
+					// A successful notification after some of the contents were written
+					// means that the manager took everything it wanted, even if it skipped
+					// some formats.
+					let succeeded = event.property != NONE
+						&& !written_targets.is_empty()
+						&& incr_transfers.is_empty();
+					if succeeded || everything_written(&context, &written_targets) {
 						handover_finished(&context, handover_state);
 					}
 				}
@@ -828,18 +1575,83 @@ fn serve_requests(context: Arc<Inner>) -> Result<(), Box<dyn std::error::Error>>
 	}
 }
 
+/// How the data of a "set" operation is placed onto the clipboard.
+pub(crate) struct SetOptions {
+	pub(crate) selection: LinuxClipboardKind,
+	pub(crate) wait: WaitConfig,
+	pub(crate) exclude_from_history: bool,
+
+	/// Whether to read the data back after setting it, see [`SetExtLinux::confirm`].
+	///
+	/// [`SetExtLinux::confirm`]: super::SetExtLinux::confirm
+	pub(crate) confirm: bool,
+
+	/// Whether to place the data onto the Primary selection together with the selection, see
+	/// [`SetExtLinux::mirror_to_primary`]. The data must not be produced lazily.
+	///
+	/// [`SetExtLinux::mirror_to_primary`]: super::SetExtLinux::mirror_to_primary
+	pub(crate) mirror_primary: bool,
+}
+
 pub(crate) struct Clipboard {
-	inner: Arc<Inner>,
+	/// The connection shared by all clipboards, which is only set up once it's needed.
+	inner: Mutex<Option<Arc<Inner>>>,
+
+	/// The display to connect to, if this clipboard is the one setting up the connection.
+	display: Option<String>,
+
+	/// The screen of the display to create the window on, in that case.
+	screen: Option<usize>,
+
+	/// The name of the clipboard window, if this clipboard is the one setting up the connection.
+	window_name: Option<String>,
+
+	/// How long to wait for the selection owner when reading from the clipboard.
+	read_timeout: Duration,
+
+	/// Whether to skip handing the data over to the clipboard manager when dropped.
+	skip_handover: bool,
+
+	/// How long to wait for the clipboard manager to take over the data when dropped.
+	handover_timeout: Duration,
 }
 
 impl Clipboard {
-	pub(crate) fn new() -> Result<Self> {
+	pub(crate) fn new(config: &ClipboardConfig) -> Result<Self> {
+		Ok(Self {
+			inner: Mutex::new(None),
+			display: config.x11_display.clone(),
+			screen: config.x11_screen,
+			window_name: config.x11_window_name.clone(),
+			read_timeout: config.x11_read_timeout.unwrap_or(LONG_TIMEOUT_DUR),
+			skip_handover: config.x11_skip_handover,
+			handover_timeout: config.x11_handover_timeout.unwrap_or(HANDOVER_TIMEOUT_DUR),
+		})
+	}
+
+	/// Returns the connection shared by all clipboards, which is set up by the first clipboard
+	/// that's used.
+	fn inner(&self) -> Result<Arc<Inner>> {
+		let mut inner = self.inner.lock();
+		if let Some(inner) = &*inner {
+			return Ok(Arc::clone(inner));
+		}
+
 		let mut global_cb = CLIPBOARD.lock();
 		if let Some(global_cb) = &*global_cb {
-			return Ok(Self { inner: Arc::clone(&global_cb.inner) });
+			// The server connection is shared, so the first clipboard decides the display.
+			if self.display.is_some() && self.display != global_cb.inner.display {
+				warn!("A clipboard connected to a different X11 display already exists. Reusing its connection instead.");
+			}
+			if self.screen.is_some() && self.screen != global_cb.inner.screen {
+				warn!("A clipboard on a different X11 screen already exists. Reusing its connection instead.");
+			}
+			*inner = Some(Arc::clone(&global_cb.inner));
+			return Ok(Arc::clone(&global_cb.inner));
 		}
 		// At this point we know that the clipboard does not exist.
-		let ctx = Arc::new(Inner::new()?);
+		let ctx =
+			Arc::new(Inner::new(self.display.clone(), self.screen, self.window_name.as_deref())?);
 		let join_handle;
 		{
 			let ctx = Arc::clone(&ctx);
@@ -850,116 +1662,398 @@ impl Clipboard {
 			});
 		}
 		*global_cb = Some(GlobalClipboard { inner: Arc::clone(&ctx), server_handle: join_handle });
-		Ok(Self { inner: ctx })
+		*inner = Some(Arc::clone(&ctx));
+		Ok(ctx)
 	}
 
-	pub(crate) fn get_text(&self, selection: LinuxClipboardKind) -> Result<String> {
-		let formats = [
-			self.inner.atoms.UTF8_STRING,
-			self.inner.atoms.UTF8_MIME_0,
-			self.inner.atoms.UTF8_MIME_1,
-			self.inner.atoms.STRING,
-			self.inner.atoms.TEXT,
-			self.inner.atoms.TEXT_MIME_UNKNOWN,
-		];
-		let result = self.inner.read(&formats, selection)?;
-		if result.format == self.inner.atoms.STRING {
-			// ISO Latin-1
-			// See: https://stackoverflow.com/questions/28169745/what-are-the-options-to-convert-iso-8859-1-latin-1-to-a-string-utf-8
-			Ok(result.bytes.into_iter().map(|c| c as char).collect())
+	pub(crate) fn owner_process_id(&self) -> Result<Option<u32>> {
+		let inner = self.inner()?;
+		inner.owner_process_id(LinuxClipboardKind::Clipboard)
+	}
+
+	pub(crate) fn is_empty(&self) -> Result<bool> {
+		self.inner()?.is_empty(LinuxClipboardKind::Clipboard, self.read_timeout)
+	}
+
+	pub(crate) fn max_request_bytes(&self) -> Option<usize> {
+		self.inner().ok().map(|inner| inner.max_property_len())
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn change_key(&self, selection: LinuxClipboardKind) -> Option<u64> {
+		self.inner().ok()?.change_key(selection, self.read_timeout)
+	}
+
+	pub(crate) fn get_targets(&self, selection: LinuxClipboardKind) -> Result<Vec<String>> {
+		self.inner()?.targets(selection, self.read_timeout)
+	}
+
+	/// Reads text in the first of `targets`, by name, which the selection offers, or the first of
+	/// the known text formats if no `targets` are given.
+	pub(crate) fn get_text(
+		&self,
+		selection: LinuxClipboardKind,
+		max_bytes: Option<usize>,
+		targets: Option<&[String]>,
+		timeout: Option<Duration>,
+		progress: Option<Progress<'_>>,
+	) -> Result<String> {
+		let limit = max_bytes.map(ReadLimit::Reject);
+		self.read_text(selection, limit, targets, timeout, progress, decode_text)
+	}
+
+	/// Reads the first `max_bytes` bytes of text like [`get_text`](Self::get_text), without
+	/// receiving the rest of it.
+	pub(crate) fn get_text_prefix(
+		&self,
+		selection: LinuxClipboardKind,
+		max_bytes: usize,
+		targets: Option<&[String]>,
+		timeout: Option<Duration>,
+	) -> Result<String> {
+		let limit = Some(ReadLimit::Truncate(max_bytes));
+		self.read_text(selection, limit, targets, timeout, None, decode_text_prefix)
+	}
+
+	/// Reads text and decodes it, using `decode_utf8` for the formats which hold UTF-8.
+	fn read_text(
+		&self,
+		selection: LinuxClipboardKind,
+		limit: Option<ReadLimit>,
+		targets: Option<&[String]>,
+		timeout: Option<Duration>,
+		progress: Option<Progress<'_>>,
+		decode_utf8: fn(Vec<u8>) -> Result<String>,
+	) -> Result<String> {
+		let inner = self.inner()?;
+		let formats = match targets {
+			Some(targets) => {
+				targets.iter().map(|target| inner.intern_atom(target)).collect::<Result<_>>()?
+			}
+			None => inner.text_formats().to_vec(),
+		};
+		// When we own the selection, `read` copies our own data once. UTF-8 without a byte order
+		// mark is decoded in place; every other encoding is converted into a new buffer.
+		let timeout = timeout.unwrap_or(self.read_timeout);
+		let result = inner.read(&formats, selection, timeout, limit, progress)?;
+		if result.format == inner.atoms.STRING {
+			Ok(decode_latin1(&result.bytes))
+		} else if result.format == inner.atoms.COMPOUND_TEXT {
+			decode_compound_text(&result.bytes)
 		} else {
-			String::from_utf8(result.bytes).map_err(|_| Error::ConversionFailure)
+			decode_utf8(result.bytes)
 		}
 	}
 
 	pub(crate) fn set_text(
 		&self,
 		message: Cow<'_, str>,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
+		mime: Option<&str>,
+		options: SetOptions,
 	) -> Result<()> {
+		let inner = self.inner()?;
+		let format = match mime {
+			Some(mime) => inner.intern_atom(mime)?,
+			None => inner.atoms.UTF8_STRING,
+		};
 		let data = vec![ClipboardData {
 			bytes: message.into_owned().into_bytes(),
-			format: self.inner.atoms.UTF8_STRING,
+			format,
+			producer: None,
 		}];
-		self.inner.write(data, selection, wait)
+		self.write(&inner, data, options)
+	}
+
+	pub(crate) fn add_text(&self, message: Cow<'_, str>, options: SetOptions) -> Result<()> {
+		let inner = self.inner()?;
+		let atoms = &inner.atoms;
+		// Only the formats which this process placed onto the selection can be kept. They are
+		// moved rather than copied, so that lazily set data still isn't produced until requested.
+		// Every text format is dropped, so that no stale text is left behind the new one.
+		let text_formats = inner.text_formats();
+		let mut data: Vec<ClipboardData> = if inner.is_owner(options.selection)? {
+			let existing = inner.selection_of(options.selection).data.write().take();
+			existing
+				.into_iter()
+				.flatten()
+				.filter(|data| {
+					!text_formats.contains(&data.format) && data.format != atoms.KDE_EXCLUSION
+				})
+				.collect()
+		} else {
+			Vec::new()
+		};
+		data.push(ClipboardData {
+			bytes: message.into_owned().into_bytes(),
+			format: atoms.UTF8_STRING,
+			producer: None,
+		});
+		self.write(&inner, data, options)
 	}
 
 	pub(crate) fn set_html(
 		&self,
 		html: Cow<'_, str>,
 		alt: Option<Cow<'_, str>>,
-		selection: LinuxClipboardKind,
-		wait: WaitConfig,
+		options: SetOptions,
 	) -> Result<()> {
+		let inner = self.inner()?;
 		let mut data = vec![];
 		if let Some(alt_text) = alt {
 			data.push(ClipboardData {
 				bytes: alt_text.into_owned().into_bytes(),
-				format: self.inner.atoms.UTF8_STRING,
+				format: inner.atoms.UTF8_STRING,
+				producer: None,
 			});
 		}
 		data.push(ClipboardData {
 			bytes: html.into_owned().into_bytes(),
-			format: self.inner.atoms.HTML,
+			format: inner.atoms.HTML,
+			producer: None,
 		});
-		self.inner.write(data, selection, wait)
+		self.write(&inner, data, options)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn get_image(&self, selection: LinuxClipboardKind) -> Result<ImageData<'static>> {
-		let formats = [self.inner.atoms.PNG_MIME];
-		let bytes = self.inner.read(&formats, selection)?.bytes;
-
-		let cursor = std::io::Cursor::new(&bytes);
-		let mut reader = image::io::Reader::new(cursor);
-		reader.set_format(image::ImageFormat::Png);
-		let image = match reader.decode() {
-			Ok(img) => img.into_rgba8(),
-			Err(_e) => return Err(Error::ConversionFailure),
-		};
-		let (w, h) = image.dimensions();
-		let image_data =
-			ImageData { width: w as usize, height: h as usize, bytes: image.into_raw().into() };
-		Ok(image_data)
+	pub(crate) fn get_image_png(
+		&self,
+		selection: LinuxClipboardKind,
+		max_bytes: Option<usize>,
+		timeout: Option<Duration>,
+		progress: Option<Progress<'_>>,
+	) -> Result<Vec<u8>> {
+		let inner = self.inner()?;
+		let formats = [inner.atoms.PNG_MIME];
+		let timeout = timeout.unwrap_or(self.read_timeout);
+		Ok(inner
+			.read(&formats, selection, timeout, max_bytes.map(ReadLimit::Reject), progress)?
+			.bytes)
 	}
 
-	#[cfg(feature = "image-data")]
-	pub(crate) fn set_image(
+	/// Reads an image in the first of the supported formats which the selection offers, along
+	/// with the MIME type of that format.
+	pub(crate) fn get_image_raw(
 		&self,
-		image: ImageData,
 		selection: LinuxClipboardKind,
-		wait: WaitConfig,
+		max_bytes: Option<usize>,
+		timeout: Option<Duration>,
+		progress: Option<Progress<'_>>,
+	) -> Result<(Vec<u8>, String)> {
+		let inner = self.inner()?;
+		let atoms = &inner.atoms;
+		let formats = [
+			(atoms.PNG_MIME, "image/png"),
+			(atoms.BMP_MIME, "image/bmp"),
+			(atoms.X_BMP_MIME, "image/x-bmp"),
+			(atoms.GIF_MIME, "image/gif"),
+		];
+		let formats_only = formats.map(|(atom, _)| atom);
+		let timeout = timeout.unwrap_or(self.read_timeout);
+		let limit = max_bytes.map(ReadLimit::Reject);
+		let data = inner.read(&formats_only, selection, timeout, limit, progress)?;
+		let mime_type = formats
+			.iter()
+			.find(|(atom, _)| *atom == data.format)
+			.map_or("image/png", |(_, mime_type)| mime_type);
+		Ok((data.bytes, mime_type.to_owned()))
+	}
+
+	pub(crate) fn set_image_png(
+		&self,
+		png: Cow<'_, [u8]>,
+		thumbnail: Option<Cow<'_, [u8]>>,
+		options: SetOptions,
 	) -> Result<()> {
-		let encoded = encode_as_png(&image)?;
-		let data = vec![ClipboardData { bytes: encoded, format: self.inner.atoms.PNG_MIME }];
-		self.inner.write(data, selection, wait)
+		let inner = self.inner()?;
+		let mut data = vec![ClipboardData {
+			bytes: png.into_owned(),
+			format: inner.atoms.PNG_MIME,
+			producer: None,
+		}];
+		if let Some(thumbnail) = thumbnail {
+			data.push(ClipboardData {
+				bytes: thumbnail.into_owned(),
+				format: inner.atoms.THUMBNAIL_MIME,
+				producer: None,
+			});
+		}
+		self.write(&inner, data, options)
+	}
+
+	pub(crate) fn get_svg(
+		&self,
+		selection: LinuxClipboardKind,
+		max_bytes: Option<usize>,
+	) -> Result<String> {
+		let inner = self.inner()?;
+		let formats = [inner.atoms.SVG_MIME];
+		let limit = max_bytes.map(ReadLimit::Reject);
+		decode_text(inner.read(&formats, selection, self.read_timeout, limit, None)?.bytes)
+	}
+
+	pub(crate) fn set_svg(&self, svg: Cow<'_, str>, options: SetOptions) -> Result<()> {
+		let inner = self.inner()?;
+		let data = vec![ClipboardData {
+			bytes: svg.into_owned().into_bytes(),
+			format: inner.atoms.SVG_MIME,
+			producer: None,
+		}];
+		self.write(&inner, data, options)
+	}
+
+	pub(crate) fn set_lazy(
+		&self,
+		mime: &str,
+		producer: DataProducer,
+		options: SetOptions,
+	) -> Result<()> {
+		let inner = self.inner()?;
+		let format = inner.intern_atom(mime)?;
+		let data =
+			vec![ClipboardData { bytes: Vec::new(), format, producer: Some(Mutex::new(producer)) }];
+		self.write(&inner, data, options)
+	}
+
+	/// Places `data` onto the selection as configured by `options`.
+	fn write(
+		&self,
+		inner: &Inner,
+		mut data: Vec<ClipboardData>,
+		options: SetOptions,
+	) -> Result<()> {
+		Self::add_clipboard_exclusions(inner, options.exclude_from_history, &mut data);
+		let confirm = options.confirm.then_some(self.read_timeout);
+		if !options.mirror_primary {
+			return inner.write(data, options.selection, options.wait, confirm);
+		}
+		// The Primary selection is restored if the data can't be placed onto the selection
+		// itself, so that the two are only ever changed together.
+		let previous = inner.mirror(&data, LinuxClipboardKind::Primary)?;
+		let result = inner.write(data, options.selection, options.wait, confirm);
+		if result.is_err() {
+			inner.restore(LinuxClipboardKind::Primary, previous);
+		}
+		result
+	}
+
+	fn add_clipboard_exclusions(
+		inner: &Inner,
+		exclude_from_history: bool,
+		data: &mut Vec<ClipboardData>,
+	) {
+		if exclude_from_history {
+			data.push(ClipboardData {
+				bytes: KDE_EXCLUSION_HINT.to_vec(),
+				format: inner.atoms.KDE_EXCLUSION,
+				producer: None,
+			});
+		}
+	}
+}
+
+/// Blocks until the X server sent more data over `conn`, or `deadline` is reached.
+///
+/// This must only be called once all of the events that were already received have been handled.
+fn wait_for_server(conn: &RustConnection, deadline: Instant) -> Result<()> {
+	use rustix::{
+		event::{poll, PollFd, PollFlags},
+		io::Errno,
+	};
+
+	let remaining = deadline.saturating_duration_since(Instant::now());
+	// Round up, so that the deadline has passed once `poll` times out.
+	let timeout_ms = i32::try_from((remaining.as_micros() + 999) / 1000).unwrap_or(i32::MAX);
+	let mut fds = [PollFd::new(conn.stream(), PollFlags::IN)];
+	match poll(&mut fds, timeout_ms) {
+		Ok(_) | Err(Errno::INTR) => Ok(()),
+		Err(e) => Err(into_unknown(std::io::Error::from(e))),
 	}
 }
 
+/// Decodes text in ISO Latin-1, the encoding of the `STRING` target.
+///
+/// Every Latin-1 byte has the same value as the Unicode code point it represents.
+/// See: https://stackoverflow.com/questions/28169745/what-are-the-options-to-convert-iso-8859-1-latin-1-to-a-string-utf-8
+fn decode_latin1(bytes: &[u8]) -> String {
+	bytes.iter().map(|&c| c as char).collect()
+}
+
+/// Decodes text in the `COMPOUND_TEXT` encoding.
+///
+/// Compound text starts out with ASCII and the right half of ISO Latin-1 designated, so text
+/// without any escape sequences decodes just like `STRING`. Besides the escape sequences which
+/// designate these two character sets again, the UTF-8 extension segments (`ESC % G` to `ESC % @`)
+/// are understood.
+///
+/// This is not a full ISO 2022 decoder: text containing any other character set, such as the
+/// CJK ones, fails with [`Error::ConversionFailure`].
+///
+/// See the X Consortium Standard "Compound Text Encoding" for the details of the format.
+fn decode_compound_text(bytes: &[u8]) -> Result<String> {
+	const ESC: u8 = 0x1B;
+	const UTF8_END: [u8; 3] = [ESC, b'%', b'@'];
+
+	let mut text = String::with_capacity(bytes.len());
+	let mut rest = bytes;
+	while let Some((&byte, tail)) = rest.split_first() {
+		if byte != ESC {
+			text.push(byte as char);
+			rest = tail;
+			continue;
+		}
+
+		match tail {
+			// ASCII into GL, or the right half of Latin-1 into GR.
+			[b'(', b'B', tail @ ..] | [b'-', b'A', tail @ ..] => rest = tail,
+			// A UTF-8 segment, which lasts until the sequence that reverts back to ISO 2022.
+			[b'%', b'G', tail @ ..] => {
+				let end = tail.windows(UTF8_END.len()).position(|w| w == UTF8_END);
+				let segment = &tail[..end.unwrap_or(tail.len())];
+				text.push_str(std::str::from_utf8(segment).map_err(|_| Error::ConversionFailure)?);
+				rest = match end {
+					Some(end) => &tail[end + UTF8_END.len()..],
+					None => &[],
+				};
+			}
+			_ => return Err(Error::ConversionFailure),
+		}
+	}
+
+	Ok(text)
+}
+
 impl Drop for Clipboard {
 	fn drop(&mut self) {
 		// There are always at least 3 owners:
 		// the global, the server thread, and one `Clipboard::inner`
 		const MIN_OWNERS: usize = 3;
 
+		// A clipboard which was never used didn't connect, so there's nothing to clean up.
+		let inner = match self.inner.get_mut() {
+			Some(inner) => inner,
+			None => return,
+		};
+
 		// We start with locking the global guard to prevent race
 		// conditions below.
 		let mut global_cb = CLIPBOARD.lock();
-		if Arc::strong_count(&self.inner) == MIN_OWNERS {
+		if Arc::strong_count(inner) == MIN_OWNERS {
 			// If the are the only owners of the clipboard are ourselves and
 			// the global object, then we should destroy the global object,
 			// and send the data to the clipboard manager
 
-			if let Err(e) = self.inner.ask_clipboard_manager_to_request_our_data() {
+			if self.skip_handover {
+				trace!("Skipping the clipboard manager handover, as configured.");
+			} else if let Err(e) =
+				inner.ask_clipboard_manager_to_request_our_data(self.handover_timeout)
+			{
 				error!("Could not hand the clipboard data over to the clipboard manager: {}", e);
 			}
 			let global_cb = global_cb.take();
-			if let Err(e) = self.inner.server.conn.destroy_window(self.inner.server.win_id) {
+			if let Err(e) = inner.server.conn.destroy_window(inner.server.win_id) {
 				error!("Failed to destroy the clipboard window. Error: {}", e);
 				return;
 			}
-			if let Err(e) = self.inner.server.conn.flush() {
+			if let Err(e) = inner.server.conn.flush() {
 				error!("Failed to flush the clipboard window. Error: {}", e);
 				return;
 			}
@@ -987,3 +2081,415 @@ impl Drop for Clipboard {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn clipboard_connects_on_first_use() {
+		// No connection is made to the display, which doesn't exist, nor is a thread spawned.
+		let config = ClipboardConfig {
+			x11_display: Some(":arboard-missing".into()),
+			..ClipboardConfig::default()
+		};
+		let clipboard = Clipboard::new(&config).unwrap();
+		assert!(clipboard.inner.lock().is_none());
+		drop(clipboard);
+	}
+
+	#[test]
+	fn string_is_decoded_as_latin1() {
+		assert_eq!(decode_latin1(b"caf\xE9"), "café");
+	}
+
+	#[test]
+	fn compound_text_is_decoded() {
+		assert_eq!(decode_compound_text(b"caf\xE9").unwrap(), "café");
+		assert_eq!(decode_compound_text(b"\x1B-Acaf\xE9\x1B(B!").unwrap(), "café!");
+		assert_eq!(decode_compound_text(b"a\x1B%G\xF0\x9F\x90\x93\x1B%@\xE9").unwrap(), "a🐓é");
+
+		// Japanese (JIS X 0208) isn't supported.
+		assert!(matches!(decode_compound_text(b"\x1B$(B\x30\x21"), Err(Error::ConversionFailure)));
+	}
+
+	/// Transfers 300MB of text between two connections, which requires INCR.
+	///
+	/// This is slow, so it only runs when `ARBOARD_TEST_LARGE_DATA` is set.
+	#[test]
+	fn large_text_is_sent_incrementally() {
+		if std::env::var_os("ARBOARD_TEST_LARGE_DATA").is_none() {
+			return;
+		}
+
+		let owner = Arc::new(Inner::new(None, None, None).unwrap());
+		let server = Arc::clone(&owner);
+		std::thread::spawn(move || {
+			if let Err(error) = serve_requests(server) {
+				error!("Worker thread errored with: {}", error);
+			}
+		});
+
+		let text = "arboard ".repeat(300 * 1024 * 1024 / 8);
+		let data = ClipboardData {
+			bytes: text.clone().into_bytes(),
+			format: owner.atoms.UTF8_STRING,
+			producer: None,
+		};
+		owner.write(vec![data], LinuxClipboardKind::Clipboard, WaitConfig::None, None).unwrap();
+
+		// A separate connection doesn't own the selection, so it has to go through the X server.
+		let reader = Inner::new(None, None, None).unwrap();
+		let read = reader
+			.read(
+				&[reader.atoms.UTF8_STRING],
+				LinuxClipboardKind::Clipboard,
+				LONG_TIMEOUT_DUR,
+				None,
+				None,
+			)
+			.unwrap();
+		assert!(read.bytes == text.as_bytes());
+
+		// The incremental transfer is abandoned once it grows past the limit.
+		let limited = Inner::new(None, None, None).unwrap();
+		let result = limited.read(
+			&[limited.atoms.UTF8_STRING],
+			LinuxClipboardKind::Clipboard,
+			LONG_TIMEOUT_DUR,
+			Some(ReadLimit::Reject(1024 * 1024)),
+			None,
+		);
+		assert!(matches!(result, Err(Error::TooLarge)));
+
+		// Or it's stopped, keeping what arrived up to the limit.
+		let truncated = Inner::new(None, None, None).unwrap();
+		let read = truncated
+			.read(
+				&[truncated.atoms.UTF8_STRING],
+				LinuxClipboardKind::Clipboard,
+				LONG_TIMEOUT_DUR,
+				Some(ReadLimit::Truncate(1024 * 1024)),
+				None,
+			)
+			.unwrap();
+		assert!(read.bytes == text.as_bytes()[..1024 * 1024]);
+	}
+
+	/// Dropping the last `Clipboard` after waiting for another application to take the
+	/// clipboard over doesn't wait for a clipboard manager.
+	///
+	/// This needs an X server, so it only runs when `DISPLAY` is set.
+	#[test]
+	fn drop_after_wait_skips_handover() {
+		if std::env::var_os("DISPLAY").is_none() {
+			return;
+		}
+
+		let config =
+			ClipboardConfig { x11_handover_timeout: Some(LONG_TIMEOUT_DUR), ..Default::default() };
+		let clipboard = Clipboard::new(&config).unwrap();
+		let other = std::thread::spawn(|| {
+			std::thread::sleep(Duration::from_millis(100));
+			let other = Inner::new(None, None, None).unwrap();
+			let data = ClipboardData {
+				bytes: b"b".to_vec(),
+				format: other.atoms.UTF8_STRING,
+				producer: None,
+			};
+			other.write(vec![data], LinuxClipboardKind::Clipboard, WaitConfig::None, None).unwrap();
+			other
+		});
+		clipboard
+			.set_text(
+				Cow::Borrowed("a"),
+				None,
+				SetOptions {
+					selection: LinuxClipboardKind::Clipboard,
+					wait: WaitConfig::Forever,
+					exclude_from_history: false,
+					confirm: false,
+					mirror_primary: false,
+				},
+			)
+			.unwrap();
+
+		let start = Instant::now();
+		drop(clipboard);
+		assert!(start.elapsed() < LONG_TIMEOUT_DUR / 2);
+		drop(other.join().unwrap());
+	}
+
+	/// Reads two INCR transfers into the same window at once, finishing the first one before
+	/// receiving any more of the second.
+	///
+	/// This needs an X server, so it only runs when `DISPLAY` is set.
+	#[test]
+	fn concurrent_incr_transfers_to_one_window_complete() {
+		if std::env::var_os("DISPLAY").is_none() {
+			return;
+		}
+
+		let owner = Arc::new(Inner::new(None, None, None).unwrap());
+		let server = Arc::clone(&owner);
+		std::thread::spawn(move || {
+			if let Err(error) = serve_requests(server) {
+				error!("Worker thread errored with: {}", error);
+			}
+		});
+
+		let len = owner.max_property_len() + 1;
+		let texts = [vec![b'a'; len], vec![b'b'; len]];
+		let formats = [owner.atoms.UTF8_STRING, owner.atoms.TEXT_MIME_UNKNOWN];
+		let data = texts
+			.iter()
+			.zip(formats)
+			.map(|(bytes, format)| ClipboardData { bytes: bytes.clone(), format, producer: None })
+			.collect();
+		owner.write(data, LinuxClipboardKind::Clipboard, WaitConfig::None, None).unwrap();
+
+		let requestor = XContext::new(None, None).unwrap();
+		let conn = &requestor.conn;
+		let second_property = conn.intern_atom(false, b"ARBOARD_SECOND").unwrap().reply().unwrap();
+		let properties = [owner.atoms.ARBOARD_CLIPBOARD, second_property.atom];
+
+		// Takes the property off the window, waiting until it's there.
+		let take = |property: Atom| {
+			let deadline = Instant::now() + LONG_TIMEOUT_DUR;
+			loop {
+				let reply = conn
+					.get_property(true, requestor.win_id, property, AtomEnum::ANY, 0, u32::MAX / 4)
+					.unwrap()
+					.reply()
+					.unwrap();
+				if reply.type_ != NONE {
+					return reply;
+				}
+				assert!(Instant::now() < deadline, "the transfer stalled");
+				std::thread::sleep(Duration::from_millis(1));
+			}
+		};
+
+		for (format, property) in formats.into_iter().zip(properties) {
+			conn.convert_selection(
+				requestor.win_id,
+				owner.atoms.CLIPBOARD,
+				format,
+				property,
+				Time::CURRENT_TIME,
+			)
+			.unwrap();
+		}
+		conn.flush().unwrap();
+		// Deleting the INCR properties starts both transfers.
+		for property in properties {
+			assert_eq!(take(property).type_, owner.atoms.INCR);
+		}
+		for (text, property) in texts.iter().zip(properties) {
+			let mut received = Vec::new();
+			loop {
+				let segment = take(property);
+				if segment.value.is_empty() {
+					break;
+				}
+				received.extend(segment.value);
+			}
+			assert!(&received == text);
+		}
+	}
+
+	/// Plays the part of an owner which starts an INCR transfer over after sending a segment.
+	///
+	/// This needs an X server, so it only runs when `DISPLAY` is set.
+	#[test]
+	fn restarted_incr_transfer_is_read_from_the_start() {
+		if std::env::var_os("DISPLAY").is_none() {
+			return;
+		}
+
+		let owner = XContext::new(None, None).unwrap();
+		let atoms = Atoms::new(&owner.conn).unwrap().reply().unwrap();
+		owner.conn.set_selection_owner(owner.win_id, atoms.CLIPBOARD, Time::CURRENT_TIME).unwrap();
+		owner.conn.flush().unwrap();
+
+		let owner = std::thread::spawn(move || {
+			let request = loop {
+				if let Event::SelectionRequest(event) = owner.conn.wait_for_event().unwrap() {
+					break event;
+				}
+			};
+			owner
+				.conn
+				.change_window_attributes(
+					request.requestor,
+					&ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+				)
+				.unwrap();
+
+			let wait_for_delete = || loop {
+				match owner.conn.wait_for_event().unwrap() {
+					Event::PropertyNotify(event)
+						if event.atom == request.property && event.state == Property::DELETE =>
+					{
+						break
+					}
+					_ => {}
+				}
+			};
+			let start = || {
+				owner
+					.conn
+					.change_property32(
+						PropMode::REPLACE,
+						request.requestor,
+						request.property,
+						atoms.INCR,
+						&[11],
+					)
+					.unwrap();
+				let event = SelectionNotifyEvent {
+					response_type: SELECTION_NOTIFY_EVENT,
+					sequence: 0,
+					time: request.time,
+					requestor: request.requestor,
+					selection: request.selection,
+					target: request.target,
+					property: request.property,
+				};
+				owner
+					.conn
+					.send_event(false, request.requestor, EventMask::NO_EVENT, event)
+					.unwrap();
+				owner.conn.flush().unwrap();
+				wait_for_delete();
+			};
+			let send = |segment: &[u8]| {
+				owner
+					.conn
+					.change_property8(
+						PropMode::REPLACE,
+						request.requestor,
+						request.property,
+						request.target,
+						segment,
+					)
+					.unwrap();
+				owner.conn.flush().unwrap();
+			};
+
+			start();
+			send(b"stale");
+			wait_for_delete();
+			start();
+			for segment in [&b"hello "[..], b"world"] {
+				send(segment);
+				wait_for_delete();
+			}
+			send(b"");
+		});
+
+		let reader = Inner::new(None, None, None).unwrap();
+		let mut progress = Vec::new();
+		let read = reader
+			.read(
+				&[reader.atoms.UTF8_STRING],
+				LinuxClipboardKind::Clipboard,
+				LONG_TIMEOUT_DUR,
+				None,
+				Some(&mut |received, size| progress.push((received, size))),
+			)
+			.unwrap();
+		assert_eq!(read.bytes, b"hello world");
+		assert_eq!(progress, [(5, Some(11)), (6, Some(11)), (11, Some(11))]);
+		owner.join().unwrap();
+	}
+
+	/// Plays the part of a clipboard manager which, like Klipper, reports a failure right away
+	/// and only then requests the formats one after another.
+	///
+	/// This takes over the `CLIPBOARD_MANAGER` selection, so it only runs when
+	/// `ARBOARD_TEST_HANDOVER` is set.
+	#[test]
+	fn handover_waits_for_every_format() {
+		if std::env::var_os("ARBOARD_TEST_HANDOVER").is_none() {
+			return;
+		}
+
+		let manager = Inner::new(None, None, None).unwrap();
+		manager
+			.server
+			.conn
+			.set_selection_owner(
+				manager.server.win_id,
+				manager.atoms.CLIPBOARD_MANAGER,
+				Time::CURRENT_TIME,
+			)
+			.unwrap();
+		manager.server.conn.flush().unwrap();
+
+		let owner = Arc::new(Inner::new(None, None, None).unwrap());
+		let server = Arc::clone(&owner);
+		std::thread::spawn(move || {
+			if let Err(error) = serve_requests(server) {
+				error!("Worker thread errored with: {}", error);
+			}
+		});
+
+		let png = b"\x89PNG\r\n\x1a\nnot really an image".to_vec();
+		let data = vec![
+			ClipboardData {
+				bytes: b"a caption".to_vec(),
+				format: owner.atoms.UTF8_STRING,
+				producer: None,
+			},
+			ClipboardData { bytes: png.clone(), format: owner.atoms.PNG_MIME, producer: None },
+		];
+		owner.write(data, LinuxClipboardKind::Clipboard, WaitConfig::None, None).unwrap();
+
+		let manager = std::thread::spawn(move || {
+			let request = loop {
+				match manager.server.conn.wait_for_event().unwrap() {
+					Event::SelectionRequest(event)
+						if event.target == manager.atoms.SAVE_TARGETS =>
+					{
+						break event
+					}
+					_ => {}
+				}
+			};
+			let notify = |property| {
+				let event = SelectionNotifyEvent {
+					response_type: SELECTION_NOTIFY_EVENT,
+					sequence: 0,
+					time: request.time,
+					requestor: request.requestor,
+					selection: request.selection,
+					target: request.target,
+					property,
+				};
+				manager
+					.server
+					.conn
+					.send_event(false, request.requestor, EventMask::NO_EVENT, event)
+					.unwrap();
+				manager.server.conn.flush().unwrap();
+			};
+
+			notify(NONE);
+			let mut saved = Vec::new();
+			for format in [manager.atoms.UTF8_STRING, manager.atoms.PNG_MIME] {
+				let data = manager
+					.read(&[format], LinuxClipboardKind::Clipboard, LONG_TIMEOUT_DUR, None, None)
+					.unwrap();
+				saved.push(data.bytes);
+			}
+			saved
+		});
+
+		owner.ask_clipboard_manager_to_request_our_data(LONG_TIMEOUT_DUR).unwrap();
+		assert_eq!(*owner.handover_state.lock(), ManagerHandoverState::Finished);
+
+		let saved = manager.join().unwrap();
+		assert_eq!(saved, [b"a caption".to_vec(), png]);
+	}
+}
@@ -8,22 +8,45 @@ the Apache 2.0 or the MIT license at the licensee's choice. The terms
 and conditions of the chosen license apply to this file.
 */
 
+use crate::common::{private, ClipboardConfig, DataProducer, Error, SelectionKind};
 #[cfg(feature = "image-data")]
-use crate::common::ImageData;
-use crate::common::{private, Error};
+use crate::common::{ImageCacheKey, ImageData, ImageDataWithMeta};
 use objc2::{
 	msg_send_id,
 	rc::{autoreleasepool, Id},
 	runtime::ProtocolObject,
 	ClassType,
 };
-use objc2_app_kit::{NSPasteboard, NSPasteboardTypeHTML, NSPasteboardTypeString};
-use objc2_foundation::{ns_string, NSArray, NSString};
+use objc2_app_kit::{
+	NSPasteboard, NSPasteboardItem, NSPasteboardNameFind, NSPasteboardTypeFileURL,
+	NSPasteboardTypeHTML, NSPasteboardTypePNG, NSPasteboardTypeString,
+};
+use objc2_foundation::{ns_string, NSArray, NSData, NSString, NSURL};
 use std::{
 	borrow::Cow,
 	panic::{RefUnwindSafe, UnwindSafe},
 };
 
+/// The file format in which images are placed on the pasteboard.
+///
+/// See [`SetExtApple::image_representation`].
+#[cfg(feature = "image-data")]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ImageRepresentation {
+	/// A TIFF file, under `public.tiff`.
+	Tiff,
+
+	/// A PNG file, under `public.png`.
+	Png,
+
+	/// A JPEG file, under `public.jpeg`. The quality ranges from `0.0`, for the smallest files,
+	/// to `1.0` for the least lossy compression.
+	///
+	/// JPEG has no alpha channel, so transparent pixels become opaque.
+	Jpeg(f64),
+}
+
 /// Returns an NSImage object on success.
 #[cfg(feature = "image-data")]
 fn image_from_pixels(
@@ -87,6 +110,41 @@ fn image_from_pixels(
 	Ok(image)
 }
 
+/// Encodes `image` as a file in the chosen representation, and returns it along with the
+/// pasteboard type it belongs under.
+#[cfg(feature = "image-data")]
+fn encode_image(
+	image: &objc2_app_kit::NSImage,
+	representation: ImageRepresentation,
+) -> Result<(Id<NSData>, &'static NSString), Error> {
+	use objc2::runtime::AnyObject;
+	use objc2_app_kit::{
+		NSBitmapImageFileType, NSBitmapImageRep, NSImageCompressionFactor, NSPasteboardTypeTIFF,
+	};
+	use objc2_foundation::{NSDictionary, NSNumber};
+
+	let tiff = unsafe { image.TIFFRepresentation() }.ok_or(Error::ConversionFailure)?;
+	let (file_type, pasteboard_type, properties) = match representation {
+		ImageRepresentation::Tiff => return Ok((tiff, unsafe { NSPasteboardTypeTIFF })),
+		ImageRepresentation::Png => {
+			(NSBitmapImageFileType::PNG, unsafe { NSPasteboardTypePNG }, NSDictionary::new())
+		}
+		ImageRepresentation::Jpeg(quality) => {
+			let quality = NSNumber::new_f64(quality.clamp(0.0, 1.0));
+			let quality: Id<AnyObject> = Id::into_super(Id::into_super(Id::into_super(quality)));
+			let properties =
+				NSDictionary::from_vec(&[unsafe { NSImageCompressionFactor }], vec![quality]);
+			(NSBitmapImageFileType::JPEG, ns_string!("public.jpeg"), properties)
+		}
+	};
+
+	let bitmap =
+		unsafe { NSBitmapImageRep::imageRepWithData(&tiff) }.ok_or(Error::ConversionFailure)?;
+	let data = unsafe { bitmap.representationUsingType_properties(file_type, &properties) }
+		.ok_or(Error::ConversionFailure)?;
+	Ok((data, pasteboard_type))
+}
+
 pub(crate) struct Clipboard {
 	pasteboard: Id<NSPasteboard>,
 }
@@ -97,7 +155,14 @@ impl UnwindSafe for Clipboard {}
 impl RefUnwindSafe for Clipboard {}
 
 impl Clipboard {
-	pub(crate) fn new() -> Result<Clipboard, Error> {
+	pub(crate) fn new(config: ClipboardConfig) -> Result<Clipboard, Error> {
+		if let Some(name) = config.macos_pasteboard_name {
+			// `pasteboardWithName` creates the pasteboard if it doesn't exist yet.
+			let pasteboard =
+				unsafe { NSPasteboard::pasteboardWithName(&NSString::from_str(&name)) };
+			return Ok(Clipboard { pasteboard });
+		}
+
 		// Rust only supports 10.7+, while `generalPasteboard` first appeared
 		// in 10.0, so this should always be available.
 		//
@@ -117,6 +182,23 @@ impl Clipboard {
 		}
 	}
 
+	pub(crate) fn primary_selection_supported(&self) -> bool {
+		false
+	}
+
+	pub(crate) fn owner_process_id(&self) -> Result<Option<u32>, Error> {
+		// The pasteboard doesn't record which application wrote to it.
+		Ok(None)
+	}
+
+	pub(crate) fn is_empty(&mut self) -> Result<bool, Error> {
+		Ok(unsafe { self.pasteboard.pasteboardItems() }.map_or(true, |items| items.is_empty()))
+	}
+
+	pub(crate) fn max_request_bytes(&self) -> Option<usize> {
+		None
+	}
+
 	fn clear(&mut self) {
 		unsafe { self.pasteboard.clearContents() };
 	}
@@ -172,105 +254,385 @@ impl Clipboard {
 	// }
 }
 
+/// Returns the error to report when the requested type isn't on the pasteboard, telling an
+/// empty pasteboard apart from one that only holds other types.
+fn missing_format_error(pasteboard: &NSPasteboard) -> Error {
+	match unsafe { pasteboard.pasteboardItems() } {
+		Some(items) if !items.is_empty() => Error::WrongFormat,
+		_ => Error::ContentNotAvailable,
+	}
+}
+
+/// Fails for every selection but the Clipboard, as macOS only has the general pasteboard.
+fn check_selection(selection: SelectionKind) -> Result<(), Error> {
+	match selection {
+		SelectionKind::Clipboard => Ok(()),
+		_ => Err(Error::ClipboardNotSupported),
+	}
+}
+
+/// Returns [`Error::TooLarge`] if `len` bytes are more than the `max_bytes` which a read may
+/// return.
+fn check_size(len: usize, max_bytes: Option<usize>) -> Result<(), Error> {
+	match max_bytes {
+		Some(max) if len > max => Err(Error::TooLarge),
+		_ => Ok(()),
+	}
+}
+
 pub(crate) struct Get<'clipboard> {
 	clipboard: &'clipboard Clipboard,
+	selection: SelectionKind,
+	text_fallback_to_paths: bool,
+	max_bytes: Option<usize>,
 }
 
 impl<'clipboard> Get<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard }
+		Self {
+			clipboard,
+			selection: SelectionKind::Clipboard,
+			text_fallback_to_paths: false,
+			max_bytes: None,
+		}
+	}
+
+	/// Returns a `Get` with the same options, for one more operation.
+	pub(crate) fn reborrow(&mut self) -> Get<'_> {
+		Get {
+			clipboard: self.clipboard,
+			selection: self.selection,
+			text_fallback_to_paths: self.text_fallback_to_paths,
+			max_bytes: self.max_bytes,
+		}
+	}
+
+	pub(crate) fn selection(mut self, selection: SelectionKind) -> Self {
+		self.selection = selection;
+		self
+	}
+
+	pub(crate) fn max_bytes(mut self, max_bytes: usize) -> Self {
+		self.max_bytes = Some(max_bytes);
+		self
 	}
 
 	pub(crate) fn text(self) -> Result<String, Error> {
+		check_selection(self.selection)?;
+
 		// XXX: There does not appear to be an alternative for obtaining text without the need for
 		// autorelease behavior.
 		autoreleasepool(|_| {
 			// XXX: We explicitly use `pasteboardItems` and not `stringForType` since the latter will concat
 			// multiple strings, if present, into one and return it instead of reading just the first which is `arboard`'s
 			// historical behavior.
-			let contents =
-				unsafe { self.clipboard.pasteboard.pasteboardItems() }.ok_or_else(|| {
-					Error::Unknown {
-						description: String::from("NSPasteboard#pasteboardItems errored"),
-					}
-				})?;
+			let contents = unsafe { self.clipboard.pasteboard.pasteboardItems() }
+				.ok_or_else(|| Error::unknown("NSPasteboard#pasteboardItems errored"))?;
 
+			let mut paths = Vec::new();
 			for item in contents {
 				if let Some(string) = unsafe { item.stringForType(NSPasteboardTypeString) } {
+					check_size(string.len(), self.max_bytes)?;
 					return Ok(string.to_string());
 				}
+
+				if self.text_fallback_to_paths {
+					if let Some(path) = unsafe { item.stringForType(NSPasteboardTypeFileURL) }
+						.and_then(|url| file_url_to_path(&url))
+					{
+						paths.push(path);
+					}
+				}
 			}
 
-			Err(Error::ContentNotAvailable)
+			if paths.is_empty() {
+				Err(missing_format_error(&self.clipboard.pasteboard))
+			} else {
+				Ok(paths.join("\n"))
+			}
 		})
 	}
 
+	pub(crate) fn text_preview(self, max_chars: usize) -> Result<String, Error> {
+		// The pasteboard hands out the whole string at once, so there's no prefix to read.
+		let _ = max_chars;
+		self.text()
+	}
+
+	fn available_types(self) -> Result<Vec<String>, Error> {
+		check_selection(self.selection)?;
+
+		autoreleasepool(|_| {
+			let types = unsafe { self.clipboard.pasteboard.types() }
+				.ok_or_else(|| Error::unknown("NSPasteboard#types errored"))?;
+			Ok(types.iter().map(|ty| ty.to_string()).collect())
+		})
+	}
+
+	fn data_for_type(self, uti: &str) -> Result<Vec<u8>, Error> {
+		check_selection(self.selection)?;
+
+		autoreleasepool(|_| {
+			let pasteboard = &self.clipboard.pasteboard;
+			let data = unsafe { pasteboard.dataForType(&NSString::from_str(uti)) }
+				.ok_or_else(|| missing_format_error(pasteboard))?;
+			check_size(data.bytes().len(), self.max_bytes)?;
+			Ok(data.bytes().to_vec())
+		})
+	}
+
+	fn find_pasteboard_text(self) -> Result<String, Error> {
+		check_selection(self.selection)?;
+
+		autoreleasepool(|_| {
+			// The Find pasteboard is shared by all applications, regardless of the pasteboard
+			// which this `Clipboard` was configured with.
+			let pasteboard = unsafe { NSPasteboard::pasteboardWithName(NSPasteboardNameFind) };
+			let string = unsafe { pasteboard.stringForType(NSPasteboardTypeString) }
+				.ok_or_else(|| missing_format_error(&pasteboard))?;
+			check_size(string.len(), self.max_bytes)?;
+			Ok(string.to_string())
+		})
+	}
+
+	pub(crate) fn image_png(self) -> Result<Vec<u8>, Error> {
+		check_selection(self.selection)?;
+
+		autoreleasepool(|_| {
+			let data = unsafe { self.clipboard.pasteboard.dataForType(NSPasteboardTypePNG) }
+				.ok_or_else(|| missing_format_error(&self.clipboard.pasteboard))?;
+			check_size(data.bytes().len(), self.max_bytes)?;
+			Ok(data.bytes().to_vec())
+		})
+	}
+
+	pub(crate) fn svg(self) -> Result<String, Error> {
+		check_selection(self.selection)?;
+
+		autoreleasepool(|_| {
+			let pasteboard = &self.clipboard.pasteboard;
+			let data = unsafe { pasteboard.dataForType(ns_string!("public.svg-image")) }
+				.ok_or_else(|| missing_format_error(pasteboard))?;
+			check_size(data.bytes().len(), self.max_bytes)?;
+			String::from_utf8(data.bytes().to_vec()).map_err(|_| Error::ConversionFailure)
+		})
+	}
+
+	pub(crate) fn image_raw(self) -> Result<(Vec<u8>, String), Error> {
+		use objc2_app_kit::NSPasteboardTypeTIFF;
+
+		check_selection(self.selection)?;
+
+		autoreleasepool(|_| {
+			let pasteboard = &self.clipboard.pasteboard;
+			let (data, mime_type) =
+				if let Some(data) = unsafe { pasteboard.dataForType(NSPasteboardTypePNG) } {
+					(data, "image/png")
+				} else if let Some(data) = unsafe { pasteboard.dataForType(NSPasteboardTypeTIFF) } {
+					(data, "image/tiff")
+				} else {
+					return Err(missing_format_error(pasteboard));
+				};
+			check_size(data.bytes().len(), self.max_bytes)?;
+			Ok((data.bytes().to_vec(), mime_type.to_owned()))
+		})
+	}
+
+	/// Returns the key of the image to read, made of the pasteboard's change count, which
+	/// changes whenever its contents are replaced.
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_cache_key(&self) -> Option<ImageCacheKey> {
+		check_selection(self.selection).ok()?;
+		let count = unsafe { self.clipboard.pasteboard.changeCount() };
+		Some(ImageCacheKey { change_key: count as u64, max_bytes: self.max_bytes })
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_dpi(self) -> Result<ImageDataWithMeta, Error> {
+		self.image().map(ImageDataWithMeta::new)
+	}
+
 	#[cfg(feature = "image-data")]
 	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
+		use image::{codecs::tiff::TiffDecoder, DynamicImage};
 		use objc2_app_kit::NSPasteboardTypeTIFF;
 		use std::io::Cursor;
 
+		check_selection(self.selection)?;
+
 		// XXX: There does not appear to be an alternative for obtaining images without the need for
 		// autorelease behavior.
 		let image = autoreleasepool(|_| {
-			let image_data = unsafe { self.clipboard.pasteboard.dataForType(NSPasteboardTypeTIFF) }
-				.ok_or(Error::ContentNotAvailable)?;
+			let pasteboard = &self.clipboard.pasteboard;
+			let Some(image_data) = (unsafe { pasteboard.dataForType(NSPasteboardTypeTIFF) }) else {
+				// Some applications only place a GIF file, which may be animated.
+				let gif = unsafe { pasteboard.dataForType(ns_string!("com.compuserve.gif")) }
+					.ok_or_else(|| missing_format_error(pasteboard))?;
+				check_size(gif.bytes().len(), self.max_bytes)?;
+				return crate::common::decode_gif(gif.bytes()).map(|image| (image, false));
+			};
+			check_size(image_data.bytes().len(), self.max_bytes)?;
+
+			let premultiplied = tiff_has_associated_alpha(image_data.bytes());
+			// Only the first image of a multi-page TIFF is decoded.
+			let decoder = TiffDecoder::new(Cursor::new(image_data.bytes()))
+				.map_err(|_| Error::ConversionFailure)?;
+			let rgba = DynamicImage::from_decoder(decoder)
+				.map_err(|_| Error::ConversionFailure)?
+				.into_rgba8();
+			Ok((ImageData::from(rgba), premultiplied))
+		})?;
+		let (mut image, premultiplied) = image;
 
-			let data = Cursor::new(image_data.bytes());
+		// `ImageData` always holds straight alpha.
+		if premultiplied {
+			image.unpremultiply_alpha();
+		}
+		Ok(image)
+	}
+}
 
-			let reader = image::io::Reader::with_format(data, image::ImageFormat::Tiff);
-			reader.decode().map_err(|_| Error::ConversionFailure)
-		})?;
+/// Returns whether the TIFF file's alpha channel is associated, meaning that it's premultiplied
+/// into the color channels.
+///
+/// This is stored in the `ExtraSamples` tag of the first image.
+#[cfg(feature = "image-data")]
+fn tiff_has_associated_alpha(tiff: &[u8]) -> bool {
+	const EXTRA_SAMPLES: u16 = 338;
+	const ASSOCIATED_ALPHA: u16 = 1;
+
+	let big_endian = match tiff.get(..2) {
+		Some(b"II") => false,
+		Some(b"MM") => true,
+		_ => return false,
+	};
+	let u16_at = |offset: usize| {
+		let bytes = tiff.get(offset..offset + 2)?;
+		let bytes = [bytes[0], bytes[1]];
+		Some(if big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+	};
+	let u32_at = |offset: usize| {
+		let bytes = tiff.get(offset..offset + 4)?;
+		let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+		Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+	};
 
-		let rgba = image.into_rgba8();
-		let (width, height) = rgba.dimensions();
+	let Some(ifd) = u32_at(4).map(|offset| offset as usize) else {
+		return false;
+	};
+	let entry_count = u16_at(ifd).unwrap_or(0) as usize;
+	(0..entry_count).any(|i| {
+		// Each entry is 12 bytes: the tag, the value type, the value count and the value itself,
+		// which is stored inline when it fits.
+		let entry = ifd + 2 + i * 12;
+		u16_at(entry) == Some(EXTRA_SAMPLES) && u16_at(entry + 8) == Some(ASSOCIATED_ALPHA)
+	})
+}
 
-		Ok(ImageData {
-			width: width as usize,
-			height: height as usize,
-			bytes: rgba.into_raw().into(),
-		})
-	}
+/// Converts a `file://` URL, as found on the pasteboard, into a filesystem path.
+fn file_url_to_path(url: &NSString) -> Option<String> {
+	let url = unsafe { NSURL::URLWithString(url) }?;
+	unsafe { url.path() }.map(|path| path.to_string())
 }
 
 pub(crate) struct Set<'clipboard> {
 	clipboard: &'clipboard mut Clipboard,
+	selection: SelectionKind,
 	exclude_from_history: bool,
+	transient: bool,
+	#[cfg(feature = "image-data")]
+	image_representation: Option<ImageRepresentation>,
 }
 
 impl<'clipboard> Set<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard, exclude_from_history: false }
+		Self {
+			clipboard,
+			selection: SelectionKind::Clipboard,
+			exclude_from_history: false,
+			transient: false,
+			#[cfg(feature = "image-data")]
+			image_representation: None,
+		}
+	}
+
+	pub(crate) fn selection(mut self, selection: SelectionKind) -> Self {
+		self.selection = selection;
+		self
 	}
 
 	pub(crate) fn text(self, data: Cow<'_, str>) -> Result<(), Error> {
+		check_selection(self.selection)?;
 		self.clipboard.clear();
 
 		let string_array =
 			NSArray::from_vec(vec![ProtocolObject::from_id(NSString::from_str(&data))]);
 		let success = unsafe { self.clipboard.pasteboard.writeObjects(&string_array) };
 
-		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
 
 		if success {
 			Ok(())
 		} else {
-			Err(Error::Unknown { description: "NSPasteboard#writeObjects: returned false".into() })
+			Err(Error::unknown("NSPasteboard#writeObjects: returned false"))
+		}
+	}
+
+	pub(crate) fn text_keeping_others(self, data: Cow<'_, str>) -> Result<(), Error> {
+		check_selection(self.selection)?;
+
+		// The plain text types which the new text replaces, so that no stale text is copied.
+		const TEXT_TYPES: &[&str] = &[
+			"public.utf8-plain-text",
+			"public.utf16-plain-text",
+			"public.utf16-external-plain-text",
+			"public.plain-text",
+			"NSStringPboardType",
+		];
+
+		// Pasteboard items can't be changed once written, so they are copied into new ones. The
+		// text is added to the first item, like `writeObjects` does with a single string.
+		let success = autoreleasepool(|_| {
+			let mut items = Vec::new();
+			if let Some(existing) = unsafe { self.clipboard.pasteboard.pasteboardItems() } {
+				for item in existing {
+					let copy = unsafe { NSPasteboardItem::new() };
+					let types = unsafe { item.types() };
+					for ty in types.iter() {
+						if TEXT_TYPES.contains(&ty.to_string().as_str()) {
+							continue;
+						}
+						if let Some(bytes) = unsafe { item.dataForType(ty) } {
+							unsafe { copy.setData_forType(&bytes, ty) };
+						}
+					}
+					items.push(copy);
+				}
+			}
+			if items.is_empty() {
+				items.push(unsafe { NSPasteboardItem::new() });
+			}
+			let text = NSString::from_str(&data);
+			unsafe { items[0].setString_forType(&text, NSPasteboardTypeString) };
+
+			self.clipboard.clear();
+			let items: Vec<_> = items.into_iter().map(ProtocolObject::from_id).collect();
+			unsafe { self.clipboard.pasteboard.writeObjects(&NSArray::from_vec(items)) }
+		});
+
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#writeObjects: returned false"))
 		}
 	}
 
 	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
+		check_selection(self.selection)?;
 		self.clipboard.clear();
-		// Text goes to the clipboard as UTF-8 but may be interpreted as Windows Latin 1.
-		// This wrapping forces it to be interpreted as UTF-8.
-		//
-		// See:
-		// https://bugzilla.mozilla.org/show_bug.cgi?id=466599
-		// https://bugs.chromium.org/p/chromium/issues/detail?id=11957
-		let html = format!(
-			r#"<html><head><meta http-equiv="content-type" content="text/html; charset=utf-8"></head><body>{html}</body></html>"#,
-		);
-		let html_nss = NSString::from_str(&html);
+		let html_nss = NSString::from_str(&utf8_html_document(&html));
 		// Make sure that we pass a pointer to the string and not the object itself.
 		let mut success =
 			unsafe { self.clipboard.pasteboard.setString_forType(&html_nss, NSPasteboardTypeHTML) };
@@ -284,36 +646,184 @@ impl<'clipboard> Set<'clipboard> {
 			}
 		}
 
-		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#writeObjects: returned false"))
+		}
+	}
+
+	fn html_keep_text(self, html: Cow<'_, str>) -> Result<(), Error> {
+		check_selection(self.selection)?;
+		// The pasteboard isn't cleared, so that the other types on it are left alone. Data can
+		// only be set for a declared type though, which the HTML type isn't when the contents
+		// were written by another application, so it's declared first.
+		let pasteboard = &self.clipboard.pasteboard;
+		let html_type = NSArray::from_slice(&[unsafe { NSPasteboardTypeHTML }]);
+		let is_empty = unsafe { pasteboard.types() }.map_or(true, |types| types.is_empty());
+		let change_count = if is_empty {
+			unsafe { pasteboard.declareTypes_owner(&html_type, None) }
+		} else {
+			unsafe { pasteboard.addTypes_owner(&html_type, None) }
+		};
+		let html_nss = NSString::from_str(&utf8_html_document(&html));
+		let success = change_count != 0
+			&& unsafe { pasteboard.setString_forType(&html_nss, NSPasteboardTypeHTML) };
+
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#setString:forType: returned false"))
+		}
+	}
+
+	fn data_for_type(self, uti: &str, bytes: &[u8]) -> Result<(), Error> {
+		check_selection(self.selection)?;
+		self.clipboard.clear();
+
+		let data = NSData::with_bytes(bytes);
+		let success = unsafe {
+			self.clipboard.pasteboard.setData_forType(Some(&data), &NSString::from_str(uti))
+		};
+
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#setData:forType: returned false"))
+		}
+	}
+
+	fn text_as_type(self, uti: &str, text: &str) -> Result<(), Error> {
+		check_selection(self.selection)?;
+		self.clipboard.clear();
+
+		let text = NSString::from_str(text);
+		let success =
+			unsafe { self.clipboard.pasteboard.setString_forType(&text, &NSString::from_str(uti)) };
+
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#setString:forType: returned false"))
+		}
+	}
+
+	pub(crate) fn image_png(self, png: Cow<'_, [u8]>) -> Result<(), Error> {
+		check_selection(self.selection)?;
+		self.clipboard.clear();
+
+		let data = NSData::with_bytes(&png);
+		let success =
+			unsafe { self.clipboard.pasteboard.setData_forType(Some(&data), NSPasteboardTypePNG) };
+
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#setData:forType: returned false"))
+		}
+	}
+
+	pub(crate) fn svg(self, svg: Cow<'_, str>) -> Result<(), Error> {
+		check_selection(self.selection)?;
+		self.clipboard.clear();
+
+		let data = NSData::with_bytes(svg.as_bytes());
+		let success = unsafe {
+			self.clipboard.pasteboard.setData_forType(Some(&data), ns_string!("public.svg-image"))
+		};
+
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+
+		if success {
+			Ok(())
+		} else {
+			Err(Error::unknown("NSPasteboard#setData:forType: returned false"))
+		}
+	}
+
+	pub(crate) fn lazy(self, mime: &str, producer: DataProducer) -> Result<(), Error> {
+		let _ = (mime, producer);
+		Err(Error::ClipboardNotSupported)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_profile(
+		self,
+		data: ImageData,
+		_icc: Option<&[u8]>,
+	) -> Result<(), Error> {
+		// `NSImage` doesn't offer a way to attach a raw profile to bitmap data, so it's dropped.
+		self.image(data)
+	}
 
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_thumbnail(
+		self,
+		data: ImageData,
+		thumbnail: ImageData,
+	) -> Result<(), Error> {
+		let thumbnail =
+			image_from_pixels(thumbnail.bytes.into(), thumbnail.width, thumbnail.height)
+				.map_err(|_| Error::ConversionFailure)?;
+		let (png, _) = encode_image(&thumbnail, ImageRepresentation::Png)?;
+
+		let pasteboard = self.clipboard.pasteboard.clone();
+		self.image(data)?;
+		// This adds the type to the item which holds the image.
+		let success = unsafe {
+			pasteboard.setData_forType(Some(&png), ns_string!("com.1password.arboard.thumbnail"))
+		};
 		if success {
 			Ok(())
 		} else {
-			Err(Error::Unknown { description: "NSPasteboard#writeObjects: returned false".into() })
+			Err(Error::unknown("NSPasteboard#setData:forType: returned false"))
 		}
 	}
 
 	#[cfg(feature = "image-data")]
 	pub(crate) fn image(self, data: ImageData) -> Result<(), Error> {
+		check_selection(self.selection)?;
+
 		let pixels = data.bytes.into();
 		let image = image_from_pixels(pixels, data.width, data.height)
 			.map_err(|_| Error::ConversionFailure)?;
 
+		if let Some(representation) = self.image_representation {
+			let (data, pasteboard_type) = encode_image(&image, representation)?;
+			self.clipboard.clear();
+			let success =
+				unsafe { self.clipboard.pasteboard.setData_forType(Some(&data), pasteboard_type) };
+			add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
+			return if success {
+				Ok(())
+			} else {
+				Err(Error::unknown("NSPasteboard#setData:forType: returned false"))
+			};
+		}
+
 		self.clipboard.clear();
 
 		let image_array = NSArray::from_vec(vec![ProtocolObject::from_id(image)]);
 		let success = unsafe { self.clipboard.pasteboard.writeObjects(&image_array) };
 
-		add_clipboard_exclusions(self.clipboard, self.exclude_from_history);
+		add_clipboard_exclusions(self.clipboard, self.exclude_from_history, self.transient);
 
 		if success {
 			Ok(())
 		} else {
-			Err(Error::Unknown {
-				description:
-					"Failed to write the image to the pasteboard (`writeObjects` returned NO)."
-						.into(),
-			})
+			Err(Error::unknown(
+				"Failed to write the image to the pasteboard (`writeObjects` returned NO).",
+			))
 		}
 	}
 }
@@ -333,9 +843,28 @@ impl<'clipboard> Clear<'clipboard> {
 	}
 }
 
-fn add_clipboard_exclusions(clipboard: &mut Clipboard, exclude_from_history: bool) {
+/// Wraps an HTML fragment into a document which declares its encoding.
+///
+/// Text goes to the clipboard as UTF-8 but may be interpreted as Windows Latin 1.
+/// This wrapping forces it to be interpreted as UTF-8.
+///
+/// See:
+/// https://bugzilla.mozilla.org/show_bug.cgi?id=466599
+/// https://bugs.chromium.org/p/chromium/issues/detail?id=11957
+fn utf8_html_document(html: &str) -> String {
+	format!(
+		r#"<html><head><meta http-equiv="content-type" content="text/html; charset=utf-8"></head><body>{html}</body></html>"#,
+	)
+}
+
+fn add_clipboard_exclusions(
+	clipboard: &mut Clipboard,
+	exclude_from_history: bool,
+	transient: bool,
+) {
 	// On Mac there isn't an official standard for excluding data from clipboard, however
-	// there is an unofficial standard which is to set `org.nspasteboard.ConcealedType`.
+	// there is an unofficial standard which is to set `org.nspasteboard.ConcealedType`, or
+	// `org.nspasteboard.AutoGeneratedType` for data which the user didn't copy themselves.
 	//
 	// See http://nspasteboard.org/ for details about the community standard.
 	if exclude_from_history {
@@ -345,6 +874,61 @@ fn add_clipboard_exclusions(clipboard: &mut Clipboard, exclude_from_history: boo
 				.setString_forType(ns_string!(""), ns_string!("org.nspasteboard.ConcealedType"));
 		}
 	}
+	if transient {
+		unsafe {
+			clipboard.pasteboard.setString_forType(
+				ns_string!(""),
+				ns_string!("org.nspasteboard.AutoGeneratedType"),
+			);
+		}
+	}
+}
+
+/// Apple-specific extensions to the [`Get`](crate::Get) builder.
+pub trait GetExtApple: private::Sealed {
+	/// Makes [`text`](crate::Get::text) fall back to the paths of any copied files when the
+	/// pasteboard holds no plain text (`public.utf8-plain-text`).
+	///
+	/// This is what's usually on the pasteboard after copying files in Finder. The paths are
+	/// joined by newlines, in the order the files appear on the pasteboard.
+	fn text_fallback_to_paths(self) -> Self;
+
+	/// Completes the "get" operation by listing the types on the pasteboard, as UTIs like
+	/// `public.utf8-plain-text`, without reading any of the data.
+	///
+	/// The types of all pasteboard items are listed, in the order of preference of the
+	/// application which wrote them.
+	fn available_types(self) -> Result<Vec<String>, Error>;
+
+	/// Completes the "get" operation by reading the data of the pasteboard type `uti`, like
+	/// `public.svg-image` or an application specific type, as it is.
+	fn data_for_type(self, uti: &str) -> Result<Vec<u8>, Error>;
+
+	/// Completes the "get" operation by reading the text on the Find pasteboard, which holds the
+	/// last text searched for, or placed there with Cmd+E, in any application.
+	///
+	/// This is a loose analogue of the Primary selection on Linux for applications being ported
+	/// from there, but not a true primary selection: merely selecting text doesn't update it.
+	fn find_pasteboard_text(self) -> Result<String, Error>;
+}
+
+impl GetExtApple for crate::Get<'_> {
+	fn text_fallback_to_paths(mut self) -> Self {
+		self.platform.text_fallback_to_paths = true;
+		self
+	}
+
+	fn available_types(self) -> Result<Vec<String>, Error> {
+		self.platform.available_types()
+	}
+
+	fn data_for_type(self, uti: &str) -> Result<Vec<u8>, Error> {
+		self.platform.data_for_type(uti)
+	}
+
+	fn find_pasteboard_text(self) -> Result<String, Error> {
+		self.platform.find_pasteboard_text()
+	}
 }
 
 /// Apple-specific extensions to the [`Set`](crate::Set) builder.
@@ -354,6 +938,45 @@ pub trait SetExtApple: private::Sealed {
 	///
 	/// See http://nspasteboard.org/ for details about the community standard.
 	fn exclude_from_history(self) -> Self;
+
+	/// Marks the data which will be set on the clipboard as generated by the application rather
+	/// than copied by the user, by adding the `org.nspasteboard.AutoGeneratedType` type.
+	///
+	/// Following the conventions of http://nspasteboard.org/, clipboard history software may
+	/// then skip the data or drop it from the history later. Unlike
+	/// [`exclude_from_history`](Self::exclude_from_history), this doesn't mark the data as
+	/// sensitive, so it's meant for transient contents like those placed for a drag or an
+	/// automated paste, rather than for passwords.
+	fn mark_transient(self) -> Self;
+
+	/// Places images set with [`image`](crate::Set::image) on the pasteboard as a single file in
+	/// the chosen representation.
+	///
+	/// By default, images are written as an `NSImage`, which the pasteboard offers as TIFF.
+	#[cfg(feature = "image-data")]
+	fn image_representation(self, representation: ImageRepresentation) -> Self;
+
+	/// Completes the "set" operation by placing HTML onto the pasteboard, next to what's already
+	/// there.
+	///
+	/// Unlike [`html`](crate::Set::html), this doesn't clear the pasteboard first, so an existing
+	/// plain text representation is kept. Only the HTML type is replaced. When the pasteboard
+	/// holds more than one item, the HTML is added to the first one.
+	fn html_keep_text<'a, T: Into<Cow<'a, str>>>(self, html: T) -> Result<(), Error>;
+
+	/// Completes the "set" operation by placing `bytes` onto the pasteboard under the type
+	/// `uti`, like `public.comma-separated-values-text` or an application specific type.
+	///
+	/// The bytes are placed as they are, without checking that they match the type.
+	fn data_for_type(self, uti: &str, bytes: &[u8]) -> Result<(), Error>;
+
+	/// Completes the "set" operation by placing `text` onto the pasteboard under the type `uti`,
+	/// instead of `public.utf8-plain-text`.
+	///
+	/// This is meant for consumers which look for another type, like `text/plain` when the
+	/// pasteboard is shared with Linux applications through a bridge. The text is stored as
+	/// UTF-8.
+	fn text_as_type(self, uti: &str, text: &str) -> Result<(), Error>;
 }
 
 impl SetExtApple for crate::Set<'_> {
@@ -361,4 +984,27 @@ impl SetExtApple for crate::Set<'_> {
 		self.platform.exclude_from_history = true;
 		self
 	}
+
+	fn mark_transient(mut self) -> Self {
+		self.platform.transient = true;
+		self
+	}
+
+	#[cfg(feature = "image-data")]
+	fn image_representation(mut self, representation: ImageRepresentation) -> Self {
+		self.platform.image_representation = Some(representation);
+		self
+	}
+
+	fn html_keep_text<'a, T: Into<Cow<'a, str>>>(self, html: T) -> Result<(), Error> {
+		self.platform.html_keep_text(html.into())
+	}
+
+	fn data_for_type(self, uti: &str, bytes: &[u8]) -> Result<(), Error> {
+		self.platform.data_for_type(uti, bytes)
+	}
+
+	fn text_as_type(self, uti: &str, text: &str) -> Result<(), Error> {
+		self.platform.text_as_type(uti, text)
+	}
 }
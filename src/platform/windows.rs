@@ -8,10 +8,10 @@ the Apache 2.0 or the MIT license at the licensee's choice. The terms
 and conditions of the chosen license apply to this file.
 */
 
+use crate::common::{private, ClipboardConfig, DataProducer, Error, SelectionKind};
 #[cfg(feature = "image-data")]
-use crate::common::ImageData;
-use crate::common::{private, Error};
-use std::{borrow::Cow, marker::PhantomData, thread, time::Duration};
+use crate::common::{ImageCacheKey, ImageData, ImageDataWithMeta};
+use std::{borrow::Cow, marker::PhantomData, path::PathBuf, thread, time::Duration};
 
 #[cfg(feature = "image-data")]
 mod image_data {
@@ -24,20 +24,23 @@ mod image_data {
 	use windows_sys::Win32::{
 		Foundation::HGLOBAL,
 		Graphics::Gdi::{
-			CreateDIBitmap, DeleteObject, GetDC, GetDIBits, BITMAPINFO, BITMAPINFOHEADER,
-			BITMAPV5HEADER, BI_BITFIELDS, BI_RGB, CBM_INIT, DIB_RGB_COLORS, HBITMAP, HDC,
-			LCS_GM_IMAGES, RGBQUAD,
+			CreateDIBitmap, DeleteObject, GetDC, GetDIBits, GetObjectW, ReleaseDC, BITMAP,
+			BITMAPINFO, BITMAPINFOHEADER, BITMAPV5HEADER, BI_BITFIELDS, BI_RGB, CBM_INIT,
+			DIB_RGB_COLORS, HBITMAP, HDC, LCS_GM_IMAGES, RGBQUAD,
 		},
 		System::{
-			DataExchange::SetClipboardData,
-			Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GHND},
-			Ole::CF_DIBV5,
+			DataExchange::{GetClipboardData, SetClipboardData},
+			Memory::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GHND},
+			Ole::{CF_BITMAP, CF_DIB, CF_DIBV5},
 		},
 	};
 
+	// These constants are missing in windows-rs
+	const PROFILE_EMBEDDED: u32 = 0x4D42_4544;
+	const PROFILE_LINKED: u32 = 0x4C49_4E4B;
+
 	fn last_error(message: &str) -> Error {
-		let os_error = io::Error::last_os_error();
-		Error::unknown(format!("{}: {}", message, os_error))
+		Error::unknown_with_source(message, io::Error::last_os_error())
 	}
 
 	unsafe fn global_unlock_checked(hdata: isize) {
@@ -52,9 +55,16 @@ mod image_data {
 		}
 	}
 
+	/// Places the image as `CF_DIBV5`, embedding the `icc` color profile after the pixels if
+	/// one is given.
+	///
+	/// The rows of `image` must be in bottom-up order, as returned by [`flip_v`], unless
+	/// `top_down` is set. See [`flip_v`] for why this is usually avoided.
 	pub(super) fn add_cf_dibv5(
-		_open_clipboard: OpenClipboard,
-		image: ImageData,
+		_open_clipboard: &OpenClipboard,
+		image: &ImageData,
+		icc: Option<&[u8]>,
+		top_down: bool,
 	) -> Result<(), Error> {
 		// This constant is missing in windows-rs
 		// https://github.com/microsoft/windows-rs/issues/2711
@@ -62,10 +72,12 @@ mod image_data {
 		const LCS_sRGB: u32 = 0x7352_4742;
 
 		let header_size = size_of::<BITMAPV5HEADER>();
+		let profile = icc.unwrap_or_default();
 		let header = BITMAPV5HEADER {
 			bV5Size: header_size as u32,
 			bV5Width: image.width as i32,
-			bV5Height: image.height as i32,
+			// A negative height marks the rows as top-down.
+			bV5Height: if top_down { -(image.height as i32) } else { image.height as i32 },
 			bV5Planes: 1,
 			bV5BitCount: 32,
 			bV5Compression: BI_BITFIELDS,
@@ -78,57 +90,151 @@ mod image_data {
 			bV5GreenMask: 0x0000ff00,
 			bV5BlueMask: 0x000000ff,
 			bV5AlphaMask: 0xff000000,
-			bV5CSType: LCS_sRGB,
+			bV5CSType: if icc.is_some() { PROFILE_EMBEDDED } else { LCS_sRGB },
 			// SAFETY: Windows ignores this field because `bV5CSType` is not set to `LCS_CALIBRATED_RGB`.
 			bV5Endpoints: unsafe { std::mem::zeroed() },
 			bV5GammaRed: 0,
 			bV5GammaGreen: 0,
 			bV5GammaBlue: 0,
 			bV5Intent: LCS_GM_IMAGES as u32, // I'm not sure about this.
-			bV5ProfileData: 0,
-			bV5ProfileSize: 0,
+			// The offset of the profile is counted from the start of the header.
+			bV5ProfileData: if icc.is_some() {
+				(header_size + image.bytes.len()) as u32
+			} else {
+				0
+			},
+			bV5ProfileSize: profile.len() as u32,
 			bV5Reserved: 0,
 		};
 
-		// In theory we don't need to flip the image because we could just specify
-		// a negative height in the header, which according to the documentation, indicates that the
-		// image rows are in top-to-bottom order. HOWEVER: MS Word (and WordPad) cannot paste an image
-		// that has a negative height in its header.
-		let image = flip_v(image);
+		let hdata = unsafe { alloc_dib(&header, image, profile)? };
+		if unsafe { SetClipboardData(CF_DIBV5 as u32, hdata as _) } == 0 {
+			// The system only takes ownership of the memory once it's on the clipboard.
+			unsafe { GlobalFree(hdata) };
+			Err(last_error("SetClipboardData failed with error"))
+		} else {
+			Ok(())
+		}
+	}
 
-		let data_size = header_size + image.bytes.len();
-		let hdata = unsafe { global_alloc(data_size)? };
-		unsafe {
-			let data_ptr = global_lock(hdata)?;
-			let _unlock = ScopeGuard::new(|| global_unlock_checked(hdata));
+	pub(super) fn add_cf_dib(
+		_open_clipboard: &OpenClipboard,
+		flipped_image: &ImageData,
+	) -> Result<(), Error> {
+		let header = bitmap_info_header(flipped_image);
 
-			copy_nonoverlapping::<u8>((&header) as *const _ as *const u8, data_ptr, header_size);
+		let hdata = unsafe { alloc_dib(&header, flipped_image, &[])? };
+		if unsafe { SetClipboardData(CF_DIB as u32, hdata as _) } == 0 {
+			unsafe { GlobalFree(hdata) };
+			Err(last_error("SetClipboardData failed with error"))
+		} else {
+			Ok(())
+		}
+	}
 
-			// Not using the `add` function, because that has a restriction, that the result cannot overflow isize
-			let pixels_dst = (data_ptr as usize + header_size) as *mut u8;
-			copy_nonoverlapping::<u8>(image.bytes.as_ptr(), pixels_dst, image.bytes.len());
+	pub(super) fn add_cf_bitmap(
+		_open_clipboard: &OpenClipboard,
+		flipped_image: &ImageData,
+	) -> Result<(), Error> {
+		let info = BITMAPINFO {
+			bmiColors: [RGBQUAD { rgbRed: 0, rgbGreen: 0, rgbBlue: 0, rgbReserved: 0 }],
+			bmiHeader: bitmap_info_header(flipped_image),
+		};
 
-			let dst_pixels_slice = std::slice::from_raw_parts_mut(pixels_dst, image.bytes.len());
+		let mut bytes = flipped_image.bytes.to_vec();
+		// SAFETY: The image data is made of 4 byte pixels.
+		let pixels = unsafe { rgba_to_win(&mut bytes) };
 
-			// If the non-allocating version of the function failed, we need to assign the new bytes to
-			// the global allocation.
-			if let Cow::Owned(new_pixels) = rgba_to_win(dst_pixels_slice) {
-				// SAFETY: `data_ptr` is valid to write to and has no outstanding mutable borrows, and
-				// `new_pixels` will be the same length as the original bytes.
-				copy_nonoverlapping::<u8>(new_pixels.as_ptr(), data_ptr, new_pixels.len())
-			}
+		let hdc = get_screen_device_context()?;
+		let hbitmap = unsafe {
+			let hbitmap = CreateDIBitmap(
+				hdc,
+				&info.bmiHeader,
+				CBM_INIT as u32,
+				pixels.as_ptr() as *const c_void,
+				&info,
+				DIB_RGB_COLORS,
+			);
+			ReleaseDC(0, hdc);
+			hbitmap
+		};
+		if hbitmap == 0 {
+			return Err(last_error(
+				"Failed to create the HBITMAP. CreateDIBitmap failed with error",
+			));
 		}
 
-		if unsafe { SetClipboardData(CF_DIBV5 as u32, hdata as _) } == 0 {
-			unsafe { DeleteObject(hdata as _) };
+		// The system owns the bitmap once it's on the clipboard, so it only needs to be deleted
+		// if placing it failed.
+		if unsafe { SetClipboardData(CF_BITMAP as u32, hbitmap as _) } == 0 {
+			unsafe { DeleteObject(hbitmap) };
 			Err(last_error("SetClipboardData failed with error"))
 		} else {
 			Ok(())
 		}
 	}
 
-	pub(super) fn add_png_file(image: &ImageData) -> Result<(), Error> {
-		// Try encoding the image as PNG.
+	/// Returns the header for a 32-bit, bottom-up bitmap of the image, as used by `CF_DIB`.
+	fn bitmap_info_header(image: &ImageData) -> BITMAPINFOHEADER {
+		BITMAPINFOHEADER {
+			biSize: size_of::<BITMAPINFOHEADER>() as u32,
+			biWidth: image.width as i32,
+			biHeight: image.height as i32,
+			biPlanes: 1,
+			biBitCount: 32,
+			biCompression: BI_RGB as u32,
+			biSizeImage: (4 * image.width * image.height) as u32,
+			biXPelsPerMeter: 0,
+			biYPelsPerMeter: 0,
+			biClrUsed: 0,
+			biClrImportant: 0,
+		}
+	}
+
+	/// Allocates a global memory object holding the bitmap `header`, directly followed by the
+	/// pixels of the image in the bitmap-native format and then by `trailer`.
+	///
+	/// Safety: `H` must be one of the bitmap header types, which are plain old data.
+	unsafe fn alloc_dib<H>(
+		header: &H,
+		flipped_image: &ImageData,
+		trailer: &[u8],
+	) -> Result<HGLOBAL, Error> {
+		let header_size = size_of::<H>();
+		let data_size = header_size + flipped_image.bytes.len() + trailer.len();
+		let hdata = global_alloc(data_size)?;
+
+		let data_ptr = global_lock(hdata)?;
+		let _unlock = ScopeGuard::new(|| global_unlock_checked(hdata));
+
+		copy_nonoverlapping::<u8>(header as *const H as *const u8, data_ptr, header_size);
+
+		// Not using the `add` function, because that has a restriction, that the result cannot overflow isize
+		let pixels_dst = (data_ptr as usize + header_size) as *mut u8;
+		copy_nonoverlapping::<u8>(
+			flipped_image.bytes.as_ptr(),
+			pixels_dst,
+			flipped_image.bytes.len(),
+		);
+
+		let dst_pixels_slice =
+			std::slice::from_raw_parts_mut(pixels_dst, flipped_image.bytes.len());
+
+		// If the non-allocating version of the function failed, we need to assign the new bytes to
+		// the global allocation.
+		if let Cow::Owned(new_pixels) = rgba_to_win(dst_pixels_slice) {
+			// SAFETY: `pixels_dst` is valid to write to and has no outstanding mutable borrows, and
+			// `new_pixels` will be the same length as the original bytes.
+			copy_nonoverlapping::<u8>(new_pixels.as_ptr(), pixels_dst, new_pixels.len())
+		}
+
+		let trailer_dst = (pixels_dst as usize + flipped_image.bytes.len()) as *mut u8;
+		copy_nonoverlapping::<u8>(trailer.as_ptr(), trailer_dst, trailer.len());
+
+		Ok(hdata)
+	}
+
+	fn encode_png(image: &ImageData, icc: Option<&[u8]>) -> Result<Vec<u8>, Error> {
 		let mut buf = Vec::new();
 		let encoder = PngEncoder::new(&mut buf);
 
@@ -140,9 +246,18 @@ mod image_data {
 				ExtendedColorType::Rgba8,
 			)
 			.map_err(|_| Error::ConversionFailure)?;
+		if let Some(icc) = icc {
+			crate::common::embed_icc_profile(&mut buf, icc)?;
+		}
+		Ok(buf)
+	}
+
+	pub(super) fn add_png_file(image: &ImageData, icc: Option<&[u8]>) -> Result<(), Error> {
+		// Try encoding the image as PNG.
+		let buf = encode_png(image, icc)?;
 
 		// Register PNG format.
-		let format_id = match clipboard_win::register_format("PNG") {
+		let format_id = match clipboard_win::register_format(PNG_FORMAT_NAME) {
 			Some(format_id) => format_id.into(),
 			None => return Err(last_error("Cannot register PNG clipboard format.")),
 		};
@@ -157,13 +272,22 @@ mod image_data {
 		}
 
 		if unsafe { SetClipboardData(format_id, hdata as _) } == 0 {
-			unsafe { DeleteObject(hdata as _) };
+			unsafe { GlobalFree(hdata) };
 			Err(last_error("SetClipboardData failed with error"))
 		} else {
 			Ok(())
 		}
 	}
 
+	/// Places `thumbnail` as a PNG file under the registered [`THUMBNAIL_FORMAT_NAME`] format.
+	pub(super) fn add_thumbnail(thumbnail: &ImageData) -> Result<(), Error> {
+		let buf = encode_png(thumbnail, None)?;
+		let format = clipboard_win::register_format(THUMBNAIL_FORMAT_NAME)
+			.ok_or_else(|| last_error("Cannot register the thumbnail clipboard format."))?;
+		clipboard_win::raw::set_without_clear(format.get(), &buf)
+			.map_err(|e| clipboard_error("Could not place the thumbnail on the clipboard", e))
+	}
+
 	unsafe fn global_alloc(bytes: usize) -> Result<HGLOBAL, Error> {
 		let hdata = GlobalAlloc(GHND, bytes);
 		if hdata == 0 {
@@ -182,14 +306,13 @@ mod image_data {
 		}
 	}
 
-	pub(super) fn read_cf_dibv5(dibv5: &[u8]) -> Result<ImageData<'static>, Error> {
+	pub(super) fn read_cf_dibv5(
+		dibv5: &[u8],
+		assume_opaque: bool,
+	) -> Result<ImageDataWithMeta, Error> {
 		// The DIBV5 format is a BITMAPV5HEADER followed by the pixel data according to
 		// https://docs.microsoft.com/en-us/windows/win32/dataxchg/standard-clipboard-formats
 
-		// These constants are missing in windows-rs
-		const PROFILE_EMBEDDED: u32 = 0x4D42_4544;
-		const PROFILE_LINKED: u32 = 0x4C49_4E4B;
-
 		// so first let's get a pointer to the header
 		let header_size = size_of::<BITMAPV5HEADER>();
 		if dibv5.len() < header_size {
@@ -200,7 +323,9 @@ mod image_data {
 		let has_profile =
 			header.bV5CSType == PROFILE_LINKED || header.bV5CSType == PROFILE_EMBEDDED;
 
-		let pixel_data_start = if has_profile {
+		// The profile is usually stored after the pixels, but some applications place it right
+		// after the header instead.
+		let pixel_data_start = if has_profile && header.bV5ProfileData as usize == header_size {
 			header.bV5ProfileData as isize + header.bV5ProfileSize as isize
 		} else {
 			header_size as isize
@@ -248,6 +373,12 @@ mod image_data {
 			);
 			result_bytes.set_len(read_len);
 
+			if assume_opaque {
+				// The alpha mask is only meaningful for bitfield encoded bitmaps.
+				let alpha_mask =
+					if header.bV5Compression == BI_BITFIELDS { header.bV5AlphaMask } else { 0 };
+				fill_missing_alpha(&mut result_bytes, alpha_mask);
+			}
 			let result_bytes = win_to_rgba(&mut result_bytes);
 
 			let result = ImageData {
@@ -255,7 +386,102 @@ mod image_data {
 				width: w as usize,
 				height: h as usize,
 			};
-			Ok(result)
+			let dpi = |pixels_per_meter: i32| {
+				ImageDataWithMeta::dpi_from_pixels_per_meter(
+					pixels_per_meter.try_into().unwrap_or(0),
+				)
+			};
+			Ok(ImageDataWithMeta {
+				x_dpi: dpi(header.bV5XPelsPerMeter),
+				y_dpi: dpi(header.bV5YPelsPerMeter),
+				..ImageDataWithMeta::new(result)
+			})
+		}
+	}
+
+	pub(super) fn read_cf_bitmap(
+		_open_clipboard: &OpenClipboard,
+		max_bytes: Option<usize>,
+		assume_opaque: bool,
+	) -> Result<ImageData<'static>, Error> {
+		// SAFETY: The clipboard is open. The bitmap is owned by the clipboard, so it must not be
+		// deleted.
+		let hbitmap = unsafe { GetClipboardData(CF_BITMAP as u32) } as HBITMAP;
+		if hbitmap == 0 {
+			return Err(last_error("Failed to read CF_BITMAP. GetClipboardData failed with error"));
+		}
+
+		// SAFETY: `BITMAP` is plain old data, and `bitmap` is valid to write to.
+		let mut bitmap: BITMAP = unsafe { std::mem::zeroed() };
+		let size = size_of::<BITMAP>() as i32;
+		if unsafe { GetObjectW(hbitmap, size, &mut bitmap as *mut BITMAP as *mut c_void) } == 0 {
+			return Err(Error::unknown(
+				"Failed to get the size of CF_BITMAP. GetObjectW returned 0",
+			));
+		}
+		let w = bitmap.bmWidth;
+		let h = bitmap.bmHeight.abs();
+		// A bitmap handle has no data size of its own, so the size of the converted pixels is
+		// checked before they're read.
+		if max_bytes.map_or(false, |max| w as usize * h as usize * 4 > max) {
+			return Err(Error::TooLarge);
+		}
+
+		let mut info = BITMAPINFO {
+			bmiColors: [RGBQUAD { rgbRed: 0, rgbGreen: 0, rgbBlue: 0, rgbReserved: 0 }],
+			bmiHeader: BITMAPINFOHEADER {
+				biSize: size_of::<BITMAPINFOHEADER>() as u32,
+				biWidth: w,
+				biHeight: -h,
+				biBitCount: 32,
+				biPlanes: 1,
+				biCompression: BI_RGB as u32,
+				biSizeImage: 0,
+				biXPelsPerMeter: 0,
+				biYPelsPerMeter: 0,
+				biClrUsed: 0,
+				biClrImportant: 0,
+			},
+		};
+
+		let hdc = get_screen_device_context()?;
+		let _release = ScopeGuard::new(|| unsafe {
+			ReleaseDC(0, hdc);
+		});
+
+		let mut bytes = Vec::<u8>::with_capacity(w as usize * h as usize * 4);
+		// The image is as high as the number of lines which were actually converted.
+		let lines = unsafe {
+			let lines =
+				convert_bitmap_to_rgb(hdc, hbitmap, h as _, bytes.as_mut_ptr() as _, &mut info)?;
+			let read_len = lines as usize * w as usize * 4;
+			assert!(
+				read_len <= bytes.capacity(),
+				"Segmentation fault. Read more bytes than allocated to pixel buffer",
+			);
+			bytes.set_len(read_len);
+			lines as usize
+		};
+
+		if assume_opaque {
+			fill_missing_alpha(&mut bytes, 0);
+		}
+
+		// SAFETY: The bytes are made of 4 byte pixels.
+		let bytes = unsafe { win_to_rgba(&mut bytes) };
+
+		Ok(ImageData { bytes: Cow::Owned(bytes), width: w as usize, height: lines })
+	}
+
+	/// Makes the pixels opaque if none of them has an alpha value, unless the bitmap declared
+	/// an alpha channel with a non-zero `alpha_mask`.
+	///
+	/// Device dependent bitmaps, the formats Windows synthesizes from them, and many 32-bit
+	/// DIBs leave the high byte of each pixel unused, which `GetDIBits` reports as zero. Such
+	/// images are meant to be opaque rather than fully transparent.
+	pub(super) fn fill_missing_alpha(pixels: &mut [u8], alpha_mask: u32) {
+		if alpha_mask == 0 && pixels.chunks_exact(4).all(|pixel| pixel[3] == 0) {
+			pixels.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
 		}
 	}
 
@@ -342,27 +568,19 @@ mod image_data {
 		}
 	}
 
-	/// Vertically flips the image pixels in memory
-	fn flip_v(image: ImageData) -> ImageData<'static> {
-		let w = image.width;
-		let h = image.height;
-
-		let mut bytes = image.bytes.into_owned();
-
-		let rowsize = w * 4; // each pixel is 4 bytes
-		let mut tmp_a = vec![0; rowsize];
-		// I believe this could be done safely with `as_chunks_mut`, but that's not stable yet
-		for a_row_id in 0..(h / 2) {
-			let b_row_id = h - a_row_id - 1;
-
-			// swap rows `first_id` and `second_id`
-			let a_byte_start = a_row_id * rowsize;
-			let a_byte_end = a_byte_start + rowsize;
-			let b_byte_start = b_row_id * rowsize;
-			let b_byte_end = b_byte_start + rowsize;
-			tmp_a.copy_from_slice(&bytes[a_byte_start..a_byte_end]);
-			bytes.copy_within(b_byte_start..b_byte_end, a_byte_start);
-			bytes[b_byte_start..b_byte_end].copy_from_slice(&tmp_a);
+	/// Returns a copy of the image with its rows in reverse order.
+	///
+	/// In theory we don't need to flip the image because we could just specify a negative height
+	/// in the bitmap headers, which according to the documentation, indicates that the image rows
+	/// are in top-to-bottom order. HOWEVER: MS Word (and WordPad) cannot paste an image that has a
+	/// negative height in its header.
+	pub(super) fn flip_v(image: &ImageData) -> ImageData<'static> {
+		let rowsize = image.width * 4; // each pixel is 4 bytes
+
+		let mut bytes = Vec::with_capacity(image.bytes.len());
+		for row_id in (0..image.height).rev() {
+			let row_start = row_id * rowsize;
+			bytes.extend_from_slice(&image.bytes[row_start..row_start + rowsize]);
 		}
 
 		ImageData { width: image.width, height: image.height, bytes: bytes.into() }
@@ -446,6 +664,12 @@ mod image_data {
 		let _converted = unsafe { rgba_to_win(&mut data) };
 		let _converted = unsafe { win_to_rgba(&mut data) };
 		assert_eq!(data, DATA);
+
+		// Windows bitmaps store the channels as BGRA.
+		let mut red = [255, 0, 0, 255];
+		assert_eq!(unsafe { rgba_to_win(&mut red) }.as_ref(), &[0, 0, 255, 255]);
+		let mut red = [0, 0, 255, 255];
+		assert_eq!(unsafe { win_to_rgba(&mut red) }, [255, 0, 0, 255]);
 	}
 }
 
@@ -456,7 +680,12 @@ mod image_data {
 /// open at once, so we have to open it very sparingly or risk causing the rest
 /// of the system to be unresponsive. Instead, the clipboard is opened for
 /// every operation and then closed afterwards.
-pub(crate) struct Clipboard(());
+pub(crate) struct Clipboard {
+	open_attempts: usize,
+	/// Whether the clipboard is kept open by a [`Batch`], so that operations don't open it
+	/// themselves.
+	batched: bool,
+}
 
 // The other platforms have `Drop` implementation on their
 // clipboard, so Windows should too for consistently.
@@ -465,7 +694,8 @@ impl Drop for Clipboard {
 }
 
 struct OpenClipboard<'clipboard> {
-	_inner: clipboard_win::Clipboard,
+	/// `None` within a batch, which closes the clipboard once it ends instead.
+	_inner: Option<clipboard_win::Clipboard>,
 	// The Windows clipboard can not be sent between threads once
 	// open.
 	_marker: PhantomData<*const ()>,
@@ -475,11 +705,73 @@ struct OpenClipboard<'clipboard> {
 impl Clipboard {
 	const DEFAULT_OPEN_ATTEMPTS: usize = 5;
 
-	pub(crate) fn new() -> Result<Self, Error> {
-		Ok(Self(()))
+	pub(crate) fn new(config: ClipboardConfig) -> Result<Self, Error> {
+		let open_attempts = config.windows_open_attempts.unwrap_or(Self::DEFAULT_OPEN_ATTEMPTS);
+		Ok(Self { open_attempts, batched: false })
+	}
+
+	pub(crate) fn primary_selection_supported(&self) -> bool {
+		false
+	}
+
+	pub(crate) fn owner_process_id(&self) -> Result<Option<u32>, Error> {
+		use windows_sys::Win32::{
+			System::DataExchange::GetClipboardOwner,
+			UI::WindowsAndMessaging::GetWindowThreadProcessId,
+		};
+
+		// SAFETY: `GetClipboardOwner` doesn't require the clipboard to be open.
+		let owner = unsafe { GetClipboardOwner() };
+		if owner == 0 {
+			return Ok(None);
+		}
+
+		let mut process_id = 0;
+		// SAFETY: `process_id` is valid to write to. The call fails, returning 0, if the owner's
+		// window was destroyed in the meantime.
+		if unsafe { GetWindowThreadProcessId(owner, &mut process_id) } == 0 {
+			return Ok(None);
+		}
+		Ok(Some(process_id))
+	}
+
+	pub(crate) fn is_empty(&mut self) -> Result<bool, Error> {
+		use windows_sys::Win32::System::DataExchange::CountClipboardFormats;
+
+		// SAFETY: `CountClipboardFormats` doesn't require the clipboard to be open.
+		Ok(unsafe { CountClipboardFormats() } == 0)
+	}
+
+	pub(crate) fn max_request_bytes(&self) -> Option<usize> {
+		None
+	}
+
+	/// Opens the clipboard until [`end_batch`](Self::end_batch) is called with the returned
+	/// batch, instead of for every operation.
+	///
+	/// Returns `None` within a batch, which already keeps the clipboard open until it ends.
+	pub(crate) fn begin_batch(&mut self) -> Result<Option<Batch>, Error> {
+		if self.batched {
+			return Ok(None);
+		}
+		let inner = self.open_raw()?;
+		self.batched = true;
+		Ok(Some(Batch { _inner: inner, _marker: PhantomData }))
+	}
+
+	pub(crate) fn end_batch(&mut self, batch: Option<Batch>) {
+		if let Some(batch) = batch {
+			self.batched = false;
+			drop(batch);
+		}
 	}
 
 	fn open(&mut self) -> Result<OpenClipboard, Error> {
+		let inner = if self.batched { None } else { Some(self.open_raw()?) };
+		Ok(OpenClipboard { _inner: inner, _marker: PhantomData, _for_shim: self })
+	}
+
+	fn open_raw(&self) -> Result<clipboard_win::Clipboard, Error> {
 		// Attempt to open the clipboard multiple times. On Windows, its common for something else to temporarily
 		// be using it during attempts.
 		//
@@ -488,7 +780,7 @@ impl Clipboard {
 		//
 		// Note: This does not use `Clipboard::new_attempts` because its implementation sleeps for `0ms`, which can
 		// cause race conditions between closing/opening the clipboard in single-threaded apps.
-		let mut attempts = Self::DEFAULT_OPEN_ATTEMPTS;
+		let mut attempts = self.open_attempts;
 		let clipboard = loop {
 			match clipboard_win::Clipboard::new() {
 				Ok(this) => break Ok(this),
@@ -500,13 +792,19 @@ impl Clipboard {
 
 			// The default value matches Chromium's implementation, but could be tweaked later.
 			thread::sleep(Duration::from_millis(5));
-		}
-		.map_err(|_| Error::ClipboardOccupied)?;
-
-		Ok(OpenClipboard { _inner: clipboard, _marker: PhantomData, _for_shim: self })
+		};
+		clipboard.map_err(|_| Error::ClipboardOccupied)
 	}
 }
 
+/// Keeps the clipboard open across the operations of [`Clipboard::batch`](crate::Clipboard::batch).
+pub(crate) struct Batch {
+	_inner: clipboard_win::Clipboard,
+	// The Windows clipboard can not be sent between threads once
+	// open.
+	_marker: PhantomData<*const ()>,
+}
+
 // Note: In all of the builders, a clipboard opening result is stored.
 // This is done for a few reasons:
 // 1. consistently with the other platforms which can have an occupied clipboard.
@@ -516,73 +814,337 @@ impl Clipboard {
 // 3. Due to how the clipboard works on Windows, we need to open it for every operation
 // and keep it open until its finished. This approach allows RAII to still be applicable.
 
+/// Converts an error of `clipboard_win` into an [`Error::Platform`], which keeps the system's
+/// error code for [`Error::os_code`].
+fn clipboard_error(message: &str, error: clipboard_win::ErrorCode) -> Error {
+	Error::unknown_with_source(message, std::io::Error::from_raw_os_error(error.raw_code()))
+}
+
+/// Returns the error to report when the requested format isn't on the clipboard, telling
+/// an empty clipboard apart from one that only holds other formats.
+fn missing_format_error() -> Error {
+	use windows_sys::Win32::System::DataExchange::CountClipboardFormats;
+
+	// SAFETY: `CountClipboardFormats` has no preconditions.
+	if unsafe { CountClipboardFormats() } > 0 {
+		Error::WrongFormat
+	} else {
+		Error::ContentNotAvailable
+	}
+}
+
 pub(crate) struct Get<'clipboard> {
 	clipboard: Result<OpenClipboard<'clipboard>, Error>,
+	max_bytes: Option<usize>,
+	#[cfg(feature = "image-data")]
+	assume_opaque: bool,
 }
 
 impl<'clipboard> Get<'clipboard> {
 	pub(crate) fn new(clipboard: &'clipboard mut Clipboard) -> Self {
-		Self { clipboard: clipboard.open() }
+		Self {
+			clipboard: clipboard.open(),
+			max_bytes: None,
+			#[cfg(feature = "image-data")]
+			assume_opaque: true,
+		}
+	}
+
+	/// Returns a `Get` with the same options, for one more operation. The clipboard stays open
+	/// until `self` is dropped.
+	///
+	/// If the clipboard couldn't be opened, the error is moved to the returned `Get`.
+	pub(crate) fn reborrow(&mut self) -> Get<'_> {
+		let clipboard = match &mut self.clipboard {
+			Ok(open) => Ok(OpenClipboard {
+				_inner: None,
+				_marker: PhantomData,
+				_for_shim: &mut *open._for_shim,
+			}),
+			Err(e) => Err(std::mem::replace(e, Error::ClipboardOccupied)),
+		};
+		Get {
+			clipboard,
+			max_bytes: self.max_bytes,
+			#[cfg(feature = "image-data")]
+			assume_opaque: self.assume_opaque,
+		}
+	}
+
+	pub(crate) fn max_bytes(mut self, max_bytes: usize) -> Self {
+		self.max_bytes = Some(max_bytes);
+		self
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn assume_opaque_when_no_alpha(mut self, assume_opaque: bool) -> Self {
+		self.assume_opaque = assume_opaque;
+		self
+	}
+
+	pub(crate) fn selection(mut self, selection: SelectionKind) -> Self {
+		// Windows only has a single clipboard.
+		if selection != SelectionKind::Clipboard {
+			self.clipboard = Err(Error::ClipboardNotSupported);
+		}
+		self
 	}
 
 	pub(crate) fn text(self) -> Result<String, Error> {
+		self.read_text(None)
+	}
+
+	pub(crate) fn text_preview(self, max_chars: usize) -> Result<String, Error> {
+		// Every character takes at most two units, and a byte order mark may come first.
+		let max_units = max_chars.saturating_add(1).saturating_mul(2);
+		// Only the start is read, so the size of the whole text doesn't matter.
+		Self { max_bytes: None, ..self }.read_text(Some(max_units))
+	}
+
+	/// Reads `CF_UNICODETEXT`, but only up to `max_units` of it if given.
+	fn read_text(self, max_units: Option<usize>) -> Result<String, Error> {
 		const FORMAT: u32 = clipboard_win::formats::CF_UNICODETEXT;
 
 		let _clipboard_assertion = self.clipboard?;
 
 		// XXX: ToC/ToU race conditions are not possible because we are the sole owners of the clipboard currently.
 		if !clipboard_win::is_format_avail(FORMAT) {
-			return Err(Error::ContentNotAvailable);
+			return Err(missing_format_error());
 		}
 
+		check_size(FORMAT, self.max_bytes)?;
 		let text_size = clipboard_win::raw::size(FORMAT)
 			.ok_or_else(|| Error::unknown("failed to read clipboard text size"))?;
 
 		// Allocate the specific number of WTF-16 characters we need to receive.
 		// This division is always accurate because Windows uses 16-bit characters.
-		let mut out: Vec<u16> = vec![0u16; text_size.get() / 2];
+		let units = text_size.get() / 2;
+		let mut out: Vec<u16> = vec![0u16; max_units.map_or(units, |max| units.min(max))];
 
-		let bytes_read = {
+		let units_read = {
 			// SAFETY: The source slice has a greater alignment than the resulting one.
 			let out: &mut [u8] =
 				unsafe { std::slice::from_raw_parts_mut(out.as_mut_ptr().cast(), out.len() * 2) };
 
-			let mut bytes_read = clipboard_win::raw::get(FORMAT, out)
-				.map_err(|_| Error::unknown("failed to read clipboard string"))?;
+			let bytes_read = clipboard_win::raw::get(FORMAT, out)
+				.map_err(|e| clipboard_error("failed to read clipboard string", e))?;
 
 			// Convert the number of bytes read to the number of `u16`s
-			bytes_read /= 2;
+			bytes_read / 2
+		};
 
-			// Remove the NUL terminator, if it existed.
-			if let Some(last) = out.last().copied() {
-				if last == 0 {
-					bytes_read -= 1;
-				}
+		let mut text = until_nul(&out[..units_read]);
+		if text.len() == units_read && units_read < units {
+			// A leading surrogate whose trailing one was cut off.
+			if let [rest @ .., 0xD800..=0xDBFF] = text {
+				text = rest;
 			}
+		}
+
+		// Create a UTF-8 string from WTF-16 data, if it was valid.
+		String::from_utf16(text).map_err(|_| Error::ConversionFailure)
+	}
+
+	fn locale(self) -> Result<u32, Error> {
+		const FORMAT: u32 = clipboard_win::formats::CF_LOCALE;
+
+		let _clipboard_assertion = self.clipboard?;
+
+		// Windows provides this format itself for any text, even when it wasn't set explicitly.
+		if !clipboard_win::is_format_avail(FORMAT) {
+			return Err(missing_format_error());
+		}
+
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(FORMAT, &mut data)
+			.map_err(|e| clipboard_error("failed to read the clipboard locale", e))?;
+		match data.get(..4) {
+			Some(&[a, b, c, d]) => Ok(u32::from_ne_bytes([a, b, c, d])),
+			_ => Err(Error::ConversionFailure),
+		}
+	}
+
+	fn drop_effect(self) -> Result<Option<DropEffect>, Error> {
+		let _clipboard_assertion = self.clipboard?;
 
-			bytes_read
+		let format = match clipboard_win::register_format(DROP_EFFECT_FORMAT_NAME) {
+			Some(format) => format.get(),
+			None => return Ok(None),
 		};
+		if !clipboard_win::is_format_avail(format) {
+			return Ok(None);
+		}
 
-		// Create a UTF-8 string from WTF-16 data, if it was valid.
-		String::from_utf16(&out[..bytes_read]).map_err(|_| Error::ConversionFailure)
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(format, &mut data)
+			.map_err(|e| clipboard_error("failed to read the clipboard drop effect", e))?;
+		let effect = match data.get(..4) {
+			Some(&[a, b, c, d]) => u32::from_ne_bytes([a, b, c, d]),
+			_ => return Err(Error::ConversionFailure),
+		};
+		Ok(DropEffect::from_bits(effect))
+	}
+
+	fn file_list_with_point(self) -> Result<(Vec<PathBuf>, (i32, i32)), Error> {
+		const FORMAT: u32 = clipboard_win::formats::CF_HDROP;
+
+		let _clipboard_assertion = self.clipboard?;
+
+		if !clipboard_win::is_format_avail(FORMAT) {
+			return Err(missing_format_error());
+		}
+
+		check_size(FORMAT, self.max_bytes)?;
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(FORMAT, &mut data)
+			.map_err(|e| clipboard_error("failed to read the clipboard file list", e))?;
+		parse_drop_files(&data)
+	}
+
+	fn html_fragment(self) -> Result<String, Error> {
+		self.cf_html().map(|html| html.fragment)
+	}
+
+	fn html_source_url(self) -> Result<Option<String>, Error> {
+		self.cf_html().map(|html| html.source_url)
+	}
+
+	fn cf_html(self) -> Result<CfHtml, Error> {
+		let _clipboard_assertion = self.clipboard?;
+
+		let format = match clipboard_win::register_format(HTML_FORMAT_NAME) {
+			Some(format) => format.get(),
+			None => return Err(missing_format_error()),
+		};
+		if !clipboard_win::is_format_avail(format) {
+			return Err(missing_format_error());
+		}
+
+		check_size(format, self.max_bytes)?;
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(format, &mut data)
+			.map_err(|e| clipboard_error("failed to read clipboard HTML data", e))?;
+		parse_cf_html(&data)
+	}
+
+	pub(crate) fn image_png(self) -> Result<Vec<u8>, Error> {
+		let _clipboard_assertion = self.clipboard?;
+
+		let format = match clipboard_win::register_format(PNG_FORMAT_NAME) {
+			Some(format) => format.get(),
+			None => return Err(missing_format_error()),
+		};
+		if !clipboard_win::is_format_avail(format) {
+			return Err(missing_format_error());
+		}
+
+		check_size(format, self.max_bytes)?;
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(format, &mut data)
+			.map_err(|e| clipboard_error("failed to read clipboard PNG data", e))?;
+		Ok(data)
+	}
+
+	pub(crate) fn svg(self) -> Result<String, Error> {
+		let _clipboard_assertion = self.clipboard?;
+
+		let format = match clipboard_win::register_format(SVG_FORMAT_NAME) {
+			Some(format) => format.get(),
+			None => return Err(missing_format_error()),
+		};
+		if !clipboard_win::is_format_avail(format) {
+			return Err(missing_format_error());
+		}
+
+		check_size(format, self.max_bytes)?;
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(format, &mut data)
+			.map_err(|e| clipboard_error("failed to read clipboard SVG data", e))?;
+		// Some applications include a NUL terminator.
+		if let Some(len) = data.iter().position(|&byte| byte == 0) {
+			data.truncate(len);
+		}
+		String::from_utf8(data).map_err(|_| Error::ConversionFailure)
+	}
+
+	pub(crate) fn image_raw(self) -> Result<(Vec<u8>, String), Error> {
+		const DIBV5_FORMAT: u32 = clipboard_win::formats::CF_DIBV5;
+
+		let _clipboard_assertion = self.clipboard?;
+
+		let png_format = clipboard_win::register_format(PNG_FORMAT_NAME).map(|format| format.get());
+		let (format, name) = match png_format {
+			Some(format) if clipboard_win::is_format_avail(format) => (format, "image/png"),
+			_ if clipboard_win::is_format_avail(DIBV5_FORMAT) => (DIBV5_FORMAT, "CF_DIBV5"),
+			_ => return Err(missing_format_error()),
+		};
+
+		check_size(format, self.max_bytes)?;
+		let mut data = Vec::new();
+		clipboard_win::raw::get_vec(format, &mut data)
+			.map_err(|e| clipboard_error("failed to read clipboard image data", e))?;
+		Ok((data, name.to_owned()))
+	}
+
+	/// Returns the key of the image to read, made of the clipboard's sequence number, which
+	/// changes whenever its contents are replaced.
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_cache_key(&self) -> Option<ImageCacheKey> {
+		use windows_sys::Win32::System::DataExchange::GetClipboardSequenceNumber;
+
+		self.clipboard.as_ref().ok()?;
+		// Zero means that the process isn't allowed to access the clipboard's sequence number.
+		let change_key = match unsafe { GetClipboardSequenceNumber() } {
+			0 => return None,
+			sequence => u64::from(sequence),
+		};
+		Some(ImageCacheKey {
+			change_key,
+			max_bytes: self.max_bytes,
+			assume_opaque: self.assume_opaque,
+		})
 	}
 
 	#[cfg(feature = "image-data")]
 	pub(crate) fn image(self) -> Result<ImageData<'static>, Error> {
+		self.image_with_dpi().map(|image| image.image)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_dpi(self) -> Result<ImageDataWithMeta, Error> {
 		const FORMAT: u32 = clipboard_win::formats::CF_DIBV5;
 
-		let _clipboard_assertion = self.clipboard?;
+		let open_clipboard = self.clipboard?;
 
 		if !clipboard_win::is_format_avail(FORMAT) {
-			return Err(Error::ContentNotAvailable);
+			// Some legacy applications only provide a device dependent bitmap.
+			if clipboard_win::is_format_avail(clipboard_win::formats::CF_BITMAP) {
+				return image_data::read_cf_bitmap(
+					&open_clipboard,
+					self.max_bytes,
+					self.assume_opaque,
+				)
+				.map(ImageDataWithMeta::new);
+			}
+			// Others, like some image viewers, only place a GIF file, which may be animated.
+			if let Some(format) = clipboard_win::register_format(GIF_FORMAT_NAME) {
+				if clipboard_win::is_format_avail(format.get()) {
+					check_size(format.get(), self.max_bytes)?;
+					let mut data = Vec::new();
+					clipboard_win::raw::get_vec(format.get(), &mut data)
+						.map_err(|e| clipboard_error("failed to read clipboard GIF data", e))?;
+					return crate::common::decode_gif(&data).map(ImageDataWithMeta::new);
+				}
+			}
+			return Err(missing_format_error());
 		}
 
+		check_size(FORMAT, self.max_bytes)?;
 		let mut data = Vec::new();
-
 		clipboard_win::raw::get_vec(FORMAT, &mut data)
-			.map_err(|_| Error::unknown("failed to read clipboard image data"))?;
+			.map_err(|e| clipboard_error("failed to read clipboard image data", e))?;
 
-		image_data::read_cf_dibv5(&data)
+		image_data::read_cf_dibv5(&data, self.assume_opaque)
 	}
 }
 
@@ -591,6 +1153,13 @@ pub(crate) struct Set<'clipboard> {
 	exclude_from_monitoring: bool,
 	exclude_from_cloud: bool,
 	exclude_from_history: bool,
+	locale: Option<u32>,
+	#[cfg(feature = "image-data")]
+	image_formats: Option<Vec<WinImageFormat>>,
+	#[cfg(feature = "image-data")]
+	top_down_dib: bool,
+	#[cfg(feature = "image-data")]
+	legacy_dib: bool,
 }
 
 impl<'clipboard> Set<'clipboard> {
@@ -600,14 +1169,65 @@ impl<'clipboard> Set<'clipboard> {
 			exclude_from_monitoring: false,
 			exclude_from_cloud: false,
 			exclude_from_history: false,
+			locale: None,
+			#[cfg(feature = "image-data")]
+			image_formats: None,
+			#[cfg(feature = "image-data")]
+			top_down_dib: false,
+			#[cfg(feature = "image-data")]
+			legacy_dib: false,
+		}
+	}
+
+	pub(crate) fn selection(mut self, selection: SelectionKind) -> Self {
+		// Windows only has a single clipboard.
+		if selection != SelectionKind::Clipboard {
+			self.clipboard = Err(Error::ClipboardNotSupported);
 		}
+		self
 	}
 
 	pub(crate) fn text(self, data: Cow<'_, str>) -> Result<(), Error> {
 		let open_clipboard = self.clipboard?;
 
-		clipboard_win::raw::set_string(&data)
-			.map_err(|_| Error::unknown("Could not place the specified text to the clipboard"))?;
+		clipboard_win::raw::set_string(&data).map_err(|e| {
+			clipboard_error("Could not place the specified text to the clipboard", e)
+		})?;
+		set_locale(self.locale)?;
+
+		add_clipboard_exclusions(
+			open_clipboard,
+			self.exclude_from_monitoring,
+			self.exclude_from_cloud,
+			self.exclude_from_history,
+		)
+	}
+
+	pub(crate) fn text_keeping_others(self, data: Cow<'_, str>) -> Result<(), Error> {
+		let open_clipboard = self.clipboard?;
+
+		use windows_sys::Win32::Globalization::{CP_ACP, CP_OEMCP};
+
+		// `set_string` would empty the clipboard first, so the NUL terminated UTF-16 is written
+		// directly instead.
+		let wide: Vec<u16> = data.encode_utf16().chain(std::iter::once(0)).collect();
+		let bytes: Vec<u8> = wide.iter().copied().flat_map(u16::to_ne_bytes).collect();
+		clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_UNICODETEXT, &bytes)
+			.map_err(|e| {
+				clipboard_error("Could not place the specified text to the clipboard", e)
+			})?;
+		// Windows only synthesizes the narrow text formats when they are missing, so any that
+		// are already on the clipboard would still hold the old text. They are replaced too.
+		for (format, code_page) in [
+			(clipboard_win::formats::CF_TEXT, CP_ACP),
+			(clipboard_win::formats::CF_OEMTEXT, CP_OEMCP),
+		] {
+			let bytes = encode_code_page(&wide, code_page)?;
+			clipboard_win::raw::set_without_clear(format, &bytes).map_err(|e| {
+				clipboard_error("Could not place the specified text to the clipboard", e)
+			})?;
+		}
+		set_locale(self.locale)?;
 
 		add_clipboard_exclusions(
 			open_clipboard,
@@ -619,18 +1239,21 @@ impl<'clipboard> Set<'clipboard> {
 
 	pub(crate) fn html(self, html: Cow<'_, str>, alt: Option<Cow<'_, str>>) -> Result<(), Error> {
 		let open_clipboard = self.clipboard?;
+		// Checked before anything is placed onto the clipboard.
+		let html = wrap_html(&html)?;
 
 		let alt = match alt {
 			Some(s) => s.into(),
 			None => String::new(),
 		};
-		clipboard_win::raw::set_string(&alt)
-			.map_err(|_| Error::unknown("Could not place the specified text to the clipboard"))?;
+		clipboard_win::raw::set_string(&alt).map_err(|e| {
+			clipboard_error("Could not place the specified text to the clipboard", e)
+		})?;
+		set_locale(self.locale)?;
 
-		if let Some(format) = clipboard_win::register_format("HTML Format") {
-			let html = wrap_html(&html);
+		if let Some(format) = clipboard_win::register_format(HTML_FORMAT_NAME) {
 			clipboard_win::raw::set_without_clear(format.get(), html.as_bytes())
-				.map_err(|e| Error::unknown(e.to_string()))?;
+				.map_err(|e| clipboard_error("Could not place the HTML on the clipboard", e))?;
 		}
 
 		add_clipboard_exclusions(
@@ -641,8 +1264,85 @@ impl<'clipboard> Set<'clipboard> {
 		)
 	}
 
+	pub(crate) fn image_png(self, png: Cow<'_, [u8]>) -> Result<(), Error> {
+		let open_clipboard = self.clipboard?;
+
+		if let Err(e) = clipboard_win::raw::empty() {
+			return Err(Error::unknown(format!(
+				"Failed to empty the clipboard. Got error code: {e}"
+			)));
+		};
+
+		let format = clipboard_win::register_format(PNG_FORMAT_NAME)
+			.ok_or_else(|| Error::unknown("Cannot register PNG clipboard format."))?;
+		clipboard_win::raw::set_without_clear(format.get(), &png)
+			.map_err(|e| clipboard_error("Could not place the PNG on the clipboard", e))?;
+
+		add_clipboard_exclusions(
+			open_clipboard,
+			self.exclude_from_monitoring,
+			self.exclude_from_cloud,
+			self.exclude_from_history,
+		)
+	}
+
+	pub(crate) fn svg(self, svg: Cow<'_, str>) -> Result<(), Error> {
+		let open_clipboard = self.clipboard?;
+
+		if let Err(e) = clipboard_win::raw::empty() {
+			return Err(Error::unknown(format!(
+				"Failed to empty the clipboard. Got error code: {e}"
+			)));
+		};
+
+		let format = clipboard_win::register_format(SVG_FORMAT_NAME)
+			.ok_or_else(|| Error::unknown("Cannot register SVG clipboard format."))?;
+		clipboard_win::raw::set_without_clear(format.get(), svg.as_bytes())
+			.map_err(|e| clipboard_error("Could not place the SVG on the clipboard", e))?;
+
+		add_clipboard_exclusions(
+			open_clipboard,
+			self.exclude_from_monitoring,
+			self.exclude_from_cloud,
+			self.exclude_from_history,
+		)
+	}
+
+	pub(crate) fn lazy(self, mime: &str, producer: DataProducer) -> Result<(), Error> {
+		let _ = (mime, producer);
+		Err(Error::ClipboardNotSupported)
+	}
+
 	#[cfg(feature = "image-data")]
 	pub(crate) fn image(self, image: ImageData) -> Result<(), Error> {
+		self.image_with_profile(image, None)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_profile(
+		self,
+		image: ImageData,
+		icc: Option<&[u8]>,
+	) -> Result<(), Error> {
+		self.set_image(image, icc, None)
+	}
+
+	#[cfg(feature = "image-data")]
+	pub(crate) fn image_with_thumbnail(
+		self,
+		image: ImageData,
+		thumbnail: ImageData,
+	) -> Result<(), Error> {
+		self.set_image(image, None, Some(&thumbnail))
+	}
+
+	#[cfg(feature = "image-data")]
+	fn set_image(
+		self,
+		image: ImageData,
+		icc: Option<&[u8]>,
+		thumbnail: Option<&ImageData>,
+	) -> Result<(), Error> {
 		let open_clipboard = self.clipboard?;
 
 		if let Err(e) = clipboard_win::raw::empty() {
@@ -651,14 +1351,71 @@ impl<'clipboard> Set<'clipboard> {
 			)));
 		};
 
-		// XXX: The ordering of these functions is important, as some programs will grab the
-		// first format available. PNGs tend to have better compatibility on Windows, so it is set first.
-		image_data::add_png_file(&image)?;
-		image_data::add_cf_dibv5(open_clipboard, image)?;
+		// XXX: The ordering of the formats is important, as some programs will grab the
+		// first format available. PNGs tend to have better compatibility on Windows, so it is set
+		// first by default.
+		let mut formats = match self.image_formats.as_deref() {
+			Some(formats) if !formats.is_empty() => formats,
+			_ => WinImageFormat::DEFAULT,
+		}
+		.to_vec();
+		if self.legacy_dib && !formats.contains(&WinImageFormat::Dib) {
+			formats.push(WinImageFormat::Dib);
+		}
+
+		// Bitmaps store their rows bottom-up, so all of them can share one flipped copy.
+		let mut flipped = None;
+		for format in &formats {
+			match format {
+				WinImageFormat::Png => image_data::add_png_file(&image, icc)?,
+				WinImageFormat::DibV5 if self.top_down_dib => {
+					image_data::add_cf_dibv5(&open_clipboard, &image, icc, true)?
+				}
+				WinImageFormat::DibV5 => image_data::add_cf_dibv5(
+					&open_clipboard,
+					flipped.get_or_insert_with(|| image_data::flip_v(&image)),
+					icc,
+					false,
+				)?,
+				WinImageFormat::Dib => image_data::add_cf_dib(
+					&open_clipboard,
+					flipped.get_or_insert_with(|| image_data::flip_v(&image)),
+				)?,
+				WinImageFormat::Bitmap => image_data::add_cf_bitmap(
+					&open_clipboard,
+					flipped.get_or_insert_with(|| image_data::flip_v(&image)),
+				)?,
+			}
+		}
+		if let Some(thumbnail) = thumbnail {
+			image_data::add_thumbnail(thumbnail)?;
+		}
 		Ok(())
 	}
 }
 
+/// The name of the registered clipboard format used for PNG files.
+const PNG_FORMAT_NAME: &str = "PNG";
+
+/// The name of the registered clipboard format used for GIF files.
+#[cfg(feature = "image-data")]
+const GIF_FORMAT_NAME: &str = "GIF";
+
+/// The name of the registered clipboard format used for SVG images, as Chromium and Inkscape
+/// name it.
+const SVG_FORMAT_NAME: &str = "image/svg+xml";
+
+/// The name of the registered clipboard format used for the PNG file placed by
+/// [`Set::image_with_thumbnail`](crate::Set::image_with_thumbnail).
+#[cfg(feature = "image-data")]
+const THUMBNAIL_FORMAT_NAME: &str = "image/x-arboard-thumbnail";
+
+/// The name of the registered clipboard format used for HTML, as produced by [`wrap_html`].
+const HTML_FORMAT_NAME: &str = "HTML Format";
+
+/// The name of the registered clipboard format which tells whether files were cut or copied.
+const DROP_EFFECT_FORMAT_NAME: &str = "Preferred DropEffect";
+
 fn add_clipboard_exclusions(
 	_open_clipboard: OpenClipboard<'_>,
 	exclude_from_monitoring: bool,
@@ -680,8 +1437,9 @@ fn add_clipboard_exclusions(
 		{
 			// The documentation states "place any data on the clipboard in this format to prevent...", and using the zero bytes
 			// like the others for consistency works.
-			clipboard_win::raw::set_without_clear(format.get(), CLIPBOARD_EXCLUSION_DATA)
-				.map_err(|_| Error::unknown("Failed to exclude data from clipboard monitoring"))?;
+			clipboard_win::raw::set_without_clear(format.get(), CLIPBOARD_EXCLUSION_DATA).map_err(
+				|e| clipboard_error("Failed to exclude data from clipboard monitoring", e),
+			)?;
 		}
 	}
 
@@ -689,23 +1447,159 @@ fn add_clipboard_exclusions(
 		if let Some(format) = clipboard_win::register_format("CanUploadToCloudClipboard") {
 			// We believe that it would be a logic error if this call failed, since we've validated the format is supported,
 			// we still have full ownership of the clipboard and aren't moving it to another thread, and this is a well-documented operation.
-			// Due to these reasons, `Error::Unknown` is used because we never expect the error path to be taken.
+			// Due to these reasons, no dedicated error is used because we never expect the error path to be taken.
 			clipboard_win::raw::set_without_clear(format.get(), CLIPBOARD_EXCLUSION_DATA)
-				.map_err(|_| Error::unknown("Failed to exclude data from cloud clipboard"))?;
+				.map_err(|e| clipboard_error("Failed to exclude data from cloud clipboard", e))?;
 		}
 	}
 
 	if exclude_from_history {
 		if let Some(format) = clipboard_win::register_format("CanIncludeInClipboardHistory") {
-			// See above for reasoning about using `Error::Unknown`.
+			// See above for reasoning about the error.
 			clipboard_win::raw::set_without_clear(format.get(), CLIPBOARD_EXCLUSION_DATA)
-				.map_err(|_| Error::unknown("Failed to exclude data from clipboard history"))?;
+				.map_err(|e| clipboard_error("Failed to exclude data from clipboard history", e))?;
 		}
 	}
 
 	Ok(())
 }
 
+/// An image format that [`SetExtWindows::image_formats`] can place on the clipboard.
+#[cfg(feature = "image-data")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WinImageFormat {
+	/// A PNG file, under the registered `PNG` clipboard format.
+	Png,
+
+	/// `CF_DIBV5`, a device independent bitmap with a `BITMAPV5HEADER`. This keeps the image's
+	/// transparency.
+	DibV5,
+
+	/// `CF_DIB`, a device independent bitmap with a `BITMAPINFOHEADER`. Many applications
+	/// ignore the alpha channel of this format.
+	Dib,
+
+	/// `CF_BITMAP`, a device dependent bitmap handle (`HBITMAP`). This doesn't keep the image's
+	/// transparency, but is understood by most legacy applications.
+	Bitmap,
+}
+
+#[cfg(feature = "image-data")]
+impl WinImageFormat {
+	const DEFAULT: &'static [WinImageFormat] = &[WinImageFormat::Png, WinImageFormat::DibV5];
+}
+
+/// Whether pasting files should move or copy them, as told by the `Preferred DropEffect`
+/// clipboard format.
+///
+/// Explorer places this format next to the files when they are cut or copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum DropEffect {
+	/// The files were copied, and should be left where they are.
+	Copy,
+
+	/// The files were cut, and should be removed from where they are once pasted.
+	Move,
+}
+
+impl DropEffect {
+	// The `DROPEFFECT_*` flags from `oleidl.h`.
+	const DROPEFFECT_COPY: u32 = 1;
+	const DROPEFFECT_MOVE: u32 = 2;
+
+	/// Maps the `DROPEFFECT_*` flags onto the effect to use, preferring to copy when both are
+	/// allowed, as that can't lose any files.
+	fn from_bits(bits: u32) -> Option<Self> {
+		if bits & Self::DROPEFFECT_COPY != 0 {
+			Some(DropEffect::Copy)
+		} else if bits & Self::DROPEFFECT_MOVE != 0 {
+			Some(DropEffect::Move)
+		} else {
+			None
+		}
+	}
+}
+
+/// Windows-specific extensions to the [`Get`](crate::Get) builder.
+pub trait GetExtWindows: private::Sealed {
+	/// Sets whether images read with [`image`](crate::Get::image) are made opaque when none of
+	/// their pixels has an alpha value. This is enabled by default.
+	///
+	/// Many applications place 32-bit bitmaps on the clipboard whose alpha bytes are unused and
+	/// left at zero, which would otherwise read as a fully transparent image. Bitmaps which
+	/// declare an alpha mask in their header are never changed.
+	#[cfg(feature = "image-data")]
+	fn assume_opaque_when_no_alpha(self, assume_opaque: bool) -> Self;
+
+	/// Completes the "get" operation by reading the HTML on the clipboard, and returning only the
+	/// fragment which was copied.
+	///
+	/// Windows places HTML on the clipboard as a full document behind a header of byte offsets,
+	/// even when only part of a page was copied. Only the markup between the `StartFragment` and
+	/// `EndFragment` offsets is returned.
+	fn html_fragment(self) -> Result<String, Error>;
+
+	/// Completes the "get" operation by reading the address of the page which the HTML on the
+	/// clipboard was copied from.
+	///
+	/// Browsers put it into the `SourceURL` field of the HTML's header. `None` is returned when
+	/// the HTML doesn't name a source, as most other applications don't.
+	fn html_source_url(self) -> Result<Option<String>, Error>;
+
+	/// Completes the "get" operation by reading the locale of the text on the clipboard
+	/// (`CF_LOCALE`), as a Windows locale identifier (LCID).
+	///
+	/// The locale tells which code page legacy applications should use to convert the text into
+	/// their ANSI encoding. When the text was placed without a locale, Windows uses the keyboard
+	/// layout which was active at the time.
+	fn locale(self) -> Result<u32, Error>;
+
+	/// Completes the "get" operation by reading whether pasted files should be moved or copied,
+	/// from the `Preferred DropEffect` format.
+	///
+	/// This tells file managers whether the user cut or copied the files on the clipboard.
+	/// `None` is returned when the format isn't on the clipboard, or allows neither effect.
+	fn drop_effect(self) -> Result<Option<DropEffect>, Error>;
+
+	/// Completes the "get" operation by reading the paths of the files on the clipboard
+	/// (`CF_HDROP`), along with the point at which they were dropped.
+	///
+	/// The point is taken from the `DROPFILES` header as is, in the client coordinates of the
+	/// window the files were dropped onto. It's `(0, 0)` for files which were copied rather than
+	/// dragged.
+	fn file_list_with_point(self) -> Result<(Vec<PathBuf>, (i32, i32)), Error>;
+}
+
+impl GetExtWindows for crate::Get<'_> {
+	#[cfg(feature = "image-data")]
+	fn assume_opaque_when_no_alpha(mut self, assume_opaque: bool) -> Self {
+		self.platform = self.platform.assume_opaque_when_no_alpha(assume_opaque);
+		self
+	}
+
+	fn html_fragment(self) -> Result<String, Error> {
+		self.platform.html_fragment()
+	}
+
+	fn html_source_url(self) -> Result<Option<String>, Error> {
+		self.platform.html_source_url()
+	}
+
+	fn locale(self) -> Result<u32, Error> {
+		self.platform.locale()
+	}
+
+	fn drop_effect(self) -> Result<Option<DropEffect>, Error> {
+		self.platform.drop_effect()
+	}
+
+	fn file_list_with_point(self) -> Result<(Vec<PathBuf>, (i32, i32)), Error> {
+		self.platform.file_list_with_point()
+	}
+}
+
 /// Windows-specific extensions to the [`Set`](crate::Set) builder.
 pub trait SetExtWindows: private::Sealed {
 	/// Exclude the data which will be set on the clipboard from being processed
@@ -725,6 +1619,60 @@ pub trait SetExtWindows: private::Sealed {
 	///
 	/// [clipboard history]: https://support.microsoft.com/en-us/windows/get-help-with-clipboard-30375039-ce71-9fe4-5b30-21b7aab6b13f
 	fn exclude_from_history(self) -> Self;
+
+	/// Sets which representations of the image are placed on the clipboard when completing
+	/// the operation with [`image`](crate::Set::image).
+	///
+	/// Applications usually paste the first format they support, so the order of `formats` is
+	/// the order of priority. By default, or when `formats` is empty, this is
+	/// [`Png`](WinImageFormat::Png) followed by [`DibV5`](WinImageFormat::DibV5).
+	///
+	/// # Examples
+	///
+	/// ```no_run
+	/// # use arboard::{Clipboard, ImageData, SetExtWindows, WinImageFormat};
+	/// # fn main() -> Result<(), arboard::Error> {
+	/// # let image = ImageData { width: 1, height: 1, bytes: [0, 0, 0, 255].as_ref().into() };
+	/// let mut ctx = Clipboard::new()?;
+	/// ctx.set()
+	///     .image_formats(&[WinImageFormat::Dib, WinImageFormat::Bitmap])
+	///     .image(image)?;
+	/// # Ok(())
+	/// # }
+	/// ```
+	#[cfg(feature = "image-data")]
+	fn image_formats(self, formats: &[WinImageFormat]) -> Self;
+
+	/// Writes [`DibV5`](WinImageFormat::DibV5) images with their rows in top-down order, instead
+	/// of flipping them into the usual bottom-up order.
+	///
+	/// This skips copying the whole image, which noticeably speeds up setting large images.
+	/// However, some applications can't paste such bitmaps, most notably MS Word and WordPad.
+	/// Only use this when the image is meant for applications which are known to support them,
+	/// or together with a [`Png`](WinImageFormat::Png), which most applications prefer anyway.
+	#[cfg(feature = "image-data")]
+	fn top_down_dib(self) -> Self;
+
+	/// Also places a [`Dib`](WinImageFormat::Dib) of the same pixels onto the clipboard, after
+	/// the other [`image_formats`](Self::image_formats), when completing the operation with
+	/// [`image`](crate::Set::image).
+	///
+	/// Windows can synthesize `CF_DIB` from `CF_DIBV5`, but the result keeps the bit fields of
+	/// the newer format, which some old applications can't read. This places a plain
+	/// `BITMAPINFOHEADER` bitmap instead.
+	#[cfg(feature = "image-data")]
+	fn legacy_dib(self) -> Self;
+
+	/// Places `lcid`, a Windows locale identifier, onto the clipboard as `CF_LOCALE` along with
+	/// the text.
+	///
+	/// Legacy applications which paste the text in their ANSI encoding (`CF_TEXT`) use the code
+	/// page of this locale to convert it. Without it, Windows uses the keyboard layout which is
+	/// active when the text is set, which may garble text in another language.
+	///
+	/// This applies to [`text`](crate::Set::text) and the alternative text of
+	/// [`html`](crate::Set::html).
+	fn locale(self, lcid: u32) -> Self;
 }
 
 impl SetExtWindows for crate::Set<'_> {
@@ -742,6 +1690,29 @@ impl SetExtWindows for crate::Set<'_> {
 		self.platform.exclude_from_history = true;
 		self
 	}
+
+	#[cfg(feature = "image-data")]
+	fn image_formats(mut self, formats: &[WinImageFormat]) -> Self {
+		self.platform.image_formats = Some(formats.to_vec());
+		self
+	}
+
+	#[cfg(feature = "image-data")]
+	fn top_down_dib(mut self) -> Self {
+		self.platform.top_down_dib = true;
+		self
+	}
+
+	#[cfg(feature = "image-data")]
+	fn legacy_dib(mut self) -> Self {
+		self.platform.legacy_dib = true;
+		self
+	}
+
+	fn locale(mut self, lcid: u32) -> Self {
+		self.platform.locale = Some(lcid);
+		self
+	}
 }
 
 pub(crate) struct Clear<'clipboard> {
@@ -755,11 +1726,130 @@ impl<'clipboard> Clear<'clipboard> {
 
 	pub(crate) fn clear(self) -> Result<(), Error> {
 		let _clipboard_assertion = self.clipboard?;
-		clipboard_win::empty().map_err(|_| Error::unknown("failed to clear clipboard"))
+		clipboard_win::empty().map_err(|e| clipboard_error("failed to clear clipboard", e))
 	}
 }
 
-fn wrap_html(ctn: &str) -> String {
+/// Returns the UTF-16 text in `units` up to its NUL terminator, or all of it if there's none.
+///
+/// The clipboard's memory can be larger than the text, when its size was rounded up, so
+/// anything after the first NUL is ignored rather than only a NUL in the last unit.
+fn until_nul(units: &[u16]) -> &[u16] {
+	let len = units.iter().position(|&unit| unit == 0).unwrap_or(units.len());
+	&units[..len]
+}
+
+/// Places `locale` onto the open clipboard as `CF_LOCALE`, if one was given.
+fn set_locale(locale: Option<u32>) -> Result<(), Error> {
+	let Some(lcid) = locale else {
+		return Ok(());
+	};
+	clipboard_win::raw::set_without_clear(clipboard_win::formats::CF_LOCALE, &lcid.to_ne_bytes())
+		.map_err(|e| clipboard_error("Could not place the locale on the clipboard", e))
+}
+
+/// Converts the NUL terminated UTF-16 in `wide` to the given code page, keeping the terminator.
+///
+/// Characters which the code page can't represent are replaced by its default character.
+fn encode_code_page(wide: &[u16], code_page: u32) -> Result<Vec<u8>, Error> {
+	use windows_sys::Win32::Globalization::WideCharToMultiByte;
+
+	let convert = |output: &mut [u8]| {
+		// SAFETY: Both buffers are valid for the lengths which are passed along with them. An
+		// empty output only queries the required length.
+		unsafe {
+			WideCharToMultiByte(
+				code_page,
+				0,
+				wide.as_ptr(),
+				wide.len() as i32,
+				if output.is_empty() { std::ptr::null_mut() } else { output.as_mut_ptr() },
+				output.len() as i32,
+				std::ptr::null(),
+				std::ptr::null_mut(),
+			)
+		}
+	};
+	let len = convert(&mut []);
+	let mut bytes = vec![0; len.max(0) as usize];
+	if len <= 0 || convert(&mut bytes) != len {
+		return Err(Error::unknown_with_source(
+			"Could not convert the text to the code page",
+			std::io::Error::last_os_error(),
+		));
+	}
+	Ok(bytes)
+}
+
+/// Returns [`Error::TooLarge`] if the clipboard's data in `format` is longer than `max_bytes`,
+/// before it's copied out of the clipboard.
+fn check_size(format: u32, max_bytes: Option<usize>) -> Result<(), Error> {
+	let Some(max) = max_bytes else {
+		return Ok(());
+	};
+	match clipboard_win::raw::size(format) {
+		Some(size) if size.get() > max => Err(Error::TooLarge),
+		_ => Ok(()),
+	}
+}
+
+/// Parses the `DROPFILES` structure of `CF_HDROP` into the paths it lists and its drop point.
+///
+/// The header is followed, at the offset given by `pFiles`, by a list of NUL terminated paths
+/// which ends with an empty one. The paths are UTF-16 if `fWide` is set, and ANSI otherwise,
+/// which is only accepted as far as it's valid UTF-8.
+///
+/// See: https://learn.microsoft.com/en-us/windows/win32/api/shlobj_core/ns-shlobj_core-dropfiles
+fn parse_drop_files(data: &[u8]) -> Result<(Vec<PathBuf>, (i32, i32)), Error> {
+	use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+	let field = |index: usize| -> Result<[u8; 4], Error> {
+		match data.get(index * 4..index * 4 + 4) {
+			Some(&[a, b, c, d]) => Ok([a, b, c, d]),
+			_ => Err(Error::ConversionFailure),
+		}
+	};
+	let files_offset = u32::from_ne_bytes(field(0)?) as usize;
+	let point = (i32::from_ne_bytes(field(1)?), i32::from_ne_bytes(field(2)?));
+	let wide = i32::from_ne_bytes(field(4)?) != 0;
+	let files = data.get(files_offset..).ok_or(Error::ConversionFailure)?;
+
+	let paths = if wide {
+		let units: Vec<u16> =
+			files.chunks_exact(2).map(|unit| u16::from_ne_bytes([unit[0], unit[1]])).collect();
+		units
+			.split(|&unit| unit == 0)
+			.take_while(|path| !path.is_empty())
+			.map(|path| PathBuf::from(OsString::from_wide(path)))
+			.collect()
+	} else {
+		files
+			.split(|&byte| byte == 0)
+			.take_while(|path| !path.is_empty())
+			.map(|path| std::str::from_utf8(path).map(PathBuf::from))
+			.collect::<Result<_, _>>()
+			.map_err(|_| Error::ConversionFailure)?
+	};
+	Ok((paths, point))
+}
+
+/// Wraps `ctn` into the "HTML Format" that Windows applications expect, with a header that
+/// holds the byte offsets of the fragment.
+///
+/// Line endings within the fragment are normalized to CRLF, as some consumers expect. NUL bytes
+/// are rejected with [`Error::ConversionFailure`], because readers treat them as the end of the
+/// data. Any `<!--StartFragment-->` and `<!--EndFragment-->` markers that `ctn` already has,
+/// like HTML which was read from another "HTML Format" producer, are removed, since wrapping them
+/// again confuses readers such as Word about where the fragment starts.
+///
+/// See: https://learn.microsoft.com/en-us/windows/win32/dataxchg/html-clipboard-format
+fn wrap_html(ctn: &str) -> Result<String, Error> {
+	if ctn.contains('\0') {
+		return Err(Error::ConversionFailure);
+	}
+	let ctn = strip_fragment_markers(ctn);
+	let ctn = normalize_line_endings(&ctn);
+
 	let h_version = "Version:0.9";
 	let h_start_html = "\r\nStartHTML:";
 	let h_end_html = "\r\nEndHTML:";
@@ -777,7 +1867,7 @@ fn wrap_html(ctn: &str) -> String {
 	let n_start_frag = h_len + c_start_frag.len();
 	let n_end_frag = n_start_frag + ctn.len();
 	let n_end_html = n_end_frag + c_end_frag.len();
-	format!(
+	Ok(format!(
 		"{}{}{:010}{}{:010}{}{:010}{}{:010}{}{}{}",
 		h_version,
 		h_start_html,
@@ -791,5 +1881,336 @@ fn wrap_html(ctn: &str) -> String {
 		c_start_frag,
 		ctn,
 		c_end_frag,
-	)
+	))
+}
+
+/// Removes every `StartFragment` and `EndFragment` comment from `html`, ignoring case.
+fn strip_fragment_markers(html: &str) -> Cow<'_, str> {
+	const MARKERS: [&[u8]; 2] = [b"<!--startfragment-->", b"<!--endfragment-->"];
+
+	// The markers are ASCII, so the lowercase copy has the same byte offsets as `html`.
+	let lowercase = html.to_ascii_lowercase();
+	let lowercase = lowercase.as_bytes();
+	let mut stripped = String::new();
+	let (mut kept_until, mut i) = (0, 0);
+	while i < lowercase.len() {
+		match MARKERS.iter().find(|marker| lowercase[i..].starts_with(marker)) {
+			Some(marker) => {
+				stripped.push_str(&html[kept_until..i]);
+				i += marker.len();
+				kept_until = i;
+			}
+			None => i += 1,
+		}
+	}
+	if kept_until == 0 {
+		return Cow::Borrowed(html);
+	}
+	stripped.push_str(&html[kept_until..]);
+	// Removing a marker may join the text around it into another one.
+	Cow::Owned(strip_fragment_markers(&stripped).into_owned())
+}
+
+/// Replaces every bare `\n` and `\r` in `text` with `\r\n`.
+fn normalize_line_endings(text: &str) -> Cow<'_, str> {
+	if !text.contains(['\r', '\n']) {
+		return Cow::Borrowed(text);
+	}
+	let mut normalized = String::with_capacity(text.len());
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'\r' => {
+				chars.next_if_eq(&'\n');
+				normalized.push_str("\r\n");
+			}
+			'\n' => normalized.push_str("\r\n"),
+			c => normalized.push(c),
+		}
+	}
+	Cow::Owned(normalized)
+}
+
+/// The "HTML Format" data that's produced by [`wrap_html`] and its counterparts in other
+/// applications, split into the fields of its header and the fragment which was copied.
+///
+/// Use [`parse_cf_html`] to read it from the raw bytes of the format. The fields which are
+/// needed most often are also available through [`GetExtWindows::html_fragment`] and
+/// [`GetExtWindows::html_source_url`].
+///
+/// See: https://learn.microsoft.com/en-us/windows/win32/dataxchg/html-clipboard-format
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CfHtml {
+	/// The version of the format, like `0.9` or `1.0`.
+	pub version: String,
+	/// The byte offset at which the fragment starts.
+	pub start_fragment: usize,
+	/// The byte offset at which the fragment ends.
+	pub end_fragment: usize,
+	/// The address of the page which the HTML was copied from, as set by browsers.
+	pub source_url: Option<String>,
+	/// The markup between the fragment offsets.
+	pub fragment: String,
+}
+
+/// Parses the header of the "HTML Format" data in `cf_html` and locates the fragment by the
+/// `StartFragment` and `EndFragment` byte offsets in it.
+///
+/// Returns [`Error::ConversionFailure`] if the header lacks either offset, the offsets are out
+/// of bounds, or the fragment isn't valid UTF-8.
+pub fn parse_cf_html(cf_html: &[u8]) -> Result<CfHtml, Error> {
+	// The header only consists of ASCII, and ends where the markup starts.
+	let header_end = cf_html.iter().position(|&b| b == b'<').unwrap_or(cf_html.len());
+	let header =
+		std::str::from_utf8(&cf_html[..header_end]).map_err(|_| Error::ConversionFailure)?;
+	let field = |name: &str| -> Option<&str> {
+		header.lines().find_map(|line| Some(line.strip_prefix(name)?.strip_prefix(':')?.trim()))
+	};
+	let offset_of = |name: &str| -> Option<usize> { field(name)?.parse().ok() };
+
+	let start_fragment = offset_of("StartFragment").ok_or(Error::ConversionFailure)?;
+	let end_fragment = offset_of("EndFragment").ok_or(Error::ConversionFailure)?;
+	let fragment =
+		cf_html.get(start_fragment..end_fragment).ok_or(Error::ConversionFailure)?.to_vec();
+	Ok(CfHtml {
+		version: field("Version").unwrap_or_default().to_owned(),
+		start_fragment,
+		end_fragment,
+		source_url: field("SourceURL").filter(|url| !url.is_empty()).map(str::to_owned),
+		fragment: String::from_utf8(fragment).map_err(|_| Error::ConversionFailure)?,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn html_fragment_is_extracted() {
+		let fragment = "<b>hello</b> wörld";
+		assert_eq!(
+			parse_cf_html(wrap_html(fragment).unwrap().as_bytes()).unwrap().fragment,
+			fragment
+		);
+
+		assert!(matches!(parse_cf_html(b"<p>no header</p>"), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	fn cf_html_header_is_parsed() {
+		let header = |start: usize, end: usize| {
+			format!(
+				"Version:1.0\r\nStartFragment:{start:010}\r\nEndFragment:{end:010}\r\n\
+				 SourceURL:https://example.com/a?b=c\r\n"
+			)
+		};
+		let (prefix, fragment) = ("<html><body><!--StartFragment-->", "<i>ünïcode</i>");
+		let start = header(0, 0).len() + prefix.len();
+		let end = start + fragment.len();
+		let blob =
+			format!("{}{prefix}{fragment}<!--EndFragment--></body></html>", header(start, end));
+
+		let html = parse_cf_html(blob.as_bytes()).unwrap();
+		assert_eq!(html.version, "1.0");
+		assert_eq!((html.start_fragment, html.end_fragment), (start, end));
+		assert_eq!(html.source_url.as_deref(), Some("https://example.com/a?b=c"));
+		assert_eq!(html.fragment, fragment);
+
+		// Applications other than browsers leave the URL out.
+		let html = parse_cf_html(wrap_html("<b>a</b>").unwrap().as_bytes()).unwrap();
+		assert_eq!((html.version.as_str(), html.source_url), ("0.9", None));
+	}
+
+	#[test]
+	fn html_is_wrapped() {
+		let offset = |html: &str, name: &str| -> usize {
+			let start = html.find(name).unwrap() + name.len() + 1;
+			html[start..start + 10].parse().unwrap()
+		};
+
+		// Multi-byte characters are counted in bytes, not characters.
+		let fragment = "<p>日本語\nüber\r\nline\r</p>";
+		let html = wrap_html(fragment).unwrap();
+		let (start, end) = (offset(&html, "StartFragment"), offset(&html, "EndFragment"));
+		assert_eq!(&html[start..end], "<p>日本語\r\nüber\r\nline\r\n</p>");
+		assert_eq!(&html[offset(&html, "StartHTML")..][..6], "<html>");
+		assert_eq!(offset(&html, "EndHTML"), html.len());
+
+		assert!(matches!(wrap_html("<p>a\0b</p>"), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	fn pre_marked_html_is_stripped() {
+		for (fragment, stripped) in [
+			("<!--StartFragment--><b>a</b><!--EndFragment-->", "<b>a</b>"),
+			("<b>a</b><!--EndFragment-->", "<b>a</b>"),
+			("<html><body><!--startfragment-->ä</body></html>", "<html><body>ä</body></html>"),
+			("<!--Start<!--EndFragment-->Fragment-->ü", "ü"),
+		] {
+			assert_eq!(
+				parse_cf_html(wrap_html(fragment).unwrap().as_bytes()).unwrap().fragment,
+				stripped,
+				"{fragment}"
+			);
+		}
+
+		// Other comments are kept as they are.
+		let fragment = "<!-- Start --><i>ö</i><!--Fragment-->";
+		assert_eq!(
+			parse_cf_html(wrap_html(fragment).unwrap().as_bytes()).unwrap().fragment,
+			fragment
+		);
+	}
+
+	#[test]
+	fn drop_files_are_parsed() {
+		let mut data = Vec::new();
+		for field in [20, 12, -7, 0, 1] {
+			data.extend_from_slice(&i32::to_ne_bytes(field));
+		}
+		for unit in "C:\\a.txt\0D:\\日本\0\0".encode_utf16() {
+			data.extend_from_slice(&unit.to_ne_bytes());
+		}
+		let (paths, point) = parse_drop_files(&data).unwrap();
+		assert_eq!(paths, [PathBuf::from("C:\\a.txt"), PathBuf::from("D:\\日本")]);
+		assert_eq!(point, (12, -7));
+
+		assert!(matches!(parse_drop_files(&data[..8]), Err(Error::ConversionFailure)));
+	}
+
+	#[test]
+	fn text_ends_at_nul() {
+		let decode = |units: &[u16]| String::from_utf16(until_nul(units));
+
+		// Ends in a surrogate pair, whose low surrogate must not be taken for the terminator.
+		let text: Vec<u16> = "hi\u{1F44D}".encode_utf16().collect();
+		assert_eq!(decode(&text).unwrap(), "hi\u{1F44D}");
+		let terminated: Vec<u16> = text.iter().copied().chain([0, 0, 0]).collect();
+		assert_eq!(decode(&terminated).unwrap(), "hi\u{1F44D}");
+
+		// The last character is kept when there's no terminator, even if its high byte is zero.
+		let ascii: Vec<u16> = "hi".encode_utf16().collect();
+		assert_eq!(decode(&ascii).unwrap(), "hi");
+		assert_eq!(decode(&[0]).unwrap(), "");
+		assert_eq!(decode(&[]).unwrap(), "");
+
+		// A lone surrogate can't be converted.
+		assert!(decode(&[0x68, 0xD83D, 0]).is_err());
+	}
+
+	#[test]
+	fn nested_batch_keeps_the_clipboard_open() {
+		let mut clipboard = Clipboard::new(ClipboardConfig::default()).unwrap();
+		let outer = clipboard.begin_batch().unwrap();
+		assert!(outer.is_some());
+
+		let inner = clipboard.begin_batch().unwrap();
+		assert!(inner.is_none());
+		clipboard.end_batch(inner);
+		assert!(clipboard.batched);
+
+		clipboard.end_batch(outer);
+		assert!(!clipboard.batched);
+	}
+
+	/// Places nothing but a device dependent bitmap, and reads it back without going through the
+	/// `CF_DIBV5` which Windows synthesizes from it.
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn cf_bitmap_is_read() {
+		let mut clipboard = Clipboard::new(ClipboardConfig::default()).unwrap();
+		// The alpha is lost in a device dependent bitmap, so the pixels are opaque.
+		let pixels = [255, 0, 0, 255, 0, 0, 255, 255];
+		let image = ImageData { width: 2, height: 1, bytes: pixels.as_ref().into() };
+		{
+			let open = clipboard.open().unwrap();
+			clipboard_win::empty().unwrap();
+			image_data::add_cf_bitmap(&open, &image).unwrap();
+		}
+
+		let open = clipboard.open().unwrap();
+		let read = image_data::read_cf_bitmap(&open, None, true).unwrap();
+		assert_eq!((read.width, read.height), (2, 1));
+		assert_eq!(&*read.bytes, pixels);
+
+		assert!(matches!(image_data::read_cf_bitmap(&open, Some(4), true), Err(Error::TooLarge)));
+	}
+
+	#[test]
+	fn drop_effect_prefers_copy() {
+		// Explorer places `DROPEFFECT_COPY | DROPEFFECT_LINK` when copying.
+		assert_eq!(DropEffect::from_bits(5), Some(DropEffect::Copy));
+		assert_eq!(DropEffect::from_bits(3), Some(DropEffect::Copy));
+		assert_eq!(DropEffect::from_bits(2), Some(DropEffect::Move));
+		assert_eq!(DropEffect::from_bits(4), None);
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn dibv5_alpha_is_filled_unless_declared() {
+		use std::mem::size_of;
+		use windows_sys::Win32::Graphics::Gdi::{BITMAPV5HEADER, BI_BITFIELDS, BI_RGB};
+
+		// A 2x1 bitmap whose pixels have no alpha, optionally declaring an alpha channel.
+		let dibv5 = |bitfields: bool| {
+			// SAFETY: `BITMAPV5HEADER` is plain old data.
+			let mut header: BITMAPV5HEADER = unsafe { std::mem::zeroed() };
+			header.bV5Size = size_of::<BITMAPV5HEADER>() as u32;
+			header.bV5Width = 2;
+			header.bV5Height = 1;
+			header.bV5Planes = 1;
+			header.bV5BitCount = 32;
+			header.bV5Compression = if bitfields { BI_BITFIELDS } else { BI_RGB };
+			if bitfields {
+				header.bV5RedMask = 0x00ff_0000;
+				header.bV5GreenMask = 0x0000_ff00;
+				header.bV5BlueMask = 0x0000_00ff;
+				header.bV5AlphaMask = 0xff00_0000;
+			}
+			// SAFETY: The header is plain old data, which is valid to read as bytes.
+			let mut bytes = unsafe {
+				std::slice::from_raw_parts(
+					&header as *const BITMAPV5HEADER as *const u8,
+					size_of::<BITMAPV5HEADER>(),
+				)
+			}
+			.to_vec();
+			// The pixels are stored as BGRA.
+			bytes.extend([255, 128, 0, 0, 255, 128, 0, 0]);
+			bytes
+		};
+		let read = |dibv5: &[u8], assume_opaque: bool| {
+			image_data::read_cf_dibv5(dibv5, assume_opaque).unwrap().image.bytes.into_owned()
+		};
+
+		let opaque = [0, 128, 255, 255, 0, 128, 255, 255];
+		let transparent = [0, 128, 255, 0, 0, 128, 255, 0];
+		assert_eq!(read(&dibv5(false), true), opaque);
+		assert_eq!(read(&dibv5(false), false), transparent);
+		// The alpha which the header declares is kept, even when it's zero everywhere.
+		assert_eq!(read(&dibv5(true), true), transparent);
+		assert_eq!(read(&dibv5(true), false), transparent);
+	}
+
+	#[test]
+	#[cfg(feature = "image-data")]
+	fn missing_alpha_is_filled() {
+		use image_data::fill_missing_alpha;
+
+		// Opaque pixels whose alpha byte was left unused.
+		let mut pixels = vec![10, 20, 30, 0, 40, 50, 60, 0];
+		fill_missing_alpha(&mut pixels, 0);
+		assert_eq!(pixels, [10, 20, 30, 255, 40, 50, 60, 255]);
+
+		// Genuine alpha is kept, even when it's zero for some of the pixels.
+		let mut pixels = vec![10, 20, 30, 0, 40, 50, 60, 128];
+		fill_missing_alpha(&mut pixels, 0);
+		assert_eq!(pixels, [10, 20, 30, 0, 40, 50, 60, 128]);
+
+		// A fully transparent image is kept when the header declares an alpha channel.
+		let mut pixels = vec![10, 20, 30, 0, 40, 50, 60, 0];
+		fill_missing_alpha(&mut pixels, 0xff00_0000);
+		assert_eq!(pixels, [10, 20, 30, 0, 40, 50, 60, 0]);
+	}
 }